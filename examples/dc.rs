@@ -9,9 +9,18 @@ async fn main() {
         dc_host: Some(String::from("192.168.39.176:9015")),
         ncom: Some(String::from("COM15")),
         baudrate: Some(9600),
+        policy: None,
+        serial: None,
+        tls: None,
         address: None,
         port: None,
         timeout: Some(10000),
+        dc_client: None,
+        sber: None,
+        external: None,
+        reconnect: None,
+        allowed_peers: None,
+        journal: None,
     });
 
     let con = term.connect().await;
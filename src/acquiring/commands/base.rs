@@ -1,9 +1,12 @@
 use crate::acquiring::connection::BaseConnection;
 use crate::acquiring::protocol::inpas::{InpasField, send_inpas_request};
+use crate::acquiring::protocol::inpas_sa::{decode_sa_fields, encode_sa_fields, fields_to_raw};
+use crate::acquiring::protocol::external::{ExternalProcessProtocol, ExternalRequest};
+use crate::acquiring::protocol::sber::{run_sb_pilot, SberRequest};
 use crate::acquiring::protocol::{TlvItem, TtkBuffer};
 use crate::acquiring::response::build_terminal_response_from_raw;
 use crate::acquiring::types::{
-    ConnectionConfig, MessageType, ProtocolType, TerminalResponse, get_tag_definition,
+    ConnectionConfig, ConnectionPolicy, InpasFieldId, MessageType, ProtocolType, TerminalResponse,
 };
 use rand::Rng;
 use std::sync::Arc;
@@ -20,6 +23,15 @@ pub trait BaseCommand: Send + Sync {
     ) -> impl Future<Output = Result<TerminalResponse, Box<dyn std::error::Error>>>;
 }
 
+/// A command's request in each protocol `execute_command` dispatches to.
+pub trait CommandRequest {
+    /// TTK items, without the serial number.
+    fn prepare(&self, context: &CommandContext) -> Result<Vec<TlvItem>, String>;
+    fn prepare_inpas(&self, context: &CommandContext) -> Vec<InpasField>;
+    fn prepare_sber(&self, context: &CommandContext) -> SberRequest;
+    fn prepare_external(&self, context: &CommandContext) -> ExternalRequest;
+}
+
 pub struct CommandContext {
     pub connection: Arc<Mutex<Box<dyn BaseConnection>>>,
     pub request_id: u32,
@@ -50,22 +62,26 @@ impl CommandContext {
     }
 
     pub fn build_inpas_fields(&self, mut fields: Vec<InpasField>) -> Vec<InpasField> {
-        let has_timestamp = fields.iter().any(|f| f.id == "21");
-        let has_serial = fields.iter().any(|f| f.id == "27");
+        let has_timestamp = fields
+            .iter()
+            .any(|f| f.field_id() == Some(InpasFieldId::Timestamp));
+        let has_serial = fields
+            .iter()
+            .any(|f| f.field_id() == Some(InpasFieldId::TerminalId));
         let config = self.config();
 
         if !has_timestamp {
-            fields.push(InpasField {
-                id: "21".to_string(),
-                value: self.get_current_timestamp(),
-            });
+            fields.push(InpasField::new(
+                InpasFieldId::Timestamp,
+                self.get_current_timestamp(),
+            ));
         }
 
         if !has_serial {
-            fields.push(InpasField {
-                id: "27".to_string(),
-                value: config.serial_number.clone(),
-            });
+            fields.push(InpasField::new(
+                InpasFieldId::TerminalId,
+                config.serial_number.clone(),
+            ));
         }
 
         fields
@@ -76,52 +92,78 @@ impl CommandContext {
         let now = Local::now();
         now.format("%Y%m%d%H%M%S").to_string()
     }
+}
 
-    pub fn int_to_bcd(&self, value: u64, length: usize) -> Vec<u8> {
-        let str_value = format!("{:0width$}", value, width = length);
-        str_value
-            .chars()
-            .map(|c| c.to_digit(10).unwrap() as u8)
-            .collect()
+/// Runs a command according to the connection policy: lazy and per-command
+/// connections are opened on demand, per-command ones closed afterwards.
+pub async fn execute_command(
+    context: CommandContext,
+    command: &impl CommandRequest,
+) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+    let policy = context.config().policy.unwrap_or_default();
+    if policy != ConnectionPolicy::Persistent {
+        let mut conn = context.connection.lock().await;
+        if !conn.is_connected() {
+            conn.connect().await?;
+        }
     }
 
-    pub fn string_to_bytes(&self, str: &str) -> Vec<u8> {
-        str.as_bytes().to_vec()
+    let dispatch = dispatch_command(&context, command);
+    if policy != ConnectionPolicy::PerCommand {
+        return dispatch.await;
     }
 
-    pub fn int_to_string(&self, number: u64, len: usize) -> String {
-        format!("{:0width$}", number, width = len)
+    let result = dispatch.await.map_err(|e| e.to_string());
+    {
+        let mut conn = context.connection.lock().await;
+        let _ = conn.disconnect().await;
     }
+    Ok(result?)
 }
 
-pub async fn execute_command(
-    context: CommandContext,
-    prepare_fn: impl Fn(&CommandContext) -> Vec<TlvItem>,
-    prepare_inpas_fn: impl Fn(&CommandContext) -> Vec<InpasField>,
+async fn dispatch_command(
+    context: &CommandContext,
+    command: &impl CommandRequest,
 ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-    if context.should_use_inpas() {
-        if context.config().dc_host.is_none() {
-            return Err("dcHost property is required for inpas protocol".into());
+    let config = context.config();
+    match config.protocol {
+        ProtocolType::Inpas => {
+            let fields = command.prepare_inpas(context);
+            let fields = context.build_inpas_fields(fields);
+            if config.dc_host.is_some() {
+                return send_inpas_request(&config, &fields).await;
+            }
+            return execute_sa_command(context, &fields).await;
         }
-
-        let fields = prepare_inpas_fn(&context);
-        let fields = context.build_inpas_fields(fields);
-        let config = context.config();
-        return send_inpas_request(&config, &fields).await;
+        ProtocolType::Sber => {
+            let request = command.prepare_sber(context);
+            return run_sb_pilot(&config, &request).await;
+        }
+        ProtocolType::External => {
+            let external = config
+                .external
+                .clone()
+                .ok_or("external property is required for external protocol")?;
+            let request = command.prepare_external(context);
+            return ExternalProcessProtocol::new(external)?
+                .execute(&request, context.ern, &config.serial_number)
+                .await;
+        }
+        ProtocolType::Ttk => {}
     }
 
-    let mut items = prepare_fn(&context);
+    let items = command.prepare(context)?;
+    execute_ttk_command(context, items).await
+}
+
+/// TTK exchange: the serial number is prepended to `items`, intermediate
+/// server messages are skipped until one carries a response code.
+pub async fn execute_ttk_command(
+    context: &CommandContext,
+    mut items: Vec<TlvItem>,
+) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
     let config = context.config();
-    let ecr_tag = get_tag_definition(0x02).ok_or("ECR_NUMBER tag not found")?;
-    items.insert(
-        0,
-        TlvItem {
-            tag: ecr_tag.tag,
-            length: 8,
-            value: context.string_to_bytes(&config.serial_number),
-            definition: Some(ecr_tag),
-        },
-    );
+    items.insert(0, TlvItem::ascii(0x02, &config.serial_number)?);
 
     let message = TtkBuffer::create_message(MessageType::ClientRequest, &items);
     {
@@ -129,11 +171,22 @@ pub async fn execute_command(
         conn.write(&message).await?;
     }
 
-    let timeout_ms = context.config().timeout.unwrap_or(30000);
+    let timeout_ms = config.timeout.unwrap_or(30000);
+    let mut pending = Vec::new();
     loop {
-        let response_data = {
-            let mut conn = context.connection.lock().await;
-            conn.read(Some(timeout_ms)).await?
+        let response_data = match TtkBuffer::take_frame(&mut pending) {
+            Some(frame) => frame,
+            None => {
+                let chunk = {
+                    let mut conn = context.connection.lock().await;
+                    conn.read(Some(timeout_ms)).await?
+                };
+                if chunk.is_empty() {
+                    return Err("Connection closed by terminal".into());
+                }
+                pending.extend_from_slice(&chunk);
+                continue;
+            }
         };
         let (response_type, response_items) = TtkBuffer::parse_message(&response_data)?;
 
@@ -152,3 +205,37 @@ pub async fn execute_command(
         }
     }
 }
+
+/// Native Inpas SA exchange. The terminal may send intermediate messages
+/// before the one carrying the response code or status.
+async fn execute_sa_command(
+    context: &CommandContext,
+    fields: &[InpasField],
+) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+    let payload = encode_sa_fields(fields)?;
+    {
+        let mut conn = context.connection.lock().await;
+        conn.write(&payload).await?;
+    }
+
+    let timeout_ms = context.config().timeout.unwrap_or(30000);
+    loop {
+        let payload = {
+            let mut conn = context.connection.lock().await;
+            conn.read(Some(timeout_ms)).await?
+        };
+        let response_fields = decode_sa_fields(&payload)?;
+        let is_final = response_fields.iter().any(|field| {
+            matches!(
+                field.field_id(),
+                Some(InpasFieldId::ResponseCode | InpasFieldId::Status)
+            )
+        });
+        if is_final {
+            return Ok(build_terminal_response_from_raw(
+                ProtocolType::Inpas,
+                fields_to_raw(response_fields),
+            ));
+        }
+    }
+}
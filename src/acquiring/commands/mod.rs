@@ -1,10 +1,12 @@
 pub mod base;
 pub mod payment;
+pub mod ping;
 pub mod refund;
 pub mod totals;
 
-pub use base::BaseCommand;
-pub use payment::PaymentCommand;
+pub use base::{BaseCommand, CommandRequest};
+pub use payment::{PaymentCommand, PaymentResponse};
+pub use ping::PingCommand;
 pub use refund::RefundCommand;
 pub use totals::TotalsCommand;
 
@@ -1,7 +1,50 @@
-use crate::acquiring::commands::base::{execute_command, CommandContext};
+use crate::acquiring::commands::base::{execute_command, CommandContext, CommandRequest};
+use crate::acquiring::protocol::external::ExternalRequest;
 use crate::acquiring::protocol::inpas::InpasField;
-use crate::acquiring::protocol::TlvItem;
-use crate::acquiring::types::{get_tag_definition, TerminalResponse};
+use crate::acquiring::protocol::sber::{SberOperation, SberRequest};
+use crate::acquiring::protocol::{TlvItem, TtkMessage};
+use crate::acquiring::types::{InpasFieldId, InpasOperation, TerminalResponse};
+use crate::ttk_message;
+
+ttk_message! {
+    pub struct PaymentRequest {
+        #[ttk(tag = 0x01, ascii)]
+        pub message_id: String,
+        #[ttk(tag = 0x03, bcd, len = 10)]
+        pub ern: u64,
+        #[ttk(tag = 0x04, bcd, len = 12)]
+        pub amount: u64,
+        #[ttk(tag = 0x1b, bcd, len = 3)]
+        pub currency: Option<u64>,
+    }
+}
+
+ttk_message! {
+    /// Final server response to PUR; intermediate prompts lack 0x9B.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct PaymentResponse {
+        #[ttk(tag = 0x81, ascii)]
+        pub message_id: String,
+        #[ttk(tag = 0x83, bcd)]
+        pub ern: Option<u64>,
+        #[ttk(tag = 0x84, bcd)]
+        pub amount: Option<u64>,
+        #[ttk(tag = 0x9b, ascii)]
+        pub response_code: Option<String>,
+        #[ttk(tag = 0xa1, ascii)]
+        pub approve: Option<String>,
+        #[ttk(tag = 0xa0, ascii)]
+        pub host_response: Option<String>,
+        #[ttk(tag = 0x98, bcd)]
+        pub rrn: Option<String>,
+        #[ttk(tag = 0x8c, ascii)]
+        pub authorization_id: Option<String>,
+        #[ttk(tag = 0x89, bcd)]
+        pub pan: Option<String>,
+        #[ttk(tag = 0x9d, ascii)]
+        pub terminal_id: Option<String>,
+    }
+}
 
 pub struct PaymentCommand {
     amount: u64,
@@ -16,74 +59,117 @@ impl PaymentCommand {
         }
     }
 
-    fn prepare(&self, context: &CommandContext) -> Vec<TlvItem> {
-        let message_id_tag = get_tag_definition(0x01).expect("MESSAGE_ID tag not found");
-        let ern_tag = get_tag_definition(0x03).expect("ERN tag not found");
-        let transaction_amount_tag =
-            get_tag_definition(0x04).expect("TRANSACTION_AMOUNT tag not found");
-
-        let mut items = vec![
-            TlvItem {
-                tag: message_id_tag.tag,
-                length: 3,
-                value: context.string_to_bytes("PUR"),
-                definition: Some(message_id_tag),
-            },
-            TlvItem {
-                tag: ern_tag.tag,
-                length: 10,
-                value: context.int_to_bcd(context.ern, 10),
-                definition: Some(ern_tag),
-            },
-            TlvItem {
-                tag: transaction_amount_tag.tag,
-                length: 12,
-                value: context.string_to_bytes(&context.int_to_string(self.amount, 12)),
-                definition: Some(transaction_amount_tag),
-            },
-        ];
+    pub async fn execute(
+        self,
+        context: CommandContext,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        execute_command(context, &self).await
+    }
+}
 
-        if let Some(currency_tag) = get_tag_definition(0x1b) {
-            if let Ok(currency_num) = self.currency.parse::<u32>() {
-                items.push(TlvItem {
-                    tag: currency_tag.tag,
-                    length: 3,
-                    value: context.int_to_bcd(currency_num as u64, 3),
-                    definition: Some(currency_tag),
-                });
-            }
+impl CommandRequest for PaymentCommand {
+    fn prepare(&self, context: &CommandContext) -> Result<Vec<TlvItem>, String> {
+        PaymentRequest {
+            message_id: "PUR".to_string(),
+            ern: context.ern,
+            amount: self.amount,
+            currency: self.currency.parse().ok(),
         }
-
-        items
+        .to_items()
     }
 
     fn prepare_inpas(&self, _context: &CommandContext) -> Vec<InpasField> {
         vec![
-            InpasField {
-                id: "00".to_string(),
-                value: self.amount.to_string(),
-            },
-            InpasField {
-                id: "04".to_string(),
-                value: self.currency.clone(),
-            },
-            InpasField {
-                id: "25".to_string(),
-                value: "1".to_string(),
-            },
+            InpasField::new(InpasFieldId::Amount, self.amount.to_string()),
+            InpasField::new(InpasFieldId::Currency, self.currency.clone()),
+            InpasField::operation(InpasOperation::Sale),
         ]
     }
 
-    pub async fn execute(
-        self,
-        context: CommandContext,
-    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-        execute_command(
-            context,
-            |ctx| self.prepare(ctx),
-            |ctx| self.prepare_inpas(ctx),
-        )
-        .await
+    fn prepare_sber(&self, _context: &CommandContext) -> SberRequest {
+        SberRequest::new(SberOperation::Sale, Some(self.amount))
+    }
+
+    fn prepare_external(&self, _context: &CommandContext) -> ExternalRequest {
+        ExternalRequest::new("sale", Some(self.amount), Some(self.currency.clone()))
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::protocol::TtkBuffer;
+    use crate::acquiring::types::MessageType;
+
+    #[test]
+    fn payment_response_is_decoded_from_a_frame() {
+        let items = vec![
+            TlvItem::ascii(0x81, "PUR").unwrap(),
+            TlvItem::ascii(0x82, "1").unwrap(),
+            TlvItem::bcd(0x83, 42, 10).unwrap(),
+            TlvItem::bcd(0x84, 1000, 12).unwrap(),
+            TlvItem::ascii(0x9b, "00").unwrap(),
+            TlvItem::ascii(0xa1, "Y").unwrap(),
+            TlvItem::ascii(0xa0, "APPROVED").unwrap(),
+            TlvItem::bcd(0x98, 251019123456, 12).unwrap(),
+            TlvItem::ascii(0x8c, "123456").unwrap(),
+            TlvItem::bcd(0x89, 4000_0000_0000_0002, 16).unwrap(),
+            TlvItem::ascii(0x9d, "00000001").unwrap(),
+        ];
+        let frame = TtkBuffer::create_message(MessageType::ServerResponse, &items);
+
+        let response = PaymentResponse::decode(&frame).unwrap();
+        assert_eq!(
+            response,
+            PaymentResponse {
+                message_id: "PUR".to_string(),
+                ern: Some(42),
+                amount: Some(1000),
+                response_code: Some("00".to_string()),
+                approve: Some("Y".to_string()),
+                host_response: Some("APPROVED".to_string()),
+                rrn: Some("251019123456".to_string()),
+                authorization_id: Some("123456".to_string()),
+                pan: Some("4000000000000002".to_string()),
+                terminal_id: Some("00000001".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn prompts_decode_without_optional_fields() {
+        let items = vec![
+            TlvItem::ascii(0x81, "PUR").unwrap(),
+            TlvItem::ascii(0xa0, "INSERT CARD").unwrap(),
+        ];
+        let frame = TtkBuffer::create_message(MessageType::ServerResponse, &items);
+
+        let response = PaymentResponse::decode(&frame).unwrap();
+        assert_eq!(response.host_response.as_deref(), Some("INSERT CARD"));
+        assert_eq!(response.response_code, None);
+        assert_eq!(response.pan, None);
+
+        let items = vec![TlvItem::ascii(0xa0, "INSERT CARD").unwrap()];
+        let frame = TtkBuffer::create_message(MessageType::ServerResponse, &items);
+        assert_eq!(
+            PaymentResponse::decode(&frame).unwrap_err(),
+            "Message ID (0x81): missing value"
+        );
+    }
+
+    #[test]
+    fn request_round_trips_through_the_macro() {
+        let request = PaymentRequest {
+            message_id: "PUR".to_string(),
+            ern: 7,
+            amount: 1000,
+            currency: Some(643),
+        };
+        let frame = request.encode(MessageType::ClientRequest).unwrap();
+        let decoded = PaymentRequest::decode(&frame).unwrap();
+        assert_eq!(decoded.ern, 7);
+        assert_eq!(decoded.amount, 1000);
+        assert_eq!(decoded.currency, Some(643));
+    }
+}
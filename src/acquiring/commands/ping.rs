@@ -0,0 +1,41 @@
+use crate::acquiring::commands::base::{execute_ttk_command, CommandContext};
+use crate::acquiring::commands::totals::ServiceRequest;
+use crate::acquiring::protocol::{TlvItem, TtkMessage};
+use crate::acquiring::types::{ProtocolType, TerminalResponse};
+
+const TEST_SERVER_SUBFUNCTION: &[u8] = b"1";
+
+/// SRV test-server request, used as a liveness probe. TTK only.
+pub struct PingCommand;
+
+impl PingCommand {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn prepare(&self, context: &CommandContext) -> Result<Vec<TlvItem>, String> {
+        ServiceRequest {
+            message_id: "SRV".to_string(),
+            ern: context.ern,
+            subfunction: TEST_SERVER_SUBFUNCTION.to_vec(),
+        }
+        .to_items()
+    }
+
+    pub async fn execute(
+        self,
+        context: CommandContext,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        if !matches!(context.config().protocol, ProtocolType::Ttk) {
+            return Err("Ping is only supported by the TTK protocol".into());
+        }
+        let items = self.prepare(&context)?;
+        execute_ttk_command(&context, items).await
+    }
+}
+
+impl Default for PingCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
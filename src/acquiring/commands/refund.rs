@@ -1,7 +1,21 @@
-use crate::acquiring::commands::base::{execute_command, CommandContext};
+use crate::acquiring::commands::base::{execute_command, CommandContext, CommandRequest};
+use crate::acquiring::protocol::external::ExternalRequest;
 use crate::acquiring::protocol::inpas::InpasField;
-use crate::acquiring::protocol::TlvItem;
-use crate::acquiring::types::{get_tag_definition, TerminalResponse};
+use crate::acquiring::protocol::sber::{SberOperation, SberRequest};
+use crate::acquiring::protocol::{TlvItem, TtkMessage};
+use crate::acquiring::types::{InpasFieldId, InpasOperation, TerminalResponse};
+use crate::ttk_message;
+
+ttk_message! {
+    pub struct RefundRequest {
+        #[ttk(tag = 0x01, ascii)]
+        pub message_id: String,
+        #[ttk(tag = 0x03, bcd, len = 10)]
+        pub ern: u64,
+        #[ttk(tag = 0x04, bcd, len = 12)]
+        pub amount: u64,
+    }
+}
 
 pub struct RefundCommand {
     amount: u64,
@@ -16,61 +30,38 @@ impl RefundCommand {
         }
     }
 
-    fn prepare(&self, context: &CommandContext) -> Vec<TlvItem> {
-        let message_id_tag = get_tag_definition(0x01).expect("MESSAGE_ID tag not found");
-        let ern_tag = get_tag_definition(0x03).expect("ERN tag not found");
-        let transaction_amount_tag =
-            get_tag_definition(0x04).expect("TRANSACTION_AMOUNT tag not found");
+    pub async fn execute(
+        self,
+        context: CommandContext,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        execute_command(context, &self).await
+    }
+}
 
-        vec![
-            TlvItem {
-                tag: message_id_tag.tag,
-                length: 3,
-                value: context.string_to_bytes("REF"),
-                definition: Some(message_id_tag),
-            },
-            TlvItem {
-                tag: ern_tag.tag,
-                length: 10,
-                value: context.int_to_bcd(context.ern, 10),
-                definition: Some(ern_tag),
-            },
-            TlvItem {
-                tag: transaction_amount_tag.tag,
-                length: 12,
-                value: context.string_to_bytes(&context.int_to_string(self.amount, 12)),
-                definition: Some(transaction_amount_tag),
-            },
-        ]
+impl CommandRequest for RefundCommand {
+    fn prepare(&self, context: &CommandContext) -> Result<Vec<TlvItem>, String> {
+        RefundRequest {
+            message_id: "REF".to_string(),
+            ern: context.ern,
+            amount: self.amount,
+        }
+        .to_items()
     }
 
     fn prepare_inpas(&self, _context: &CommandContext) -> Vec<InpasField> {
         vec![
-            InpasField {
-                id: "00".to_string(),
-                value: self.amount.to_string(),
-            },
-            InpasField {
-                id: "04".to_string(),
-                value: self.currency.clone(),
-            },
-            InpasField {
-                id: "25".to_string(),
-                value: "29".to_string(),
-            },
+            InpasField::new(InpasFieldId::Amount, self.amount.to_string()),
+            InpasField::new(InpasFieldId::Currency, self.currency.clone()),
+            InpasField::operation(InpasOperation::Refund),
         ]
     }
 
-    pub async fn execute(
-        self,
-        context: CommandContext,
-    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-        execute_command(
-            context,
-            |ctx| self.prepare(ctx),
-            |ctx| self.prepare_inpas(ctx),
-        )
-        .await
+    fn prepare_sber(&self, _context: &CommandContext) -> SberRequest {
+        SberRequest::new(SberOperation::Refund, Some(self.amount))
+    }
+
+    fn prepare_external(&self, _context: &CommandContext) -> ExternalRequest {
+        ExternalRequest::new("refund", Some(self.amount), Some(self.currency.clone()))
     }
 }
 
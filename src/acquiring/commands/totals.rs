@@ -1,7 +1,21 @@
-use crate::acquiring::commands::base::{execute_command, CommandContext};
+use crate::acquiring::commands::base::{execute_command, CommandContext, CommandRequest};
+use crate::acquiring::protocol::external::ExternalRequest;
 use crate::acquiring::protocol::inpas::InpasField;
-use crate::acquiring::protocol::TlvItem;
-use crate::acquiring::types::{get_tag_definition, TerminalResponse};
+use crate::acquiring::protocol::sber::{SberOperation, SberRequest};
+use crate::acquiring::protocol::{TlvItem, TtkMessage};
+use crate::acquiring::types::{InpasOperation, TerminalResponse};
+use crate::ttk_message;
+
+ttk_message! {
+    pub struct ServiceRequest {
+        #[ttk(tag = 0x01, ascii)]
+        pub message_id: String,
+        #[ttk(tag = 0x03, bcd, len = 10)]
+        pub ern: u64,
+        #[ttk(tag = 0x1a, hex)]
+        pub subfunction: Vec<u8>,
+    }
+}
 
 pub struct TotalsCommand;
 
@@ -10,51 +24,34 @@ impl TotalsCommand {
         Self
     }
 
-    fn prepare(&self, context: &CommandContext) -> Vec<TlvItem> {
-        let message_id_tag = get_tag_definition(0x01).expect("MESSAGE_ID tag not found");
-        let ern_tag = get_tag_definition(0x03).expect("ERN tag not found");
-        let srv_subfunction_tag =
-            get_tag_definition(0x1a).expect("SRV_SUBFUNCTION tag not found");
-
-        vec![
-            TlvItem {
-                tag: message_id_tag.tag,
-                length: 3,
-                value: context.string_to_bytes("SRV"),
-                definition: Some(message_id_tag),
-            },
-            TlvItem {
-                tag: ern_tag.tag,
-                length: 10,
-                value: context.int_to_bcd(context.ern, 10),
-                definition: Some(ern_tag),
-            },
-            TlvItem {
-                tag: srv_subfunction_tag.tag,
-                length: 1,
-                value: context.string_to_bytes("2"),
-                definition: Some(srv_subfunction_tag),
-            },
-        ]
+    pub async fn execute(
+        self,
+        context: CommandContext,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        execute_command(context, &self).await
+    }
+}
+
+impl CommandRequest for TotalsCommand {
+    fn prepare(&self, context: &CommandContext) -> Result<Vec<TlvItem>, String> {
+        ServiceRequest {
+            message_id: "SRV".to_string(),
+            ern: context.ern,
+            subfunction: b"2".to_vec(),
+        }
+        .to_items()
     }
 
     fn prepare_inpas(&self, _context: &CommandContext) -> Vec<InpasField> {
-        vec![InpasField {
-            id: "25".to_string(),
-            value: "59".to_string(),
-        }]
+        vec![InpasField::operation(InpasOperation::Reconciliation)]
     }
 
-    pub async fn execute(
-        self,
-        context: CommandContext,
-    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-        execute_command(
-            context,
-            |ctx| self.prepare(ctx),
-            |ctx| self.prepare_inpas(ctx),
-        )
-        .await
+    fn prepare_sber(&self, _context: &CommandContext) -> SberRequest {
+        SberRequest::new(SberOperation::CloseDay, None)
+    }
+
+    fn prepare_external(&self, _context: &CommandContext) -> ExternalRequest {
+        ExternalRequest::new("reconciliation", None, None)
     }
 }
 
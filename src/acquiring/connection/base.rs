@@ -8,5 +8,14 @@ pub trait BaseConnection: Send + Sync {
     async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>>;
     async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
     async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+    /// Liveness check that doesn't exchange messages with the terminal.
+    async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_connected() {
+            Ok(())
+        } else {
+            Err("Not connected".into())
+        }
+    }
 }
 
@@ -0,0 +1,284 @@
+use crate::acquiring::connection::usb::open_serial;
+use crate::acquiring::connection::{BaseConnection, StreamConnection};
+use crate::acquiring::types::ConnectionConfig;
+use std::path::PathBuf;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{timeout, Duration};
+
+const DEFAULT_CHANNEL: u16 = 1;
+const CONNECT_TIMEOUT_MS: u64 = 10000;
+
+trait BluetoothStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> BluetoothStream for T {}
+
+/// Bluetooth SPP connection. `address` holds the terminal's MAC address and
+/// `port` the RFCOMM channel (1 by default). When the socket can't be opened
+/// the bound rfcomm device in `ncom` is used, if set. Only without a MAC is
+/// the first `/dev/rfcomm*` picked, since it may belong to another device.
+pub struct BluetoothConnection {
+    inner: StreamConnection<Box<dyn BluetoothStream>>,
+}
+
+impl BluetoothConnection {
+    pub fn new(config: ConnectionConfig) -> Self {
+        Self {
+            inner: StreamConnection::detached(config),
+        }
+    }
+
+    fn fallback_devices(&self) -> Vec<PathBuf> {
+        let config = self.inner.config();
+        if let Some(ncom) = &config.ncom {
+            return vec![PathBuf::from(ncom)];
+        }
+        if config.address.is_some() {
+            return Vec::new();
+        }
+        let mut devices: Vec<PathBuf> = std::fs::read_dir("/dev")
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.file_name().to_string_lossy().starts_with("rfcomm"))
+                    .map(|entry| entry.path())
+                    .collect()
+            })
+            .unwrap_or_default();
+        devices.sort();
+        devices
+    }
+
+    async fn open(&self) -> Result<Box<dyn BluetoothStream>, Box<dyn std::error::Error>> {
+        let config = self.inner.config();
+        let mut errors = Vec::new();
+
+        if let Some(address) = &config.address {
+            let mac = parse_mac(address)?;
+            let channel = config.port.unwrap_or(DEFAULT_CHANNEL);
+            let channel = u8::try_from(channel)
+                .ok()
+                .filter(|channel| (1..=30).contains(channel))
+                .ok_or_else(|| format!("Invalid RFCOMM channel: {}", channel))?;
+            match open_rfcomm(mac, channel).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => errors.push(format!("RFCOMM {} channel {}: {}", address, channel, e)),
+            }
+        }
+
+        for device in self.fallback_devices() {
+            match open_serial(&device.to_string_lossy(), config) {
+                Ok(port) => return Ok(Box::new(port)),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+
+        if errors.is_empty() {
+            return Err("Bluetooth MAC address or rfcomm device is required".into());
+        }
+        Err(format!("Failed to open Bluetooth connection: {}", errors.join("; ")).into())
+    }
+}
+
+/// Accepts `AA:BB:CC:DD:EE:FF` or `AA-BB-CC-DD-EE-FF`.
+pub fn parse_mac(address: &str) -> Result<[u8; 6], String> {
+    let parts: Vec<&str> = address.trim().split([':', '-']).collect();
+    if parts.len() != 6 {
+        return Err(format!("Invalid Bluetooth address: {}", address));
+    }
+    let mut mac = [0u8; 6];
+    for (byte, part) in mac.iter_mut().zip(parts) {
+        *byte = u8::from_str_radix(part, 16)
+            .ok()
+            .filter(|_| part.len() == 2)
+            .ok_or_else(|| format!("Invalid Bluetooth address: {}", address))?;
+    }
+    Ok(mac)
+}
+
+#[cfg(target_os = "linux")]
+async fn open_rfcomm(mac: [u8; 6], channel: u8) -> std::io::Result<Box<dyn BluetoothStream>> {
+    let stream = timeout(
+        Duration::from_millis(CONNECT_TIMEOUT_MS),
+        rfcomm::connect(mac, channel),
+    )
+    .await
+    .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    Ok(Box::new(stream))
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn open_rfcomm(_mac: [u8; 6], _channel: u8) -> std::io::Result<Box<dyn BluetoothStream>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "RFCOMM sockets are only supported on Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+mod rfcomm {
+    use crate::acquiring::connection::fd::FdStream;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const BTPROTO_RFCOMM: libc::c_int = 3;
+
+    #[repr(C)]
+    struct SockaddrRc {
+        rc_family: libc::sa_family_t,
+        rc_bdaddr: [u8; 6],
+        rc_channel: u8,
+    }
+
+    pub async fn connect(mac: [u8; 6], channel: u8) -> io::Result<FdStream> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_BLUETOOTH,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                BTPROTO_RFCOMM,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // bdaddr_t is stored least significant byte first.
+        let mut bdaddr = mac;
+        bdaddr.reverse();
+        let addr = SockaddrRc {
+            rc_family: libc::AF_BLUETOOTH as libc::sa_family_t,
+            rc_bdaddr: bdaddr,
+            rc_channel: channel,
+        };
+        let result = unsafe {
+            libc::connect(
+                fd.as_raw_fd(),
+                &addr as *const SockaddrRc as *const libc::sockaddr,
+                std::mem::size_of::<SockaddrRc>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(error);
+            }
+        }
+
+        let stream = FdStream::new(fd)?;
+        stream.writable().await?;
+
+        let mut error: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                stream.get_ref().as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ERROR,
+                &mut error as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if error != 0 {
+            return Err(io::Error::from_raw_os_error(error));
+        }
+        Ok(stream)
+    }
+}
+
+#[async_trait::async_trait]
+impl BaseConnection for BluetoothConnection {
+    fn config(&self) -> &ConnectionConfig {
+        self.inner.config()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let stream = self.open().await?;
+        self.inner.attach(stream);
+        Ok(true)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let _ = self.inner.disconnect().await;
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.write(data).await
+    }
+
+    async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.inner.read(timeout_ms).await
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::acquiring::simulator::{Scenario, TtkSimulator};
+    use crate::acquiring::types::{ConnectionConfigBuilder, ConnectionType, ProtocolType};
+    use crate::acquiring::Terminal;
+    use std::sync::Arc;
+
+    fn builder() -> crate::acquiring::types::ConnectionConfigBuilder {
+        ConnectionConfigBuilder::new(ProtocolType::Ttk, ConnectionType::Bluetooth)
+            .serial_number("1")
+            .timeout(2000)
+    }
+
+    #[test]
+    fn configured_mac_never_scans_for_devices() {
+        let config = builder().address("00:11:22:33:44:55").build().unwrap();
+        assert!(BluetoothConnection::new(config).fallback_devices().is_empty());
+
+        let config = builder()
+            .address("00:11:22:33:44:55")
+            .ncom("/dev/rfcomm3")
+            .build()
+            .unwrap();
+        assert_eq!(
+            BluetoothConnection::new(config).fallback_devices(),
+            vec![PathBuf::from("/dev/rfcomm3")]
+        );
+    }
+
+    #[tokio::test]
+    async fn payment_over_bound_device() {
+        let simulator = Arc::new(TtkSimulator::new(Scenario::Approve));
+        let (path, _) = simulator.open_pty().unwrap();
+        let config = builder().ncom(path.to_str().unwrap()).build().unwrap();
+
+        let mut terminal = Terminal::new(config);
+        assert!(terminal.connect().await.unwrap());
+        let response = terminal.payment(1000, None).await.unwrap();
+        assert!(response.success);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn failed_rfcomm_falls_back_to_ncom_only() {
+        // Nothing listens on this address, so the socket fails (or Bluetooth
+        // is missing altogether) and the pty in `ncom` takes over.
+        let simulator = Arc::new(TtkSimulator::new(Scenario::Approve));
+        let (path, _) = simulator.open_pty().unwrap();
+        let config = builder()
+            .address("00:00:00:00:00:01")
+            .ncom(path.to_str().unwrap())
+            .build()
+            .unwrap();
+        let mut connection = BluetoothConnection::new(config);
+        assert!(connection.connect().await.unwrap());
+        assert!(connection.is_connected());
+
+        let config = builder().address("00:00:00:00:00:01").build().unwrap();
+        let error = BluetoothConnection::new(config).connect().await.unwrap_err();
+        assert!(error.to_string().starts_with("Failed to open Bluetooth connection: RFCOMM"));
+        assert!(!error.to_string().contains("/dev/"));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::protocol::ExternalProcessProtocol;
+use crate::acquiring::types::ConnectionConfig;
+
+/// Placeholder connection for `ProtocolType::External`. Every operation runs
+/// the configured program; connecting only validates the configuration.
+pub struct ExternalProcessConnection {
+    config: ConnectionConfig,
+    connected: bool,
+}
+
+impl ExternalProcessConnection {
+    pub fn new(config: ConnectionConfig) -> Self {
+        Self {
+            config,
+            connected: false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BaseConnection for ExternalProcessConnection {
+    fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let external = self
+            .config
+            .external
+            .clone()
+            .ok_or("external property is required for external protocol")?;
+        ExternalProcessProtocol::new(external)?;
+        self.connected = true;
+        Ok(true)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connected = false;
+        Ok(())
+    }
+
+    async fn write(&mut self, _data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Err("write is not supported for external protocol".into())
+    }
+
+    async fn read(&mut self, _timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Err("read is not supported for external protocol".into())
+    }
+}
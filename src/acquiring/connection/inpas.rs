@@ -1,18 +1,116 @@
-use crate::acquiring::connection::BaseConnection;
-use crate::acquiring::types::ConnectionConfig;
+use crate::acquiring::connection::{
+  BaseConnection, BluetoothConnection, Rfc2217Connection, TcpConnection, TcpListenerConnection,
+  UsbConnection,
+};
+use crate::acquiring::protocol::inpas::validate_inpas_fields;
+use crate::acquiring::protocol::inpas_sa::{
+  build_sa_frame, decode_sa_fields, take_sa_unit, SaUnit, ACK, ENQ, EOT, NAK,
+};
+use crate::acquiring::types::{ConnectionConfig, ConnectionType};
+use tokio::time::{Duration, Instant};
 
+const LINK_TIMEOUT_MS: u64 = 2000;
+const MAX_ATTEMPTS: usize = 3;
+
+/// Inpas connection. With `dc_host` set the terminal is driven by
+/// DualConnector over HTTP and this connection carries no data. Otherwise it
+/// speaks the SA link layer (ENQ/ACK handshake, framed payloads) over a TCP,
+/// serial or Bluetooth transport, and `write`/`read` take and return field
+/// payloads.
 pub struct InpasConnection {
   config: ConnectionConfig,
+  transport: Option<Box<dyn BaseConnection>>,
+  pending: Vec<u8>,
   connected: bool,
 }
 
 impl InpasConnection {
   pub fn new(config: ConnectionConfig) -> Self {
+    let transport: Option<Box<dyn BaseConnection>> = if config.dc_host.is_some() {
+      None
+    } else {
+      Some(match config.connection_type {
+        ConnectionType::Tcp => Box::new(TcpConnection::new(config.clone())),
+        ConnectionType::Usb => Box::new(UsbConnection::new(config.clone())),
+        ConnectionType::Bluetooth => Box::new(BluetoothConnection::new(config.clone())),
+        ConnectionType::Listener => Box::new(TcpListenerConnection::new(config.clone())),
+        ConnectionType::Rfc2217 => Box::new(Rfc2217Connection::new(config.clone())),
+      })
+    };
+    Self {
+      config,
+      transport,
+      pending: Vec::new(),
+      connected: false,
+    }
+  }
+
+  /// Native SA over a caller-built transport.
+  pub fn with_transport(config: ConnectionConfig, transport: Box<dyn BaseConnection>) -> Self {
     Self {
       config,
+      transport: Some(transport),
+      pending: Vec::new(),
       connected: false,
     }
   }
+
+  pub fn is_native(&self) -> bool {
+    self.transport.is_some()
+  }
+
+  fn transport(&mut self) -> Result<&mut Box<dyn BaseConnection>, &'static str> {
+    self
+      .transport
+      .as_mut()
+      .ok_or("read and write are not supported for inpas protocol via DualConnector")
+  }
+
+  /// Next link-layer unit, or `None` once `deadline` passes.
+  async fn next_unit(
+    &mut self,
+    deadline: Instant,
+  ) -> Result<Option<SaUnit>, Box<dyn std::error::Error>> {
+    loop {
+      if let Some(unit) = take_sa_unit(&mut self.pending) {
+        return Ok(Some(unit));
+      }
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        return Ok(None);
+      }
+      let chunk = match self
+        .transport()?
+        .read(Some(remaining.as_millis().max(1) as u32))
+        .await
+      {
+        Ok(chunk) => chunk,
+        Err(_) if Instant::now() >= deadline => return Ok(None),
+        Err(e) => return Err(e),
+      };
+      if chunk.is_empty() {
+        return Err("Connection closed by terminal".into());
+      }
+      self.pending.extend_from_slice(&chunk);
+    }
+  }
+
+  /// Waits for ACK or NAK, skipping anything else. Returns false on NAK or
+  /// when the terminal stays silent.
+  async fn await_ack(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+    let deadline = Instant::now() + Duration::from_millis(LINK_TIMEOUT_MS);
+    loop {
+      match self.next_unit(deadline).await? {
+        Some(SaUnit::Control(ACK)) => return Ok(true),
+        Some(SaUnit::Control(NAK)) | None => return Ok(false),
+        Some(_) => continue,
+      }
+    }
+  }
+
+  async fn send_control(&mut self, byte: u8) -> Result<(), Box<dyn std::error::Error>> {
+    self.transport()?.write(&[byte]).await
+  }
 }
 
 #[async_trait::async_trait]
@@ -26,23 +124,203 @@ impl BaseConnection for InpasConnection {
   }
 
   async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(transport) = self.transport.as_mut() {
+      transport.connect().await?;
+    }
+    self.pending.clear();
     self.connected = true;
     Ok(true)
   }
 
   async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(transport) = self.transport.as_mut() {
+      transport.disconnect().await?;
+    }
     self.connected = false;
     Ok(())
   }
 
-  async fn write(&mut self, _data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-    Err("write is not supported for inpas protocol".into())
+  async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    if !self.connected {
+      return Err("Not connected".into());
+    }
+    match self.transport.as_mut() {
+      Some(transport) => transport.check().await,
+      None => Ok(()),
+    }
+  }
+
+  async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    validate_inpas_fields(&decode_sa_fields(data)?)?;
+    let frame = build_sa_frame(data)?;
+
+    let mut line_acquired = false;
+    for _ in 0..MAX_ATTEMPTS {
+      self.send_control(ENQ).await?;
+      if self.await_ack().await? {
+        line_acquired = true;
+        break;
+      }
+    }
+    if !line_acquired {
+      return Err("Inpas terminal did not acknowledge ENQ".into());
+    }
+
+    for _ in 0..MAX_ATTEMPTS {
+      self.transport()?.write(&frame).await?;
+      if self.await_ack().await? {
+        return self.send_control(EOT).await;
+      }
+    }
+    Err("Inpas terminal rejected the message".into())
   }
 
   async fn read(
     &mut self,
-    _timeout_ms: Option<u32>,
+    timeout_ms: Option<u32>,
   ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    Err("read is not supported for inpas protocol".into())
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms.unwrap_or(30000) as u64);
+    let mut rejected = 0;
+    loop {
+      let Some(unit) = self.next_unit(deadline).await? else {
+        return Err("Timed out waiting for Inpas terminal".into());
+      };
+      match unit {
+        SaUnit::Control(ENQ) => self.send_control(ACK).await?,
+        SaUnit::Control(_) => {}
+        SaUnit::Frame(Ok(payload)) => {
+          self.send_control(ACK).await?;
+          return Ok(payload);
+        }
+        SaUnit::Frame(Err(e)) => {
+          rejected += 1;
+          if rejected >= MAX_ATTEMPTS {
+            return Err(e.into());
+          }
+          self.send_control(NAK).await?;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::acquiring::connection::StreamConnection;
+  use crate::acquiring::protocol::inpas::InpasField;
+  use crate::acquiring::protocol::inpas_sa::{decode_sa_fields, encode_sa_fields};
+  use crate::acquiring::types::{ConnectionConfigBuilder, InpasFieldId, ProtocolType};
+  use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+  async fn native() -> (InpasConnection, DuplexStream) {
+    let (local, remote) = duplex(1024);
+    let config = ConnectionConfigBuilder::new(ProtocolType::Inpas, ConnectionType::Tcp)
+      .address("127.0.0.1")
+      .port(27015)
+      .serial_number("1")
+      .build()
+      .unwrap();
+    let transport = StreamConnection::new(config.clone(), local);
+    let mut connection = InpasConnection::with_transport(config, Box::new(transport));
+    connection.connect().await.unwrap();
+    (connection, remote)
+  }
+
+  async fn expect(terminal: &mut DuplexStream, expected: &[u8]) {
+    let mut buffer = vec![0u8; expected.len()];
+    terminal.read_exact(&mut buffer).await.unwrap();
+    assert_eq!(buffer, expected);
+  }
+
+  fn payload(fields: &[(InpasFieldId, &str)]) -> Vec<u8> {
+    let fields: Vec<InpasField> = fields
+      .iter()
+      .map(|(id, value)| InpasField::new(*id, *value))
+      .collect();
+    encode_sa_fields(&fields).unwrap()
+  }
+
+  #[tokio::test]
+  async fn write_retries_enq_and_frame_after_nak() {
+    let (mut connection, mut terminal) = native().await;
+    let payload = payload(&[(InpasFieldId::Amount, "1000")]);
+    let frame = build_sa_frame(&payload).unwrap();
+
+    let script = tokio::spawn(async move {
+      expect(&mut terminal, &[ENQ]).await;
+      terminal.write_all(&[NAK]).await.unwrap();
+      expect(&mut terminal, &[ENQ]).await;
+      terminal.write_all(&[ACK]).await.unwrap();
+      expect(&mut terminal, &frame).await;
+      terminal.write_all(&[NAK]).await.unwrap();
+      expect(&mut terminal, &frame).await;
+      terminal.write_all(&[ACK]).await.unwrap();
+      expect(&mut terminal, &[EOT]).await;
+      terminal
+    });
+
+    connection.write(&payload).await.unwrap();
+    script.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn write_gives_up_after_max_attempts() {
+    let (mut connection, mut terminal) = native().await;
+    let script = tokio::spawn(async move {
+      for _ in 0..MAX_ATTEMPTS {
+        expect(&mut terminal, &[ENQ]).await;
+        terminal.write_all(&[NAK]).await.unwrap();
+      }
+      terminal
+    });
+
+    let error = connection
+      .write(&payload(&[(InpasFieldId::Amount, "1000")]))
+      .await
+      .unwrap_err();
+    assert_eq!(error.to_string(), "Inpas terminal did not acknowledge ENQ");
+    script.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn read_naks_a_corrupted_frame_and_acks_the_resent_one() {
+    let (mut connection, mut terminal) = native().await;
+    let response = payload(&[(InpasFieldId::ResponseCode, "00"), (InpasFieldId::Status, "1")]);
+    let frame = build_sa_frame(&response).unwrap();
+    let mut corrupted = frame.clone();
+    corrupted[4] ^= 0xff;
+
+    let script = tokio::spawn(async move {
+      terminal.write_all(&[ENQ]).await.unwrap();
+      expect(&mut terminal, &[ACK]).await;
+      terminal.write_all(&corrupted).await.unwrap();
+      expect(&mut terminal, &[NAK]).await;
+      terminal.write_all(&frame).await.unwrap();
+      expect(&mut terminal, &[ACK]).await;
+      terminal.write_all(&[EOT]).await.unwrap();
+      terminal
+    });
+
+    let received = connection.read(Some(2000)).await.unwrap();
+    assert_eq!(received, response);
+    let fields = decode_sa_fields(&received).unwrap();
+    assert_eq!(fields[0].field_id(), Some(InpasFieldId::ResponseCode));
+    script.await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn invalid_fields_never_reach_the_line() {
+    let (mut connection, mut terminal) = native().await;
+
+    let error = connection
+      .write(&payload(&[(InpasFieldId::Currency, "RUB")]))
+      .await
+      .unwrap_err();
+    assert!(error.to_string().starts_with("Inpas field 04 (Currency)"), "{}", error);
+
+    let mut buffer = [0u8; 1];
+    let read = tokio::time::timeout(Duration::from_millis(50), terminal.read(&mut buffer)).await;
+    assert!(read.is_err(), "nothing should have been sent");
   }
 }
@@ -1,10 +1,32 @@
 pub mod base;
+pub mod bluetooth;
+pub mod external;
+#[cfg(unix)]
+pub mod fd;
 pub mod inpas;
+pub mod recording;
+pub mod replay;
+pub mod rfc2217;
+pub mod sber;
+pub mod stream;
 pub mod tcp;
+pub mod tcp_listener;
+pub mod tls;
 pub mod usb;
 
 pub use base::BaseConnection;
+pub use bluetooth::BluetoothConnection;
+pub use external::ExternalProcessConnection;
 pub use inpas::InpasConnection;
+pub use recording::RecordingConnection;
+pub use replay::ReplayConnection;
+pub use rfc2217::Rfc2217Connection;
+pub use sber::SberConnection;
+pub use stream::StreamConnection;
 pub use tcp::TcpConnection;
-pub use usb::UsbConnection;
+pub use tcp_listener::TcpListenerConnection;
+pub use usb::{
+    find_serial_terminal, list_serial_terminals, select_serial_port, SerialPortDescription,
+    UsbConnection,
+};
 
@@ -0,0 +1,368 @@
+use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::protocol::{TlvItem, TtkBuffer};
+use crate::acquiring::types::{ConnectionConfig, DataType};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+const PAN_TAG: u32 = 0x89;
+
+static TEXT_PAN: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"\d{13,19}").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameDirection {
+    Write,
+    Read,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionFrame {
+    pub timestamp: String,
+    pub direction: FrameDirection,
+    pub data: String,
+}
+
+impl SessionFrame {
+    pub fn new(direction: FrameDirection, data: &[u8]) -> Self {
+        Self {
+            timestamp: chrono::Local::now()
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            direction,
+            data: data.iter().map(|b| format!("{:02X}", b)).collect(),
+        }
+    }
+
+    pub fn bytes(&self) -> Result<Vec<u8>, String> {
+        if !self.data.len().is_multiple_of(2) {
+            return Err(format!("Odd number of hex digits in frame at {}", self.timestamp));
+        }
+        (0..self.data.len())
+            .step_by(2)
+            .map(|i| {
+                u8::from_str_radix(&self.data[i..i + 2], 16)
+                    .map_err(|_| format!("Invalid hex in frame at {}", self.timestamp))
+            })
+            .collect()
+    }
+}
+
+pub fn read_session(path: impl AsRef<Path>) -> Result<Vec<SessionFrame>, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut frames = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        frames.push(serde_json::from_str(&line)?);
+    }
+    Ok(frames)
+}
+
+/// Complete TTK frames have the PAN tag zeroed between the first six and
+/// last four digits. Every other value is masked in place, without decoding
+/// it: 13+ digit ASCII runs are starred out and, outside text tags, 13+ digit
+/// BCD runs zeroed the same way. Frames that don't parse keep their length
+/// header but have the body zeroed, so nothing unmasked reaches the session
+/// file.
+pub fn mask_frame(data: &[u8]) -> Vec<u8> {
+    let Ok((message_type, items)) = TtkBuffer::parse_message(data) else {
+        let mut masked = data.to_vec();
+        masked.iter_mut().skip(2).for_each(|byte| *byte = 0);
+        return masked;
+    };
+
+    let items: Vec<TlvItem> = items
+        .into_iter()
+        .map(|item| {
+            let text = item
+                .definition
+                .is_some_and(|definition| definition.data_type == DataType::String);
+            let value = if item.tag == PAN_TAG {
+                mask_bcd_pan(&item.value)
+            } else if text {
+                mask_ascii_digits(&item.value)
+            } else {
+                mask_bcd_digits(&mask_ascii_digits(&item.value))
+            };
+            TlvItem::new(item.tag, value)
+        })
+        .collect();
+
+    TtkBuffer::create_message(message_type, &items)
+}
+
+fn mask_bcd_pan(value: &[u8]) -> Vec<u8> {
+    let digits = value.len() * 2;
+    let mut masked = value.to_vec();
+    for position in 6..digits.saturating_sub(4) {
+        set_nibble(&mut masked, position, 0);
+    }
+    masked
+}
+
+fn set_nibble(bytes: &mut [u8], position: usize, nibble: u8) {
+    let byte = &mut bytes[position / 2];
+    if position.is_multiple_of(2) {
+        *byte = (*byte & 0x0f) | (nibble << 4);
+    } else {
+        *byte = (*byte & 0xf0) | nibble;
+    }
+}
+
+/// Start and end of every run of at least 13 items matching `is_digit`.
+fn digit_runs(len: usize, is_digit: impl Fn(usize) -> bool) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for position in 0..=len {
+        if position < len && is_digit(position) {
+            continue;
+        }
+        if position - start >= 13 {
+            runs.push((start, position));
+        }
+        start = position + 1;
+    }
+    runs
+}
+
+/// Stars out ASCII digit runs in raw bytes; digits are ASCII in every text
+/// encoding TTK uses, so nothing has to be decoded or re-encoded.
+fn mask_ascii_digits(value: &[u8]) -> Vec<u8> {
+    let mut masked = value.to_vec();
+    for (start, end) in digit_runs(value.len(), |i| value[i].is_ascii_digit()) {
+        masked[start + 6..end - 4].fill(b'*');
+    }
+    masked
+}
+
+fn mask_bcd_digits(value: &[u8]) -> Vec<u8> {
+    let nibble = |position: usize| {
+        let byte = value[position / 2];
+        if position.is_multiple_of(2) { byte >> 4 } else { byte & 0x0f }
+    };
+    let mut masked = value.to_vec();
+    // Bytes already starred out end a run, so masked text stays readable.
+    let is_digit = |position: usize| value[position / 2] != b'*' && nibble(position) <= 9;
+    for (start, end) in digit_runs(value.len() * 2, is_digit) {
+        for position in start + 6..end - 4 {
+            set_nibble(&mut masked, position, 0);
+        }
+    }
+    masked
+}
+
+/// Stars out every 13 to 19 digit run in `text` between its first six and
+/// last four digits.
+pub fn mask_pans(text: &str) -> String {
+    TEXT_PAN
+        .replace_all(text, |caps: &regex::Captures| {
+            let pan = &caps[0];
+            format!(
+                "{}{}{}",
+                &pan[..6],
+                "*".repeat(pan.len() - 10),
+                &pan[pan.len() - 4..]
+            )
+        })
+        .to_string()
+}
+
+/// Records every frame passing through `inner` to a JSON-lines session file.
+/// Reads are reassembled into whole TTK frames before they are masked and
+/// recorded; a partial frame still pending on disconnect is dropped rather
+/// than recorded unmasked.
+pub struct RecordingConnection {
+    inner: Box<dyn BaseConnection>,
+    file: File,
+    pending_write: Vec<u8>,
+    pending_read: Vec<u8>,
+    record_error: Option<String>,
+}
+
+impl RecordingConnection {
+    pub fn new(
+        inner: Box<dyn BaseConnection>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            inner,
+            file,
+            pending_write: Vec::new(),
+            pending_read: Vec::new(),
+            record_error: None,
+        })
+    }
+
+    /// The last failure to write the session file. Recording failures don't
+    /// fail the exchange with the terminal.
+    pub fn take_record_error(&mut self) -> Option<String> {
+        self.record_error.take()
+    }
+
+    fn record(&mut self, direction: FrameDirection, data: &[u8]) {
+        let pending = match direction {
+            FrameDirection::Write => &mut self.pending_write,
+            FrameDirection::Read => &mut self.pending_read,
+        };
+        pending.extend_from_slice(data);
+
+        let mut frames = Vec::new();
+        while let Some(frame) = TtkBuffer::take_frame(pending) {
+            frames.push(frame);
+        }
+        for frame in frames {
+            if let Err(e) = self.write_frame(direction, &frame) {
+                self.record_error = Some(e.to_string());
+            }
+        }
+    }
+
+    fn write_frame(
+        &mut self,
+        direction: FrameDirection,
+        frame: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let frame = SessionFrame::new(direction, &mask_frame(frame));
+        writeln!(self.file, "{}", serde_json::to_string(&frame)?)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl BaseConnection for RecordingConnection {
+    fn config(&self) -> &ConnectionConfig {
+        self.inner.config()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.inner.connect().await
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.pending_write.clear();
+        self.pending_read.clear();
+        self.inner.disconnect().await
+    }
+
+    async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.check().await
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.write(data).await?;
+        self.record(FrameDirection::Write, data);
+        Ok(())
+    }
+
+    async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let data = self.inner.read(timeout_ms).await?;
+        self.record(FrameDirection::Read, &data);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::connection::ReplayConnection;
+    use crate::acquiring::protocol::TlvEncoder;
+    use crate::acquiring::types::{ConnectionConfigBuilder, MessageType};
+
+    fn session_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn split_frames_are_reassembled_and_masked() {
+        let pan = vec![0x42, 0x76, 0x12, 0x34, 0x56, 0x78, 0x12, 0x34];
+        let frame = TtkBuffer::create_message(
+            MessageType::ServerResponse,
+            &[TlvItem::new(PAN_TAG, pan.clone())],
+        );
+        let (head, tail) = frame.split_at(7);
+        let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", 1).build().unwrap();
+        let replay = ReplayConnection::new(
+            config,
+            vec![
+                SessionFrame::new(FrameDirection::Read, head),
+                SessionFrame::new(FrameDirection::Read, tail),
+            ],
+        );
+
+        let path = session_path("recording-split");
+        let mut recording = RecordingConnection::new(Box::new(replay), &path).unwrap();
+        recording.connect().await.unwrap();
+        assert_eq!(recording.read(None).await.unwrap(), head);
+        assert_eq!(recording.read(None).await.unwrap(), tail);
+        assert!(recording.take_record_error().is_none());
+
+        let frames = read_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(frames.len(), 1);
+        let recorded = frames[0].bytes().unwrap();
+        assert_eq!(recorded.len(), frame.len());
+        assert!(!recorded.windows(pan.len()).any(|window| window == pan));
+        let (_, items) = TtkBuffer::parse_message(&recorded).unwrap();
+        assert_eq!(TlvEncoder::value_to_string(&items[0]), "4276120000001234");
+    }
+
+    #[test]
+    fn unparsed_frames_are_zeroed() {
+        let masked = mask_frame(&[0x00, 0x04, 0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(masked, vec![0x00, 0x04, 0x00, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn values_that_cannot_be_re_encoded_are_still_masked() {
+        // Receipt is registered as ASCII, so the 0xFF byte can't round-trip
+        // through a decoded string.
+        let mut receipt = b"CARD 4000000000000002 ".to_vec();
+        receipt.push(0xff);
+        let frame = TtkBuffer::create_message(
+            MessageType::ServerResponse,
+            &[TlvItem::new(0x9c, receipt)],
+        );
+
+        let (_, items) = TtkBuffer::parse_message(&mask_frame(&frame)).unwrap();
+        assert_eq!(items[0].value, b"CARD 400000******0002 \xff".to_vec());
+    }
+
+    #[test]
+    fn unknown_and_binary_tags_are_masked() {
+        let ascii = TlvItem::new(0xdf01, b"4000000000000002".to_vec());
+        let bcd = TlvItem::new(0xdf02, vec![0x42, 0x76, 0x12, 0x34, 0x56, 0x78, 0x12, 0x34]);
+        let binary = TlvItem::new(0x9e, vec![0x00, 0x42, 0x76, 0x12, 0x34, 0x56, 0x78, 0x12, 0x34, 0xff]);
+        let tvr = TlvItem::new(0x95, vec![0x00, 0x00, 0x00, 0x80, 0x00]);
+        let frame = TtkBuffer::create_message(
+            MessageType::ServerResponse,
+            &[ascii, bcd, binary, tvr.clone()],
+        );
+
+        let (_, items) = TtkBuffer::parse_message(&mask_frame(&frame)).unwrap();
+        assert_eq!(items[0].value, b"400000******0002".to_vec());
+        assert_eq!(items[1].value, vec![0x42, 0x76, 0x12, 0x00, 0x00, 0x00, 0x12, 0x34]);
+        assert_eq!(
+            items[2].value,
+            vec![0x00, 0x42, 0x76, 0x00, 0x00, 0x00, 0x00, 0x12, 0x34, 0xff]
+        );
+        assert_eq!(items[3].value, tvr.value);
+    }
+
+    #[test]
+    fn text_pans_keep_first_six_and_last_four() {
+        assert_eq!(mask_pans("card 4000000000000002 ok"), "card 400000******0002 ok");
+        assert_eq!(mask_pans("RRN 123456789012"), "RRN 123456789012");
+    }
+}
@@ -0,0 +1,94 @@
+use crate::acquiring::connection::recording::{read_session, FrameDirection, SessionFrame};
+use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::types::ConnectionConfig;
+use std::collections::VecDeque;
+use std::path::Path;
+
+/// Serves a recorded session back: every `read` returns the next recorded
+/// read frame, and written data is kept in `written` for assertions instead
+/// of being compared, since ERNs differ from run to run.
+pub struct ReplayConnection {
+    config: ConnectionConfig,
+    frames: VecDeque<SessionFrame>,
+    written: Vec<Vec<u8>>,
+    connected: bool,
+}
+
+impl ReplayConnection {
+    pub fn new(config: ConnectionConfig, frames: Vec<SessionFrame>) -> Self {
+        Self {
+            config,
+            frames: frames.into(),
+            written: Vec::new(),
+            connected: false,
+        }
+    }
+
+    pub fn from_file(
+        config: ConnectionConfig,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::new(config, read_session(path)?))
+    }
+
+    pub fn written(&self) -> &[Vec<u8>] {
+        &self.written
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+#[async_trait::async_trait]
+impl BaseConnection for ReplayConnection {
+    fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.connected = true;
+        Ok(true)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connected = false;
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+
+        if let Some(position) = self
+            .frames
+            .iter()
+            .position(|frame| frame.direction == FrameDirection::Write)
+        {
+            if self.frames.iter().take(position).any(|f| f.direction == FrameDirection::Read) {
+                return Err("Replay out of order: unread frames before the next write".into());
+            }
+            self.frames.remove(position);
+        }
+        self.written.push(data.to_vec());
+        Ok(())
+    }
+
+    async fn read(&mut self, _timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+
+        loop {
+            let frame = self.frames.pop_front().ok_or("Replay session exhausted")?;
+            if frame.direction == FrameDirection::Read {
+                return Ok(frame.bytes()?);
+            }
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::types::{ConnectionConfig, SerialFlowControl, SerialParity};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{timeout_at, Duration, Instant};
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+pub const SE: u8 = 240;
+
+pub const BINARY: u8 = 0;
+pub const SUPPRESS_GO_AHEAD: u8 = 3;
+pub const COM_PORT_OPTION: u8 = 44;
+
+pub const SET_BAUDRATE: u8 = 1;
+pub const SET_DATASIZE: u8 = 2;
+pub const SET_PARITY: u8 = 3;
+pub const SET_STOPSIZE: u8 = 4;
+pub const SET_CONTROL: u8 = 5;
+/// Added to a command code in the access server's reply.
+pub const SERVER_OFFSET: u8 = 100;
+
+const DEFAULT_BAUD_RATE: u32 = 9600;
+const NEGOTIATION_TIMEOUT_MS: u64 = 5000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TelnetEvent {
+    /// WILL/WONT/DO/DONT and the option.
+    Negotiation(u8, u8),
+    /// Subnegotiation payload after the option byte.
+    Subnegotiation(u8, Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum DecoderState {
+    #[default]
+    Data,
+    Iac,
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// Splits a telnet byte stream into data and commands. Keeps its state
+/// between chunks, so commands may be split across reads.
+#[derive(Debug, Default)]
+pub struct TelnetDecoder {
+    state: DecoderState,
+    subnegotiation: Vec<u8>,
+}
+
+impl TelnetDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn decode(&mut self, input: &[u8]) -> (Vec<u8>, Vec<TelnetEvent>) {
+        let mut data = Vec::new();
+        let mut events = Vec::new();
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (DecoderState::Data, IAC) => DecoderState::Iac,
+                (DecoderState::Data, _) => {
+                    data.push(byte);
+                    DecoderState::Data
+                }
+                (DecoderState::Iac, IAC) => {
+                    data.push(IAC);
+                    DecoderState::Data
+                }
+                (DecoderState::Iac, WILL | WONT | DO | DONT) => DecoderState::Negotiation(byte),
+                (DecoderState::Iac, SB) => {
+                    self.subnegotiation.clear();
+                    DecoderState::Subnegotiation
+                }
+                // NOP, GA and the like carry nothing we need.
+                (DecoderState::Iac, _) => DecoderState::Data,
+                (DecoderState::Negotiation(command), option) => {
+                    events.push(TelnetEvent::Negotiation(command, option));
+                    DecoderState::Data
+                }
+                (DecoderState::Subnegotiation, IAC) => DecoderState::SubnegotiationIac,
+                (DecoderState::Subnegotiation, _) => {
+                    self.subnegotiation.push(byte);
+                    DecoderState::Subnegotiation
+                }
+                (DecoderState::SubnegotiationIac, IAC) => {
+                    self.subnegotiation.push(IAC);
+                    DecoderState::Subnegotiation
+                }
+                (DecoderState::SubnegotiationIac, SE) => {
+                    if let Some((&option, payload)) = self.subnegotiation.split_first() {
+                        events.push(TelnetEvent::Subnegotiation(option, payload.to_vec()));
+                    }
+                    DecoderState::Data
+                }
+                (DecoderState::SubnegotiationIac, _) => DecoderState::Subnegotiation,
+            };
+        }
+        (data, events)
+    }
+}
+
+/// Doubles IAC bytes in outgoing data.
+pub fn escape_iac(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        escaped.push(byte);
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+    }
+    escaped
+}
+
+pub fn subnegotiation(option: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = vec![IAC, SB, option];
+    message.extend(escape_iac(payload));
+    message.extend([IAC, SE]);
+    message
+}
+
+/// Serial port on an RFC 2217 access server (Moxa NPort and the like):
+/// `address`/`port` point to the server, `baudrate` and `serial` set up the
+/// remote line.
+pub struct Rfc2217Connection {
+    config: ConnectionConfig,
+    stream: Option<TcpStream>,
+    decoder: TelnetDecoder,
+    pending: Vec<u8>,
+    connected: bool,
+}
+
+impl Rfc2217Connection {
+    pub fn new(config: ConnectionConfig) -> Self {
+        Self {
+            config,
+            stream: None,
+            decoder: TelnetDecoder::new(),
+            pending: Vec::new(),
+            connected: false,
+        }
+    }
+
+    /// COM-PORT-OPTION commands for the configured line settings.
+    fn line_settings(&self) -> Result<Vec<(u8, Vec<u8>)>, String> {
+        let settings = self.config.serial.clone().unwrap_or_default();
+        let baud_rate = self.config.baudrate.unwrap_or(DEFAULT_BAUD_RATE);
+        let data_bits = settings.data_bits.unwrap_or(8);
+        if !(5..=8).contains(&data_bits) {
+            return Err(format!("Invalid data bits: {}", data_bits));
+        }
+        let stop_bits = match settings.stop_bits.unwrap_or(1) {
+            1 => 1,
+            2 => 2,
+            other => return Err(format!("Invalid stop bits: {}", other)),
+        };
+        let parity = match settings.parity.unwrap_or(SerialParity::None) {
+            SerialParity::None => 1,
+            SerialParity::Odd => 2,
+            SerialParity::Even => 3,
+        };
+        let flow_control = match settings.flow_control.unwrap_or(SerialFlowControl::None) {
+            SerialFlowControl::None => 1,
+            SerialFlowControl::Software => 2,
+            SerialFlowControl::Hardware => 3,
+        };
+
+        let mut commands = vec![
+            (SET_BAUDRATE, baud_rate.to_be_bytes().to_vec()),
+            (SET_DATASIZE, vec![data_bits]),
+            (SET_PARITY, vec![parity]),
+            (SET_STOPSIZE, vec![stop_bits]),
+            (SET_CONTROL, vec![flow_control]),
+        ];
+        if let Some(dtr) = settings.dtr {
+            commands.push((SET_CONTROL, vec![if dtr { 8 } else { 9 }]));
+        }
+        if let Some(rts) = settings.rts {
+            commands.push((SET_CONTROL, vec![if rts { 11 } else { 12 }]));
+        }
+        Ok(commands)
+    }
+
+    /// Reads once, answering telnet negotiation and queueing line data.
+    /// Returns false on EOF.
+    async fn receive(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<(bool, Vec<TelnetEvent>), Box<dyn std::error::Error>> {
+        let stream = self.stream.as_mut().ok_or("Not connected")?;
+        let mut buffer = vec![0u8; 4096];
+        let n = match deadline {
+            Some(deadline) => timeout_at(deadline, stream.read(&mut buffer)).await??,
+            None => stream.read(&mut buffer).await?,
+        };
+        if n == 0 {
+            return Ok((false, Vec::new()));
+        }
+
+        let (data, events) = self.decoder.decode(&buffer[..n]);
+        self.pending.extend(data);
+        let mut replies = Vec::new();
+        for event in &events {
+            if let TelnetEvent::Negotiation(command, option) = *event {
+                let wanted = matches!(option, BINARY | SUPPRESS_GO_AHEAD | COM_PORT_OPTION);
+                match command {
+                    DO if !wanted => replies.extend([IAC, WONT, option]),
+                    WILL if !wanted => replies.extend([IAC, DONT, option]),
+                    _ => {}
+                }
+            }
+        }
+        if !replies.is_empty() {
+            stream.write_all(&replies).await?;
+        }
+        Ok((true, events))
+    }
+
+    async fn negotiate(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let deadline = Instant::now() + Duration::from_millis(NEGOTIATION_TIMEOUT_MS);
+        let stream = self.stream.as_mut().ok_or("Not connected")?;
+        stream
+            .write_all(&[
+                IAC, WILL, COM_PORT_OPTION,
+                IAC, WILL, BINARY,
+                IAC, DO, BINARY,
+                IAC, WILL, SUPPRESS_GO_AHEAD,
+                IAC, DO, SUPPRESS_GO_AHEAD,
+            ])
+            .await?;
+
+        let commands = self.line_settings()?;
+        let mut settings = Vec::new();
+        for (command, value) in &commands {
+            settings.extend(subnegotiation(
+                COM_PORT_OPTION,
+                &[&[*command][..], value].concat(),
+            ));
+        }
+        let stream = self.stream.as_mut().ok_or("Not connected")?;
+        stream.write_all(&settings).await?;
+
+        let mut accepted = false;
+        let mut unconfirmed: Vec<u8> = commands.iter().map(|(command, _)| *command).collect();
+        while !accepted || !unconfirmed.is_empty() {
+            let (open, events) = self.receive(Some(deadline)).await.map_err(|e| {
+                if e.is::<tokio::time::error::Elapsed>() {
+                    "Serial server did not confirm the RFC 2217 port settings".into()
+                } else {
+                    e
+                }
+            })?;
+            if !open {
+                return Err("Serial server closed the connection".into());
+            }
+            for event in events {
+                match event {
+                    TelnetEvent::Negotiation(DO, COM_PORT_OPTION) => accepted = true,
+                    TelnetEvent::Negotiation(DONT, COM_PORT_OPTION) => {
+                        return Err("Serial server does not support RFC 2217".into());
+                    }
+                    TelnetEvent::Subnegotiation(COM_PORT_OPTION, payload) => {
+                        if let Some(&reply) = payload.first() {
+                            let command = reply.wrapping_sub(SERVER_OFFSET);
+                            if let Some(i) = unconfirmed.iter().position(|c| *c == command) {
+                                unconfirmed.remove(i);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl BaseConnection for Rfc2217Connection {
+    fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let port = self.config.port.ok_or("Port is required for RFC 2217 connection")?;
+        let address = self
+            .config
+            .address
+            .as_ref()
+            .ok_or("Address is required for RFC 2217 connection")?;
+
+        let addr = format!("{}:{}", address, port);
+        let stream = TcpStream::connect(&addr).await?;
+        stream.set_nodelay(true)?;
+        self.stream = Some(stream);
+        self.decoder = TelnetDecoder::new();
+        self.pending.clear();
+        if let Err(e) = self.negotiate().await {
+            self.stream = None;
+            return Err(e);
+        }
+        self.connected = true;
+        Ok(true)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connected = false;
+        self.pending.clear();
+        if let Some(mut stream) = self.stream.take() {
+            stream.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+        let stream = self.stream.as_mut().ok_or("Not connected")?;
+        stream.write_all(&escape_iac(data)).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+        let deadline = timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms as u64));
+        while self.pending.is_empty() {
+            let (open, _) = self.receive(deadline).await?;
+            if !open {
+                self.connected = false;
+                self.stream = None;
+                return Err("Serial server closed the connection".into());
+            }
+        }
+        Ok(std::mem::take(&mut self.pending))
+    }
+
+    async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+        let stream = self.stream.as_ref().ok_or("Not connected")?;
+        if let Some(error) = stream.take_error()? {
+            return Err(error.into());
+        }
+        let mut buffer = [0u8; 1];
+        match tokio::time::timeout(Duration::ZERO, stream.peek(&mut buffer)).await {
+            Ok(Ok(0)) => Err("Serial server closed the connection".into()),
+            Ok(Err(e)) => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::types::{ConnectionConfigBuilder, ConnectionType, ProtocolType, SerialConfig};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn commands_may_be_split_across_reads() {
+        let mut decoder = TelnetDecoder::new();
+        assert_eq!(decoder.decode(&[0x01, IAC]), (vec![0x01], vec![]));
+        assert_eq!(decoder.decode(&[IAC, 0x02, IAC]), (vec![IAC, 0x02], vec![]));
+        assert_eq!(decoder.decode(&[DO]), (vec![], vec![]));
+        assert_eq!(
+            decoder.decode(&[COM_PORT_OPTION, 0x03]),
+            (vec![0x03], vec![TelnetEvent::Negotiation(DO, COM_PORT_OPTION)])
+        );
+    }
+
+    #[test]
+    fn escaped_iac_inside_subnegotiation() {
+        let mut decoder = TelnetDecoder::new();
+        let message = [IAC, SB, COM_PORT_OPTION, 101, IAC, IAC, 0x00, IAC, SE, 0x04];
+        let (data, events) = decoder.decode(&message);
+        assert_eq!(data, vec![0x04]);
+        assert_eq!(
+            events,
+            vec![TelnetEvent::Subnegotiation(COM_PORT_OPTION, vec![101, IAC, 0x00])]
+        );
+
+        let (first, second) = message.split_at(5);
+        assert_eq!(decoder.decode(first), (vec![], vec![]));
+        assert_eq!(decoder.decode(second).1, events);
+    }
+
+    #[test]
+    fn iac_bytes_are_doubled() {
+        assert_eq!(escape_iac(&[]), Vec::<u8>::new());
+        assert_eq!(escape_iac(&[0x01, 0x02]), vec![0x01, 0x02]);
+        assert_eq!(escape_iac(&[0x01, IAC, IAC, 0x02]), vec![0x01, IAC, IAC, IAC, IAC, 0x02]);
+        assert_eq!(
+            subnegotiation(COM_PORT_OPTION, &[SET_BAUDRATE, 0x00, 0x00, 0x00, IAC]),
+            vec![IAC, SB, COM_PORT_OPTION, SET_BAUDRATE, 0x00, 0x00, 0x00, IAC, IAC, IAC, SE]
+        );
+    }
+
+    /// Reads from `stream` until `done` holds for everything decoded so far.
+    async fn receive_until(
+        stream: &mut TcpStream,
+        decoder: &mut TelnetDecoder,
+        received: &mut (Vec<u8>, Vec<TelnetEvent>),
+        done: impl Fn(&(Vec<u8>, Vec<TelnetEvent>)) -> bool,
+    ) {
+        let mut buffer = [0u8; 256];
+        while !done(received) {
+            let n = stream.read(&mut buffer).await.unwrap();
+            assert_ne!(n, 0, "client closed the connection");
+            let (data, events) = decoder.decode(&buffer[..n]);
+            received.0.extend(data);
+            received.1.extend(events);
+        }
+    }
+
+    #[tokio::test]
+    async fn negotiates_with_a_serial_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut decoder = TelnetDecoder::new();
+            let mut received = (Vec::new(), Vec::new());
+            let settings = |received: &(Vec<u8>, Vec<TelnetEvent>)| -> Vec<Vec<u8>> {
+                received
+                    .1
+                    .iter()
+                    .filter_map(|event| match event {
+                        TelnetEvent::Subnegotiation(COM_PORT_OPTION, payload) => Some(payload.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            };
+            receive_until(&mut stream, &mut decoder, &mut received, |r| settings(r).len() == 5).await;
+            assert!(received.1.contains(&TelnetEvent::Negotiation(WILL, COM_PORT_OPTION)));
+            assert_eq!(
+                settings(&received),
+                vec![
+                    vec![SET_BAUDRATE, 0x00, 0x01, 0xc2, 0x00],
+                    vec![SET_DATASIZE, 8],
+                    vec![SET_PARITY, 3],
+                    vec![SET_STOPSIZE, 1],
+                    vec![SET_CONTROL, 1],
+                ]
+            );
+
+            // An option the client doesn't know, then the confirmations a
+            // byte at a time.
+            stream.write_all(&[IAC, DO, COM_PORT_OPTION, IAC, DO, 99]).await.unwrap();
+            for payload in settings(&received) {
+                let mut reply = payload.clone();
+                reply[0] += SERVER_OFFSET;
+                for byte in subnegotiation(COM_PORT_OPTION, &reply) {
+                    stream.write_all(&[byte]).await.unwrap();
+                }
+            }
+            receive_until(&mut stream, &mut decoder, &mut received, |r| {
+                r.1.contains(&TelnetEvent::Negotiation(WONT, 99))
+            })
+            .await;
+
+            receive_until(&mut stream, &mut decoder, &mut received, |r| r.0.len() == 3).await;
+            assert_eq!(received.0, vec![0x02, IAC, 0x03]);
+            stream.write_all(&[IAC, IAC, 0x01]).await.unwrap();
+        });
+
+        let config = ConnectionConfigBuilder::new(ProtocolType::Ttk, ConnectionType::Rfc2217)
+            .address("127.0.0.1")
+            .port(port)
+            .baudrate(115200)
+            .serial(SerialConfig {
+                parity: Some(SerialParity::Even),
+                ..Default::default()
+            })
+            .serial_number("1")
+            .build()
+            .unwrap();
+        let mut connection = Rfc2217Connection::new(config);
+        assert!(connection.connect().await.unwrap());
+
+        connection.write(&[0x02, IAC, 0x03]).await.unwrap();
+        assert_eq!(connection.read(Some(2000)).await.unwrap(), vec![IAC, 0x01]);
+        server.await.unwrap();
+
+        let error = connection.read(Some(2000)).await.unwrap_err();
+        assert_eq!(error.to_string(), "Serial server closed the connection");
+        assert!(!connection.is_connected());
+    }
+}
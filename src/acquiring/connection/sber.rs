@@ -0,0 +1,57 @@
+use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::protocol::sber::{pilot_path, work_dir};
+use crate::acquiring::types::ConnectionConfig;
+
+/// Placeholder connection for the Sberbank UPOS backend. sb_pilot talks to the
+/// terminal itself, so this only checks that the executable and its working
+/// directory are in place.
+pub struct SberConnection {
+    config: ConnectionConfig,
+    connected: bool,
+}
+
+impl SberConnection {
+    pub fn new(config: ConnectionConfig) -> Self {
+        Self {
+            config,
+            connected: false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BaseConnection for SberConnection {
+    fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let pilot = pilot_path(&self.config);
+        if pilot.components().count() > 1 && !pilot.is_file() {
+            return Err(format!("sb_pilot not found at {}", pilot.display()).into());
+        }
+        let dir = work_dir(&self.config);
+        if !dir.is_dir() {
+            return Err(format!("sb_pilot working directory {} does not exist", dir.display()).into());
+        }
+        self.connected = true;
+        Ok(true)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connected = false;
+        Ok(())
+    }
+
+    async fn write(&mut self, _data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Err("write is not supported for sber protocol".into())
+    }
+
+    async fn read(&mut self, _timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Err("read is not supported for sber protocol".into())
+    }
+}
@@ -0,0 +1,178 @@
+use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::types::ConnectionConfig;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio::time::{timeout, Duration};
+
+/// `BaseConnection` over any byte stream: Unix sockets, SSH tunnels,
+/// `tokio::io::duplex` pipes in tests, vendor bridges. The stream is opened
+/// by the caller; once disconnected it is gone until another is attached.
+pub struct StreamConnection<S> {
+    config: ConnectionConfig,
+    // Only ever accessed through `&mut self`; the mutex makes the connection
+    // `Sync` for streams that are merely `Send`.
+    stream: Option<Mutex<S>>,
+    connected: bool,
+}
+
+impl<S> StreamConnection<S> {
+    pub fn new(config: ConnectionConfig, stream: S) -> Self {
+        Self {
+            config,
+            stream: Some(Mutex::new(stream)),
+            connected: false,
+        }
+    }
+
+    /// A connection without a stream yet, see `attach`.
+    pub fn detached(config: ConnectionConfig) -> Self {
+        Self {
+            config,
+            stream: None,
+            connected: false,
+        }
+    }
+
+    /// Replaces the stream and marks the connection as connected.
+    pub fn attach(&mut self, stream: S) {
+        self.stream = Some(Mutex::new(stream));
+        self.connected = true;
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut S> {
+        self.stream.as_mut().map(Mutex::get_mut)
+    }
+
+    fn connected_stream(&mut self) -> Result<&mut S, Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+        self.get_mut().ok_or_else(|| "Not connected".into())
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> BaseConnection for StreamConnection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.stream.is_none() {
+            return Err("Stream is closed".into());
+        }
+        self.connected = true;
+        Ok(true)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connected = false;
+        if let Some(stream) = self.stream.take() {
+            stream.into_inner().shutdown().await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let stream = self.connected_stream()?;
+        stream.write_all(data).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let stream = self.connected_stream()?;
+
+        let mut buffer = vec![0u8; 4096];
+        let read_future = stream.read(&mut buffer);
+
+        let result = if let Some(timeout_ms) = timeout_ms {
+            timeout(Duration::from_millis(timeout_ms as u64), read_future).await?
+        } else {
+            read_future.await
+        };
+
+        let n = result?;
+        buffer.truncate(n);
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::types::ConnectionConfigBuilder;
+    use tokio::io::{duplex, DuplexStream};
+
+    async fn connected() -> (StreamConnection<DuplexStream>, DuplexStream) {
+        let (local, remote) = duplex(64);
+        let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", 1).build().unwrap();
+        let mut connection = StreamConnection::new(config, local);
+        assert!(connection.connect().await.unwrap());
+        (connection, remote)
+    }
+
+    #[tokio::test]
+    async fn exchanges_data() {
+        let (mut connection, mut remote) = connected().await;
+
+        connection.write(b"ping").await.unwrap();
+        let mut buffer = [0u8; 4];
+        remote.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(&buffer, b"ping");
+
+        remote.write_all(b"pong").await.unwrap();
+        assert_eq!(connection.read(Some(1000)).await.unwrap(), b"pong");
+    }
+
+    #[tokio::test]
+    async fn read_timeout() {
+        let (mut connection, _remote) = connected().await;
+
+        let started = tokio::time::Instant::now();
+        assert!(connection.read(Some(50)).await.is_err());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(connection.is_connected());
+    }
+
+    #[tokio::test]
+    async fn eof_reads_empty() {
+        let (mut connection, remote) = connected().await;
+        drop(remote);
+
+        assert!(connection.read(Some(1000)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn disconnect_closes_the_stream() {
+        let (mut connection, mut remote) = connected().await;
+
+        connection.disconnect().await.unwrap();
+        assert!(!connection.is_connected());
+        let mut buffer = Vec::new();
+        assert_eq!(remote.read_to_end(&mut buffer).await.unwrap(), 0);
+
+        assert_eq!(connection.write(b"x").await.unwrap_err().to_string(), "Not connected");
+        assert_eq!(connection.read(None).await.unwrap_err().to_string(), "Not connected");
+        assert_eq!(connection.connect().await.unwrap_err().to_string(), "Stream is closed");
+    }
+
+    #[tokio::test]
+    async fn attach_replaces_the_stream() {
+        let (mut connection, _old) = connected().await;
+        connection.disconnect().await.unwrap();
+
+        let (local, mut remote) = duplex(64);
+        connection.attach(local);
+        assert!(connection.is_connected());
+        remote.write_all(b"new").await.unwrap();
+        assert_eq!(connection.read(Some(1000)).await.unwrap(), b"new");
+    }
+}
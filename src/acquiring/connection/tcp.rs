@@ -1,22 +1,79 @@
-use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::connection::tls::{client_config, server_name};
+use crate::acquiring::connection::{BaseConnection, StreamConnection};
 use crate::acquiring::types::ConnectionConfig;
+use std::io;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
 use tokio::time::{timeout, Duration};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// A plain socket, or one wrapped in TLS when `ConnectionConfig::tls` is set.
+pub enum TcpTransport {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl TcpTransport {
+    pub fn socket(&self) -> &TcpStream {
+        match self {
+            Self::Plain(stream) => stream,
+            Self::Tls(stream) => stream.get_ref().0,
+        }
+    }
+}
+
+impl AsyncRead for TcpTransport {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for TcpTransport {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
 
 pub struct TcpConnection {
-    config: ConnectionConfig,
-    stream: Option<Arc<Mutex<TcpStream>>>,
-    connected: bool,
+    inner: StreamConnection<TcpTransport>,
 }
 
 impl TcpConnection {
     pub fn new(config: ConnectionConfig) -> Self {
         Self {
-            config,
-            stream: None,
-            connected: false,
+            inner: StreamConnection::detached(config),
         }
     }
 }
@@ -24,79 +81,66 @@ impl TcpConnection {
 #[async_trait::async_trait]
 impl BaseConnection for TcpConnection {
     fn config(&self) -> &ConnectionConfig {
-        &self.config
+        self.inner.config()
     }
 
     fn is_connected(&self) -> bool {
-        self.connected
+        self.inner.is_connected()
     }
 
     async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
-        let port = self.config.port.ok_or("Port is required for TCP connection")?;
-        let address = self
-            .config
+        let config = self.inner.config();
+        let port = config.port.ok_or("Port is required for TCP connection")?;
+        let address = config
             .address
             .as_ref()
             .ok_or("Address is required for TCP connection")?;
 
         let addr = format!("{}:{}", address, port);
         let stream = TcpStream::connect(&addr).await?;
-        self.stream = Some(Arc::new(Mutex::new(stream)));
-        self.connected = true;
+        let stream = match &config.tls {
+            Some(tls) => {
+                let connector = TlsConnector::from(Arc::new(client_config(tls)?));
+                let name = server_name(tls.server_name.as_deref().unwrap_or(address))?;
+                let stream = connector
+                    .connect(name, stream)
+                    .await
+                    .map_err(|e| format!("TLS handshake with {} failed: {}", addr, e))?;
+                TcpTransport::Tls(Box::new(stream))
+            }
+            None => TcpTransport::Plain(stream),
+        };
+        self.inner.attach(stream);
         Ok(true)
     }
 
     async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(stream) = &self.stream {
-            let mut stream = stream.lock().await;
-            use tokio::io::AsyncWriteExt;
-            stream.shutdown().await?;
-        }
-        self.stream = None;
-        self.connected = false;
-        Ok(())
+        self.inner.disconnect().await
     }
 
     async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-        if !self.connected {
-            return Err("Not connected".into());
-        }
-
-        if let Some(stream) = &self.stream {
-            let mut stream = stream.lock().await;
-            use tokio::io::AsyncWriteExt;
-            stream.write_all(data).await?;
-            stream.flush().await?;
-            Ok(())
-        } else {
-            Err("Not connected".into())
-        }
+        self.inner.write(data).await
     }
 
     async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        if !self.connected {
+        self.inner.read(timeout_ms).await
+    }
+
+    /// Peeks without consuming data: a closed or reset socket shows up as
+    /// EOF or an error, an idle one as a pending read.
+    async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.inner.is_connected() {
             return Err("Not connected".into());
         }
-
-        if let Some(stream) = &self.stream {
-            let mut stream = stream.lock().await;
-            use tokio::io::AsyncReadExt;
-
-            let mut buffer = vec![0u8; 4096];
-            let read_future = stream.read(&mut buffer);
-
-            let result = if let Some(timeout_ms) = timeout_ms {
-                timeout(Duration::from_millis(timeout_ms as u64), read_future).await?
-            } else {
-                read_future.await
-            };
-
-            let n = result?;
-            buffer.truncate(n);
-            Ok(buffer)
-        } else {
-            Err("Not connected".into())
+        let stream = self.inner.get_mut().ok_or("Not connected")?.socket();
+        if let Some(error) = stream.take_error()? {
+            return Err(error.into());
+        }
+        let mut buffer = [0u8; 1];
+        match timeout(Duration::ZERO, stream.peek(&mut buffer)).await {
+            Ok(Ok(0)) => Err("Connection closed by terminal".into()),
+            Ok(Err(e)) => Err(e.into()),
+            _ => Ok(()),
         }
     }
 }
-
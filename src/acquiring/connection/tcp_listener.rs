@@ -0,0 +1,340 @@
+use crate::acquiring::connection::{BaseConnection, StreamConnection};
+use crate::acquiring::types::ConnectionConfig;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::{lookup_host, TcpListener, TcpStream};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+const DEFAULT_TIMEOUT_MS: u32 = 30000;
+const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0";
+
+type PeerStream = Arc<Mutex<StreamConnection<TcpStream>>>;
+type Peer = Option<(SocketAddr, PeerStream)>;
+
+/// Listens on `address:port` (all interfaces without `address`) for the
+/// terminal to connect to the POS. Connections from outside `allowed_peers`
+/// are closed right away. A new connection from the terminal replaces the
+/// current one.
+pub struct TcpListenerConnection {
+    config: ConnectionConfig,
+    local_addr: Option<SocketAddr>,
+    peer: Arc<watch::Sender<Peer>>,
+    accept_task: Option<JoinHandle<()>>,
+    connected: bool,
+}
+
+impl TcpListenerConnection {
+    pub fn new(config: ConnectionConfig) -> Self {
+        Self {
+            config,
+            local_addr: None,
+            peer: Arc::new(watch::Sender::new(None)),
+            accept_task: None,
+            connected: false,
+        }
+    }
+
+    /// Bound address, useful when listening on port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer.borrow().as_ref().map(|(addr, _)| *addr)
+    }
+
+    /// Resolves `allowed_peers`; empty means any peer.
+    async fn allowed_peers(&self) -> Result<Vec<IpAddr>, Box<dyn std::error::Error>> {
+        let mut allowed = Vec::new();
+        for peer in self.config.allowed_peers.iter().flatten() {
+            if let Ok(ip) = peer.parse::<IpAddr>() {
+                allowed.push(ip.to_canonical());
+                continue;
+            }
+            let resolved = lookup_host((peer.as_str(), 0))
+                .await
+                .map_err(|e| format!("Failed to resolve terminal address {}: {}", peer, e))?;
+            allowed.extend(resolved.map(|addr| addr.ip().to_canonical()));
+        }
+        Ok(allowed)
+    }
+
+    /// Waits up to `timeout_ms` for the terminal to connect.
+    async fn wait_for_peer(&self, timeout_ms: u32) -> Result<PeerStream, Box<dyn std::error::Error>> {
+        let mut peer = self.peer.subscribe();
+        let wait = peer.wait_for(|peer| peer.is_some());
+        match timeout(Duration::from_millis(timeout_ms as u64), wait).await {
+            Ok(Ok(peer)) => Ok(peer.clone().ok_or("Terminal is not connected")?.1),
+            Ok(Err(_)) => Err("Not connected".into()),
+            Err(_) => Err(format!("Terminal did not connect within {} ms", timeout_ms).into()),
+        }
+    }
+
+    /// Waits for the accept task to finish, so the port is free again.
+    async fn stop_accepting(&mut self) {
+        if let Some(task) = self.accept_task.take() {
+            task.abort();
+            let _ = task.await;
+        }
+    }
+
+    /// Forgets `stream` unless the terminal has already reconnected.
+    fn drop_peer(&self, stream: &PeerStream) {
+        self.peer.send_if_modified(|peer| match peer {
+            Some((_, current)) if Arc::ptr_eq(current, stream) => {
+                *peer = None;
+                true
+            }
+            _ => false,
+        });
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    config: ConnectionConfig,
+    allowed: Vec<IpAddr>,
+    peer: Arc<watch::Sender<Peer>>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(_) => {
+                // Out of descriptors and the like; don't spin.
+                sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        // IPv4 peers show up as ::ffff:a.b.c.d on a dual-stack socket.
+        if !allowed.is_empty() && !allowed.contains(&addr.ip().to_canonical()) {
+            continue;
+        }
+        let _ = stream.set_nodelay(true);
+        let mut stream = StreamConnection::new(config.clone(), stream);
+        if stream.connect().await.is_ok() {
+            peer.send_replace(Some((addr, Arc::new(Mutex::new(stream)))));
+        }
+    }
+}
+
+impl Drop for TcpListenerConnection {
+    fn drop(&mut self) {
+        if let Some(task) = self.accept_task.take() {
+            task.abort();
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BaseConnection for TcpListenerConnection {
+    fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let port = self.config.port.ok_or("Port is required for TCP listener")?;
+        let address = self
+            .config
+            .address
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BIND_ADDRESS.to_string());
+        let allowed = self.allowed_peers().await?;
+        self.stop_accepting().await;
+        self.peer.send_replace(None);
+        let listener = TcpListener::bind((address.as_str(), port))
+            .await
+            .map_err(|e| format!("Failed to listen on {}:{}: {}", address, port, e))?;
+        self.local_addr = Some(listener.local_addr()?);
+        self.accept_task = Some(tokio::spawn(accept_loop(
+            listener,
+            self.config.clone(),
+            allowed,
+            Arc::clone(&self.peer),
+        )));
+        self.connected = true;
+        Ok(true)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.stop_accepting().await;
+        if let Some((_, stream)) = self.peer.send_replace(None) {
+            let _ = stream.lock().await.disconnect().await;
+        }
+        self.local_addr = None;
+        self.connected = false;
+        Ok(())
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+
+        let timeout_ms = self.config.timeout.unwrap_or(DEFAULT_TIMEOUT_MS);
+        let stream = self.wait_for_peer(timeout_ms).await?;
+        let result = stream.lock().await.write(data).await;
+        if result.is_err() {
+            self.drop_peer(&stream);
+        }
+        result
+    }
+
+    /// Waiting for the terminal to connect and for its data share one
+    /// `timeout_ms` deadline.
+    async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected".into());
+        }
+
+        let started = Instant::now();
+        let stream = self
+            .wait_for_peer(timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS))
+            .await?;
+        let remaining_ms =
+            timeout_ms.map(|ms| ms.saturating_sub(started.elapsed().as_millis() as u32));
+        let result = stream.lock().await.read(remaining_ms).await;
+        if !matches!(&result, Ok(buffer) if !buffer.is_empty()) {
+            self.drop_peer(&stream);
+        }
+        result
+    }
+
+    /// Only the listener has to be alive; a terminal that went away is
+    /// forgotten until it connects again.
+    async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected || self.accept_task.as_ref().is_none_or(|task| task.is_finished()) {
+            return Err("Not listening".into());
+        }
+        let Some((_, stream)) = self.peer.borrow().clone() else {
+            return Ok(());
+        };
+        let closed = {
+            let mut stream = stream.lock().await;
+            let Some(socket) = stream.get_mut() else {
+                return Ok(());
+            };
+            let mut buffer = [0u8; 1];
+            matches!(socket.take_error(), Ok(Some(_)) | Err(_))
+                || matches!(
+                    timeout(Duration::ZERO, socket.peek(&mut buffer)).await,
+                    Ok(Ok(0)) | Ok(Err(_))
+                )
+        };
+        if closed {
+            self.drop_peer(&stream);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::simulator::{Scenario, TtkSimulator};
+    use crate::acquiring::types::{ConnectionConfigBuilder, ConnectionType, ProtocolType};
+    use crate::acquiring::Terminal;
+    use tokio::io::AsyncReadExt;
+
+    async fn listening() -> TcpListenerConnection {
+        listening_for(None).await
+    }
+
+    async fn listening_for(allowed_peers: Option<&[&str]>) -> TcpListenerConnection {
+        let mut builder = ConnectionConfigBuilder::new(ProtocolType::Ttk, ConnectionType::Listener)
+            .address("127.0.0.1")
+            .port(0)
+            .serial_number("1")
+            .timeout(2000);
+        if let Some(peers) = allowed_peers {
+            builder = builder.allowed_peers(peers);
+        }
+        let config = builder.build().unwrap();
+        let mut connection = TcpListenerConnection::new(config);
+        connection.connect().await.unwrap();
+        connection
+    }
+
+    #[tokio::test]
+    async fn binds_the_configured_address() {
+        let connection = listening().await;
+        let addr = connection.local_addr().unwrap();
+        assert_eq!(addr.ip().to_string(), "127.0.0.1");
+        assert_ne!(addr.port(), 0);
+    }
+
+    #[tokio::test]
+    async fn peers_outside_allowed_peers_are_dropped() {
+        let mut connection = listening_for(Some(&["10.0.0.7"])).await;
+        let addr = connection.local_addr().unwrap();
+
+        let mut stranger = TcpStream::connect(addr).await.unwrap();
+        let mut buffer = [0u8; 1];
+        let closed = timeout(Duration::from_secs(2), stranger.read(&mut buffer)).await;
+        assert!(matches!(closed, Ok(Ok(0)) | Ok(Err(_))), "{:?}", closed);
+        assert_eq!(connection.peer_addr(), None);
+        assert_eq!(
+            connection.read(Some(100)).await.unwrap_err().to_string(),
+            "Terminal did not connect within 100 ms"
+        );
+    }
+
+    #[tokio::test]
+    async fn allowed_peers_are_accepted() {
+        let connection = listening_for(Some(&["localhost", "127.0.0.1"])).await;
+        let addr = connection.local_addr().unwrap();
+
+        let _terminal = TcpStream::connect(addr).await.unwrap();
+        let peer = connection.wait_for_peer(2000).await;
+        assert!(peer.is_ok());
+        assert_eq!(connection.peer_addr().unwrap().ip().to_string(), "127.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn read_timeout_covers_waiting_for_the_terminal() {
+        let mut connection = listening().await;
+        let addr = connection.local_addr().unwrap();
+        let _silent = tokio::spawn(async move {
+            sleep(Duration::from_millis(250)).await;
+            let stream = TcpStream::connect(addr).await.unwrap();
+            sleep(Duration::from_secs(5)).await;
+            drop(stream);
+        });
+
+        let started = Instant::now();
+        assert!(connection.read(Some(400)).await.is_err());
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(390), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(600), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn payment_from_a_terminal_that_dials_in() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = ConnectionConfigBuilder::new(ProtocolType::Ttk, ConnectionType::Listener)
+            .address("127.0.0.1")
+            .port(port)
+            .serial_number("1")
+            .timeout(2000)
+            .build()
+            .unwrap();
+        let mut terminal = Terminal::new(config);
+        terminal.connect().await.unwrap();
+
+        tokio::spawn(async move {
+            let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            let _ = TtkSimulator::new(Scenario::Approve).serve(stream).await;
+        });
+        let response = terminal.payment(1000, None).await.unwrap();
+        assert!(response.success);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::acquiring::types::TlsConfig;
+use sha2::{Digest, Sha256};
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::client::WebPkiServerVerifier;
+use tokio_rustls::rustls::crypto::{
+    ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{
+    ClientConfig, DigitallySignedStruct, Error as TlsError, RootCertStore, ServerConfig,
+    SignatureScheme,
+};
+
+pub fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid certificate file {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates in {}", path));
+    }
+    Ok(certs)
+}
+
+pub fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("Invalid key file {}: {}", path, e))?
+        .ok_or_else(|| format!("No private key in {}", path))
+}
+
+fn load_roots(path: &str) -> Result<Arc<RootCertStore>, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .map_err(|e| format!("Invalid CA certificate in {}: {}", path, e))?;
+    }
+    Ok(Arc::new(roots))
+}
+
+/// SHA-256 of the DER certificate as uppercase hex, colon-separated the way
+/// `openssl x509 -fingerprint -sha256` prints it.
+pub fn cert_fingerprint(cert: &[u8]) -> String {
+    Sha256::digest(cert)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+pub(crate) fn parse_fingerprint(pin: &str) -> Result<[u8; 32], String> {
+    let hex: String = pin.chars().filter(|c| *c != ':' && !c.is_whitespace()).collect();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(format!("Invalid SHA-256 certificate fingerprint: {}", pin));
+    }
+    let mut fingerprint = [0u8; 32];
+    for (i, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("Invalid SHA-256 certificate fingerprint: {}", pin))?;
+    }
+    Ok(fingerprint)
+}
+
+/// Accepts only pinned server certificates. With a CA, the chain and name are
+/// checked as well; without one, the pin replaces chain validation.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    pins: Vec<[u8; 32]>,
+    chain: Option<Arc<WebPkiServerVerifier>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        if let Some(chain) = &self.chain {
+            chain.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        let fingerprint: [u8; 32] = Sha256::digest(end_entity.as_ref()).into();
+        if self.pins.contains(&fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(TlsError::General(format!(
+                "Server certificate {} is not pinned",
+                cert_fingerprint(end_entity.as_ref())
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+pub fn client_config(settings: &TlsConfig) -> Result<ClientConfig, String> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+
+    let chain = match &settings.ca_cert {
+        Some(path) => Some(
+            WebPkiServerVerifier::builder_with_provider(load_roots(path)?, Arc::clone(&provider))
+                .build()
+                .map_err(|e| format!("Invalid CA certificate in {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    let pins = settings
+        .pinned_certs
+        .iter()
+        .map(|pin| parse_fingerprint(pin))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let builder = match (chain, pins.is_empty()) {
+        (Some(chain), true) => builder.with_webpki_verifier(chain),
+        (chain, false) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier {
+                pins,
+                chain,
+                provider,
+            })),
+        (None, true) => return Err("TLS requires ca_cert or pinned_certs".to_string()),
+    };
+
+    match (&settings.client_cert, &settings.client_key) {
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_private_key(key)?)
+            .map_err(|e| format!("Invalid client certificate: {}", e)),
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err("TLS client_cert and client_key must be set together".to_string()),
+    }
+}
+
+/// Server side, for simulators. With `client_ca`, clients must present a
+/// certificate signed by it.
+pub fn server_config(
+    cert: &str,
+    key: &str,
+    client_ca: Option<&str>,
+) -> Result<ServerConfig, String> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match client_ca {
+        Some(path) => builder.with_client_cert_verifier(
+            WebPkiClientVerifier::builder_with_provider(load_roots(path)?, provider)
+                .build()
+                .map_err(|e| format!("Invalid client CA certificate in {}: {}", path, e))?,
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(load_certs(cert)?, load_private_key(key)?)
+        .map_err(|e| format!("Invalid server certificate: {}", e))
+}
+
+pub fn server_name(name: &str) -> Result<ServerName<'static>, String> {
+    ServerName::try_from(name.to_string()).map_err(|_| format!("Invalid TLS server name: {}", name))
+}
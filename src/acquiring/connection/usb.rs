@@ -1,22 +1,191 @@
-use crate::acquiring::connection::BaseConnection;
-use crate::acquiring::types::ConnectionConfig;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio::time::{timeout, Duration};
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use crate::acquiring::connection::{BaseConnection, StreamConnection};
+use crate::acquiring::types::{ConnectionConfig, SerialConfig, SerialFlowControl, SerialParity};
+use tokio_serial::{
+    DataBits, FlowControl, Parity, SerialPort, SerialPortBuilderExt, SerialPortInfo,
+    SerialPortType, SerialStream, StopBits,
+};
+
+const DEFAULT_BAUD_RATE: u32 = 9600;
+
+/// A serial port found by `list_serial_terminals`. USB attributes are only
+/// filled in for USB devices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialPortDescription {
+    pub path: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+impl SerialPortDescription {
+    pub fn matches(&self, settings: &SerialConfig) -> bool {
+        settings.usb_vid.is_none_or(|vid| self.vid == Some(vid))
+            && settings.usb_pid.is_none_or(|pid| self.pid == Some(pid))
+            && settings
+                .usb_serial
+                .as_ref()
+                .is_none_or(|serial| self.serial_number.as_ref() == Some(serial))
+    }
+}
+
+pub fn list_serial_terminals() -> Result<Vec<SerialPortDescription>, Box<dyn std::error::Error>> {
+    let mut ports: Vec<SerialPortDescription> = tokio_serial::available_ports()?
+        .into_iter()
+        .map(describe_port)
+        .collect();
+    ports.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(ports)
+}
+
+fn describe_port(port: SerialPortInfo) -> SerialPortDescription {
+    match port.port_type {
+        SerialPortType::UsbPort(usb) => SerialPortDescription {
+            path: port.port_name,
+            vid: Some(usb.vid),
+            pid: Some(usb.pid),
+            serial_number: usb.serial_number,
+            manufacturer: usb.manufacturer,
+            product: usb.product,
+        },
+        _ => SerialPortDescription {
+            path: port.port_name,
+            vid: None,
+            pid: None,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+        },
+    }
+}
+
+/// Finds the port of a USB device by the VID/PID/serial number in `settings`.
+pub fn find_serial_terminal(
+    settings: &SerialConfig,
+) -> Result<SerialPortDescription, Box<dyn std::error::Error>> {
+    Ok(select_serial_port(tokio_serial::available_ports()?, settings)?)
+}
+
+/// Picks the only USB port in `ports` matching the VID/PID/serial number in
+/// `settings`. Non-USB ports never match.
+pub fn select_serial_port(
+    ports: Vec<SerialPortInfo>,
+    settings: &SerialConfig,
+) -> Result<SerialPortDescription, String> {
+    if settings.usb_vid.is_none() && settings.usb_pid.is_none() && settings.usb_serial.is_none() {
+        return Err("USB vid, pid or serial number is required to find a serial port".to_string());
+    }
+    let mut matching: Vec<SerialPortDescription> = ports
+        .into_iter()
+        .map(describe_port)
+        .filter(|port| port.vid.is_some() && port.matches(settings))
+        .collect();
+    matching.sort_by(|a, b| a.path.cmp(&b.path));
+    match matching.len() {
+        0 => Err(format!(
+            "No serial port found for USB device {}",
+            describe_usb_filter(settings)
+        )),
+        1 => Ok(matching.remove(0)),
+        _ => Err(format!(
+            "Several serial ports match USB device {}: {}",
+            describe_usb_filter(settings),
+            matching
+                .iter()
+                .map(|port| port.path.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn describe_usb_filter(settings: &SerialConfig) -> String {
+    let mut parts = Vec::new();
+    if let Some(vid) = settings.usb_vid {
+        parts.push(format!("vid={:04x}", vid));
+    }
+    if let Some(pid) = settings.usb_pid {
+        parts.push(format!("pid={:04x}", pid));
+    }
+    if let Some(serial) = &settings.usb_serial {
+        parts.push(format!("serial={}", serial));
+    }
+    parts.join(" ")
+}
+
+/// Opens `path` with the baud rate and line settings from `config`.
+pub fn open_serial(
+    path: &str,
+    config: &ConnectionConfig,
+) -> Result<SerialStream, Box<dyn std::error::Error>> {
+    let settings = config.serial.clone().unwrap_or_default();
+    let baud_rate = config.baudrate.unwrap_or(DEFAULT_BAUD_RATE);
+
+    let data_bits = match settings.data_bits.unwrap_or(8) {
+        5 => DataBits::Five,
+        6 => DataBits::Six,
+        7 => DataBits::Seven,
+        8 => DataBits::Eight,
+        other => return Err(format!("Invalid data bits: {}", other).into()),
+    };
+    let stop_bits = match settings.stop_bits.unwrap_or(1) {
+        1 => StopBits::One,
+        2 => StopBits::Two,
+        other => return Err(format!("Invalid stop bits: {}", other).into()),
+    };
+    let parity = match settings.parity.unwrap_or(SerialParity::None) {
+        SerialParity::None => Parity::None,
+        SerialParity::Odd => Parity::Odd,
+        SerialParity::Even => Parity::Even,
+    };
+    let flow_control = match settings.flow_control.unwrap_or(SerialFlowControl::None) {
+        SerialFlowControl::None => FlowControl::None,
+        SerialFlowControl::Software => FlowControl::Software,
+        SerialFlowControl::Hardware => FlowControl::Hardware,
+    };
+
+    let mut builder = tokio_serial::new(path, baud_rate)
+        .data_bits(data_bits)
+        .stop_bits(stop_bits)
+        .parity(parity)
+        .flow_control(flow_control);
+    if let Some(dtr) = settings.dtr {
+        builder = builder.dtr_on_open(dtr);
+    }
+
+    #[allow(unused_mut)]
+    let mut port = builder
+        .open_native_async()
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+
+    #[cfg(unix)]
+    port.set_exclusive(settings.exclusive.unwrap_or(true))?;
+    if let Some(rts) = settings.rts {
+        port.write_request_to_send(rts)?;
+    }
+    Ok(port)
+}
 
 pub struct UsbConnection {
-    config: ConnectionConfig,
-    port: Option<Arc<Mutex<SerialStream>>>,
-    connected: bool,
+    inner: StreamConnection<SerialStream>,
 }
 
 impl UsbConnection {
     pub fn new(config: ConnectionConfig) -> Self {
         Self {
-            config,
-            port: None,
-            connected: false,
+            inner: StreamConnection::detached(config),
+        }
+    }
+
+    fn port_path(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let config = self.inner.config();
+        if let Some(path) = config.ncom.as_ref().or(config.address.as_ref()) {
+            return Ok(path.clone());
+        }
+        match &config.serial {
+            Some(settings) => Ok(find_serial_terminal(settings)?.path),
+            None => Err("USB path or serialNumber must be provided in config".into()),
         }
     }
 }
@@ -24,74 +193,110 @@ impl UsbConnection {
 #[async_trait::async_trait]
 impl BaseConnection for UsbConnection {
     fn config(&self) -> &ConnectionConfig {
-        &self.config
+        self.inner.config()
     }
 
     fn is_connected(&self) -> bool {
-        self.connected
+        self.inner.is_connected()
     }
 
     async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
-        let path = self
-            .config
-            .ncom
-            .as_ref()
-            .or_else(|| self.config.address.as_ref())
-            .ok_or("USB path or serialNumber must be provided in config")?;
-
-        let baud_rate = self.config.baudrate.unwrap_or(9600);
-        let port = tokio_serial::new(path, baud_rate).open_native_async()?;
-        self.port = Some(Arc::new(Mutex::new(port)));
-        self.connected = true;
+        let path = self.port_path()?;
+        let port = open_serial(&path, self.inner.config())?;
+        self.inner.attach(port);
         Ok(true)
     }
 
     async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.port = None;
-        self.connected = false;
-        Ok(())
+        self.inner.disconnect().await
     }
 
     async fn write(&mut self, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-        if !self.connected {
+        self.inner.write(data).await
+    }
+
+    async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.inner.read(timeout_ms).await
+    }
+
+    /// Fails once the device is unplugged, even if it has since come back
+    /// under the same name.
+    async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.inner.is_connected() {
             return Err("Not connected".into());
         }
+        let port = self.inner.get_mut().ok_or("Not connected")?;
+        port.bytes_to_read()?;
+        Ok(())
+    }
+}
 
-        if let Some(port) = &self.port {
-            let mut port = port.lock().await;
-            use tokio::io::AsyncWriteExt;
-            port.write_all(data).await?;
-            port.flush().await?;
-            Ok(())
-        } else {
-            Err("Not connected".into())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_serial::UsbPortInfo;
+
+    fn usb_port(path: &str, vid: u16, pid: u16, serial: Option<&str>) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: path.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: serial.map(str::to_string),
+                manufacturer: Some("Ingenico".to_string()),
+                product: None,
+            }),
         }
     }
 
-    async fn read(&mut self, timeout_ms: Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        if !self.connected {
-            return Err("Not connected".into());
+    fn ports() -> Vec<SerialPortInfo> {
+        vec![
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".to_string(),
+                port_type: SerialPortType::PciPort,
+            },
+            usb_port("/dev/ttyACM1", 0x0b00, 0x0084, Some("PP123")),
+            usb_port("/dev/ttyACM0", 0x0b00, 0x0084, Some("PP456")),
+            usb_port("/dev/ttyUSB0", 0x067b, 0x2303, None),
+        ]
+    }
+
+    fn filter(vid: Option<u16>, pid: Option<u16>, serial: Option<&str>) -> SerialConfig {
+        SerialConfig {
+            usb_vid: vid,
+            usb_pid: pid,
+            usb_serial: serial.map(str::to_string),
+            ..Default::default()
         }
+    }
 
-        if let Some(port) = &self.port {
-            let mut port = port.lock().await;
-            use tokio::io::AsyncReadExt;
+    #[test]
+    fn a_unique_match_is_selected() {
+        let port = select_serial_port(ports(), &filter(None, None, Some("PP456"))).unwrap();
+        assert_eq!(port.path, "/dev/ttyACM0");
+        assert_eq!(port.vid, Some(0x0b00));
+        assert_eq!(port.manufacturer.as_deref(), Some("Ingenico"));
 
-            let mut buffer = vec![0u8; 4096];
-            let read_future = port.read(&mut buffer);
+        let port = select_serial_port(ports(), &filter(Some(0x067b), None, None)).unwrap();
+        assert_eq!(port.path, "/dev/ttyUSB0");
+    }
 
-            let result = if let Some(timeout_ms) = timeout_ms {
-                timeout(Duration::from_millis(timeout_ms as u64), read_future).await?
-            } else {
-                read_future.await
-            };
+    #[test]
+    fn every_given_attribute_must_match() {
+        let error =
+            select_serial_port(ports(), &filter(Some(0x067b), None, Some("PP123"))).unwrap_err();
+        assert_eq!(error, "No serial port found for USB device vid=067b serial=PP123");
 
-            let n = result?;
-            buffer.truncate(n);
-            Ok(buffer)
-        } else {
-            Err("Not connected".into())
-        }
+        let error = select_serial_port(ports(), &filter(None, Some(0x0084), None)).unwrap_err();
+        assert_eq!(
+            error,
+            "Several serial ports match USB device pid=0084: /dev/ttyACM0, /dev/ttyACM1"
+        );
     }
-}
 
+    #[test]
+    fn an_empty_filter_is_rejected() {
+        let error = select_serial_port(ports(), &filter(None, None, None)).unwrap_err();
+        assert_eq!(error, "USB vid, pid or serial number is required to find a serial port");
+    }
+}
@@ -0,0 +1,180 @@
+use crate::acquiring::commands::base::CommandContext;
+use crate::acquiring::commands::PingCommand;
+use crate::acquiring::connection::{BaseConnection, StreamConnection};
+use crate::acquiring::types::ConnectionConfigBuilder;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::{timeout, Duration};
+
+const DEFAULT_CONNECT_TIMEOUT_MS: u32 = 300;
+const DEFAULT_RESPONSE_TIMEOUT_MS: u32 = 5000;
+const DEFAULT_CONCURRENCY: usize = 64;
+/// A /16 at most.
+const MAX_HOSTS: u32 = 65536;
+
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// IPv4 network in CIDR notation (`192.168.1.0/24`) or a single address.
+    pub subnet: String,
+    pub ports: RangeInclusive<u16>,
+    /// ECR number sent in the probe.
+    pub serial_number: String,
+    pub connect_timeout: u32,
+    pub response_timeout: u32,
+    /// Candidates probed at the same time.
+    pub concurrency: usize,
+}
+
+impl DiscoveryOptions {
+    pub fn new(subnet: &str, ports: RangeInclusive<u16>) -> Self {
+        Self {
+            subnet: subnet.to_string(),
+            ports,
+            serial_number: "1".to_string(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT_MS,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT_MS,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+}
+
+/// A terminal that answered the probe, with the Terminal ID it reported about
+/// itself. TTK has no request for the hardware serial number, and the ECR
+/// number in the response is only the probe's own echoed back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredTerminal {
+    pub address: String,
+    pub port: u16,
+    pub terminal_id: Option<String>,
+}
+
+/// Host addresses of an IPv4 network, without the network and broadcast
+/// addresses for prefixes up to /30.
+pub fn subnet_hosts(subnet: &str) -> Result<Vec<Ipv4Addr>, String> {
+    let invalid = || format!("Invalid subnet: {}", subnet);
+    let (address, prefix) = match subnet.trim().split_once('/') {
+        Some((address, prefix)) => (address, prefix.parse::<u32>().map_err(|_| invalid())?),
+        None => (subnet.trim(), 32),
+    };
+    let address: Ipv4Addr = address.parse().map_err(|_| invalid())?;
+    if prefix > 32 {
+        return Err(invalid());
+    }
+
+    let size = 1u64 << (32 - prefix);
+    if size > MAX_HOSTS as u64 {
+        return Err(format!("Subnet {} is too large to scan", subnet));
+    }
+    let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+    let network = u32::from(address) & mask;
+    let hosts = (0..size as u32).map(|offset| network + offset);
+    Ok(if prefix <= 30 {
+        hosts.skip(1).take(size as usize - 2).map(Ipv4Addr::from).collect()
+    } else {
+        hosts.map(Ipv4Addr::from).collect()
+    })
+}
+
+/// Scans `options.subnet` on `options.ports` with an SRV test-server request
+/// and returns every terminal that answered, sorted by address.
+pub async fn discover_terminals(
+    options: &DiscoveryOptions,
+) -> Result<Vec<DiscoveredTerminal>, Box<dyn std::error::Error>> {
+    let hosts = subnet_hosts(&options.subnet)?;
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut probes = JoinSet::new();
+
+    for host in hosts {
+        for port in options.ports.clone() {
+            let permit = Arc::clone(&semaphore).acquire_owned().await?;
+            let options = options.clone();
+            probes.spawn(async move {
+                let result = probe(SocketAddr::from((host, port)), &options).await;
+                drop(permit);
+                result
+            });
+        }
+    }
+
+    let mut terminals = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        if let Ok(Some(terminal)) = result {
+            terminals.push(terminal);
+        }
+    }
+    terminals.sort_by_key(|terminal| (terminal.address.parse::<Ipv4Addr>().ok(), terminal.port));
+    Ok(terminals)
+}
+
+async fn probe(addr: SocketAddr, options: &DiscoveryOptions) -> Option<DiscoveredTerminal> {
+    let stream = timeout(
+        Duration::from_millis(options.connect_timeout as u64),
+        TcpStream::connect(addr),
+    )
+    .await
+    .ok()?
+    .ok()?;
+
+    let config = ConnectionConfigBuilder::ttk_tcp(&addr.ip().to_string(), addr.port())
+        .serial_number(&options.serial_number)
+        .timeout(options.response_timeout)
+        .build()
+        .ok()?;
+    let mut connection = StreamConnection::new(config, stream);
+    connection.connect().await.ok()?;
+    let connection: Arc<Mutex<Box<dyn BaseConnection>>> =
+        Arc::new(Mutex::new(Box::new(connection)));
+
+    let response = timeout(
+        Duration::from_millis(options.response_timeout as u64),
+        PingCommand::new().execute(CommandContext::new(Arc::clone(&connection))),
+    )
+    .await
+    .ok()?
+    .ok();
+    let _ = connection.lock().await.disconnect().await;
+
+    let data = response?.data?;
+    Some(DiscoveredTerminal {
+        address: addr.ip().to_string(),
+        port: addr.port(),
+        terminal_id: data.terminal_id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::simulator::{Scenario, TtkSimulator};
+
+    #[test]
+    fn subnet_hosts_skip_network_and_broadcast() {
+        let hosts = subnet_hosts("192.168.1.0/30").unwrap();
+        assert_eq!(hosts, vec![Ipv4Addr::new(192, 168, 1, 1), Ipv4Addr::new(192, 168, 1, 2)]);
+        assert_eq!(subnet_hosts("10.0.0.7").unwrap(), vec![Ipv4Addr::new(10, 0, 0, 7)]);
+        assert!(subnet_hosts("10.0.0.0/8").is_err());
+    }
+
+    #[tokio::test]
+    async fn reports_the_terminal_identity_not_the_echoed_ecr_number() {
+        let simulator = Arc::new(TtkSimulator::new(Scenario::Approve).with_terminal_id("10285694"));
+        let (addr, _) = simulator.listen_tcp("127.0.0.1:0").await.unwrap();
+
+        let mut options = DiscoveryOptions::new("127.0.0.1", addr.port()..=addr.port());
+        options.serial_number = "7".to_string();
+        let terminals = discover_terminals(&options).await.unwrap();
+
+        assert_eq!(
+            terminals,
+            vec![DiscoveredTerminal {
+                address: "127.0.0.1".to_string(),
+                port: addr.port(),
+                terminal_id: Some("10285694".to_string()),
+            }]
+        );
+    }
+}
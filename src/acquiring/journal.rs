@@ -0,0 +1,319 @@
+use crate::acquiring::connection::recording::mask_pans;
+use crate::acquiring::types::{ConnectionConfig, InpasFieldId, ProtocolType, TerminalResponse};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionState {
+    /// Recorded before the request is sent.
+    Pending,
+    Approved,
+    Declined,
+    /// No response; the outcome on the terminal is unknown.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateTransition {
+    pub state: TransactionState,
+    pub at: DateTime<Local>,
+}
+
+/// One command sent to a terminal, saved again on every state change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub ern: u64,
+    /// `payment`, `refund` or `totals`.
+    pub operation: String,
+    pub amount: Option<u64>,
+    pub currency: Option<String>,
+    pub order_id: Option<String>,
+    pub protocol: ProtocolType,
+    pub serial_number: String,
+    pub state: TransactionState,
+    pub transitions: Vec<StateTransition>,
+    pub started_at: DateTime<Local>,
+    pub finished_at: Option<DateTime<Local>>,
+    pub rrn: Option<String>,
+    pub response: Option<TerminalResponse>,
+    pub error: Option<String>,
+}
+
+impl TransactionRecord {
+    pub fn new(operation: &str, ern: u64, config: &ConnectionConfig) -> Self {
+        let now = Local::now();
+        Self {
+            ern,
+            operation: operation.to_string(),
+            amount: None,
+            currency: None,
+            order_id: None,
+            protocol: config.protocol,
+            serial_number: config.serial_number.clone(),
+            state: TransactionState::Pending,
+            transitions: vec![StateTransition {
+                state: TransactionState::Pending,
+                at: now,
+            }],
+            started_at: now,
+            finished_at: None,
+            rrn: None,
+            response: None,
+            error: None,
+        }
+    }
+
+    fn transition(&mut self, state: TransactionState) {
+        let now = Local::now();
+        self.state = state;
+        self.transitions.push(StateTransition { state, at: now });
+        self.finished_at = Some(now);
+    }
+
+    /// Moves to the final state for the outcome of the command. The response
+    /// is kept with its PAN masked, see `mask_response`.
+    pub fn finish(&mut self, result: &Result<TerminalResponse, String>) {
+        match result {
+            Ok(response) => {
+                self.rrn = response.data.as_ref().and_then(|data| data.rrn.clone());
+                let state = if response.success {
+                    TransactionState::Approved
+                } else {
+                    TransactionState::Declined
+                };
+                let mut response = response.clone();
+                mask_response(&mut response);
+                self.response = Some(response);
+                self.transition(state);
+            }
+            Err(e) => {
+                self.error = Some(e.clone());
+                self.transition(TransactionState::Failed);
+            }
+        }
+    }
+}
+
+/// Masks the PAN the terminal returned in full (TTK sends it from tag 0x89,
+/// Sber from the `e` file) the way session recordings do, including every
+/// copy of it in the other fields.
+pub fn mask_response(response: &mut TerminalResponse) {
+    let Some(data) = response.data.as_mut() else {
+        return;
+    };
+    // TTK and Sber name the raw field, Inpas numbers it.
+    let pan_keys = ["PAN", InpasFieldId::Pan.code()];
+    let mut pans: Vec<String> = data
+        .pan_masked
+        .iter()
+        .chain(pan_keys.iter().filter_map(|key| data.raw.get(*key)))
+        .filter(|pan| mask_pans(pan) != **pan)
+        .cloned()
+        .collect();
+    pans.sort();
+    pans.dedup();
+    if pans.is_empty() {
+        return;
+    }
+
+    let mask = |value: &mut String| {
+        for pan in &pans {
+            if value.contains(pan.as_str()) {
+                *value = value.replace(pan.as_str(), &mask_pans(pan));
+            }
+        }
+    };
+    for value in [&mut data.pan_masked, &mut data.receipt]
+        .into_iter()
+        .flatten()
+        .chain(data.raw.values_mut())
+        .chain(data.extras.iter_mut().flat_map(|extras| extras.values_mut()))
+    {
+        mask(value);
+    }
+}
+
+/// Filters for `TransactionStore::query`; unset fields match everything.
+/// `from` and `to` bound `started_at`, `to` exclusively.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionQuery {
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+    pub ern: Option<u64>,
+    pub rrn: Option<String>,
+    pub order_id: Option<String>,
+}
+
+impl TransactionQuery {
+    pub fn matches(&self, record: &TransactionRecord) -> bool {
+        self.from.is_none_or(|from| record.started_at >= from)
+            && self.to.is_none_or(|to| record.started_at < to)
+            && self.ern.is_none_or(|ern| record.ern == ern)
+            && self.rrn.as_ref().is_none_or(|rrn| record.rrn.as_ref() == Some(rrn))
+            && self
+                .order_id
+                .as_ref()
+                .is_none_or(|order_id| record.order_id.as_ref() == Some(order_id))
+    }
+}
+
+/// Where `Terminal` journals its commands.
+#[async_trait::async_trait]
+pub trait TransactionStore: Send + Sync {
+    /// Stores the current state of `record`, replacing earlier states of the
+    /// same ERN on the same terminal.
+    async fn save(&self, record: &TransactionRecord) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Matching records, oldest first.
+    async fn query(
+        &self,
+        query: &TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, Box<dyn std::error::Error>>;
+}
+
+/// Append-only JSON-lines file: every saved state is a line, so the file
+/// keeps the full history and queries return the latest state per terminal
+/// serial number and ERN. Terminals sharing a journal may reuse ERNs.
+pub struct JsonLinesStore {
+    path: PathBuf,
+    write: Mutex<()>,
+}
+
+impl JsonLinesStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            write: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionStore for JsonLinesStore {
+    async fn save(&self, record: &TransactionRecord) -> Result<(), Box<dyn std::error::Error>> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let _write = self.write.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| format!("Failed to open journal {}: {}", self.path.display(), e))?;
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        query: &TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, Box<dyn std::error::Error>> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(format!("Failed to read journal {}: {}", self.path.display(), e).into());
+            }
+        };
+
+        let lines: Vec<&str> = content.lines().collect();
+        let mut latest: HashMap<(String, u64), TransactionRecord> = HashMap::new();
+        for (number, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A last line cut short by a crash is skipped rather than failing
+            // the whole journal.
+            let Ok(record) = serde_json::from_str::<TransactionRecord>(line) else {
+                if number + 1 == lines.len() {
+                    continue;
+                }
+                return Err(format!("Invalid journal line {} in {}", number + 1, self.path.display()).into());
+            };
+            latest.insert((record.serial_number.clone(), record.ern), record);
+        }
+
+        let mut records: Vec<TransactionRecord> = latest
+            .into_values()
+            .filter(|record| query.matches(record))
+            .collect();
+        records.sort_by_key(|record| record.started_at);
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::response::build_terminal_response_from_raw;
+    use crate::acquiring::types::ConnectionConfigBuilder;
+
+    #[test]
+    fn finish_masks_the_pan_everywhere() {
+        let raw = HashMap::from([
+            ("10".to_string(), "4276123456781234".to_string()),
+            ("90".to_string(), "CARD 4276123456781234\nRRN 123456789012".to_string()),
+            ("39".to_string(), "1".to_string()),
+        ]);
+        let response = build_terminal_response_from_raw(ProtocolType::Inpas, raw);
+        let config = ConnectionConfigBuilder::inpas_dc_tcp("127.0.0.1:9015", "127.0.0.1", 27015)
+            .serial_number("1")
+            .build()
+            .unwrap();
+        let mut record = TransactionRecord::new("payment", 1, &config);
+        record.finish(&Ok(response));
+
+        let line = serde_json::to_string(&record).unwrap();
+        assert!(!line.contains("4276123456781234"), "{}", line);
+        let data = record.response.unwrap().data.unwrap();
+        assert_eq!(data.pan_masked.as_deref(), Some("427612******1234"));
+        assert_eq!(data.receipt.as_deref(), Some("CARD 427612******1234\nRRN 123456789012"));
+        assert_eq!(data.raw["10"], "427612******1234");
+    }
+
+    #[tokio::test]
+    async fn terminals_sharing_a_journal_keep_their_own_erns() {
+        let path = std::env::temp_dir().join(format!("journal-test-{}.jsonl", std::process::id()));
+        let store = JsonLinesStore::new(&path);
+        let record = |serial_number: &str| {
+            let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", 27015)
+                .serial_number(serial_number)
+                .build()
+                .unwrap();
+            TransactionRecord::new("payment", 42, &config)
+        };
+
+        let first = record("00000001");
+        let mut second = record("00000002");
+        store.save(&first).await.unwrap();
+        store.save(&second).await.unwrap();
+        second.finish(&Err("Terminal did not answer".to_string()));
+        store.save(&second).await.unwrap();
+
+        let records = store
+            .query(&TransactionQuery {
+                ern: Some(42),
+                ..Default::default()
+            })
+            .await;
+        std::fs::remove_file(&path).unwrap();
+        let records = records.unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].serial_number, "00000001");
+        assert_eq!(records[0].state, TransactionState::Pending);
+        assert_eq!(records[1].serial_number, "00000002");
+        assert_eq!(records[1].error.as_deref(), Some("Terminal did not answer"));
+    }
+}
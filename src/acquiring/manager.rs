@@ -0,0 +1,371 @@
+use crate::acquiring::supervisor::ConnectionState;
+use crate::acquiring::terminal::Terminal;
+use crate::acquiring::types::config::read_file;
+use crate::acquiring::types::{ConnectionConfig, ConnectionPolicy};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{watch, Mutex, OwnedMutexGuard};
+
+/// One terminal of a `TerminalManager`: the flat `ConnectionConfig` plus
+/// its name, lane and backup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManagedTerminalConfig {
+    pub name: String,
+    #[serde(default)]
+    pub lane: Option<String>,
+    /// Terminal used when this one is unreachable.
+    #[serde(default)]
+    pub backup: Option<String>,
+    #[serde(flatten)]
+    pub connection: ConnectionConfig,
+}
+
+/// `terminals` is a list, `[[terminals]]` tables in TOML.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TerminalManagerConfig {
+    pub terminals: Vec<ManagedTerminalConfig>,
+}
+
+impl TerminalManagerConfig {
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_value(serde_json::from_str(json)?)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_value(toml::from_str(toml)?)
+    }
+
+    /// Reads a `.toml` or `.json` file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_value(read_file(path.as_ref())?)
+    }
+
+    fn from_value(value: serde_json::Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: Self = serde_json::from_value(value)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks every connection, that names are unique and that backups
+    /// refer to other configured terminals.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        let mut names = HashSet::new();
+        for terminal in &self.terminals {
+            if terminal.name.trim().is_empty() {
+                errors.push("terminal name must not be empty".to_string());
+            } else if !names.insert(terminal.name.as_str()) {
+                errors.push(format!("duplicate terminal name: {}", terminal.name));
+            }
+            if let Err(e) = terminal.connection.validate() {
+                errors.push(format!("terminal {}: {}", terminal.name, e));
+            }
+        }
+        for terminal in &self.terminals {
+            match &terminal.backup {
+                Some(backup) if backup == &terminal.name => {
+                    errors.push(format!("terminal {} is its own backup", terminal.name))
+                }
+                Some(backup) if !names.contains(backup.as_str()) => errors.push(format!(
+                    "terminal {}: unknown backup terminal {}",
+                    terminal.name, backup
+                )),
+                _ => {}
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+}
+
+struct ManagedTerminal {
+    settings: ManagedTerminalConfig,
+    terminal: Arc<Mutex<Terminal>>,
+    state: watch::Receiver<ConnectionState>,
+}
+
+/// A terminal picked by `TerminalManager::acquire`, locked for the caller
+/// until dropped.
+pub struct RoutedTerminal {
+    name: String,
+    terminal: OwnedMutexGuard<Terminal>,
+}
+
+impl RoutedTerminal {
+    /// Name of the terminal that was picked, which differs from the primary
+    /// after a failover.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Deref for RoutedTerminal {
+    type Target = Terminal;
+
+    fn deref(&self) -> &Terminal {
+        &self.terminal
+    }
+}
+
+impl DerefMut for RoutedTerminal {
+    fn deref_mut(&mut self) -> &mut Terminal {
+        &mut self.terminal
+    }
+}
+
+/// Named terminals, connected on first use. Operations are routed by
+/// terminal name or by lane; an unreachable terminal is replaced by the
+/// other terminals of its lane and then by its backup.
+pub struct TerminalManager {
+    terminals: Vec<ManagedTerminal>,
+}
+
+impl TerminalManager {
+    pub fn new(config: TerminalManagerConfig) -> Result<Self, String> {
+        config.validate()?;
+        let terminals = config
+            .terminals
+            .into_iter()
+            .map(|settings| {
+                let terminal = Terminal::new(settings.connection.clone());
+                let state = terminal.state_changes();
+                ManagedTerminal {
+                    settings,
+                    terminal: Arc::new(Mutex::new(terminal)),
+                    state,
+                }
+            })
+            .collect();
+        Ok(Self { terminals })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::new(TerminalManagerConfig::from_file(path)?)?)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.terminals
+            .iter()
+            .map(|managed| managed.settings.name.as_str())
+            .collect()
+    }
+
+    pub fn config(&self, name: &str) -> Option<&ManagedTerminalConfig> {
+        self.find(name).map(|managed| &managed.settings)
+    }
+
+    /// The terminal itself, without routing or connecting.
+    pub fn terminal(&self, name: &str) -> Option<Arc<Mutex<Terminal>>> {
+        self.find(name).map(|managed| Arc::clone(&managed.terminal))
+    }
+
+    /// Doesn't wait for terminals busy with an operation.
+    pub fn state(&self, name: &str) -> Option<ConnectionState> {
+        self.find(name).map(|managed| managed.state.borrow().clone())
+    }
+
+    /// State of every terminal, in configuration order.
+    pub fn states(&self) -> Vec<(String, ConnectionState)> {
+        self.terminals
+            .iter()
+            .map(|managed| (managed.settings.name.clone(), managed.state.borrow().clone()))
+            .collect()
+    }
+
+    fn find(&self, name: &str) -> Option<&ManagedTerminal> {
+        self.terminals
+            .iter()
+            .find(|managed| managed.settings.name == name)
+    }
+
+    /// Terminals to try for `key`, a terminal name or a lane: the matching
+    /// terminals in configuration order, followed by their backups.
+    pub fn route(&self, key: &str) -> Result<Vec<String>, String> {
+        let mut route: Vec<String> = match self.find(key) {
+            Some(managed) => vec![managed.settings.name.clone()],
+            None => self
+                .terminals
+                .iter()
+                .filter(|managed| managed.settings.lane.as_deref() == Some(key))
+                .map(|managed| managed.settings.name.clone())
+                .collect(),
+        };
+        if route.is_empty() {
+            return Err(format!("Unknown terminal or lane: {}", key));
+        }
+
+        let mut i = 0;
+        while i < route.len() {
+            let backup = self
+                .find(&route[i])
+                .and_then(|managed| managed.settings.backup.clone());
+            if let Some(backup) = backup.filter(|backup| !route.contains(backup)) {
+                route.push(backup);
+            }
+            i += 1;
+        }
+        Ok(route)
+    }
+
+    /// Locks the first reachable terminal on the route for `key`, connecting
+    /// it if needed. Failover only happens here, before anything is sent, so
+    /// a failed operation is never repeated on another terminal.
+    pub async fn acquire(&self, key: &str) -> Result<RoutedTerminal, Box<dyn std::error::Error>> {
+        let mut errors = Vec::new();
+        for name in self.route(key)? {
+            let Some(managed) = self.find(&name) else {
+                continue;
+            };
+            let policy = managed.settings.connection.policy.unwrap_or_default();
+            let mut terminal = Arc::clone(&managed.terminal).lock_owned().await;
+            match ensure_reachable(&mut terminal, policy).await {
+                Ok(()) => return Ok(RoutedTerminal { name, terminal }),
+                Err(e) => errors.push(format!("{}: {}", name, e)),
+            }
+        }
+        Err(format!("No terminal available for {}: {}", key, errors.join("; ")).into())
+    }
+
+    /// Disconnects every terminal that was connected.
+    pub async fn disconnect_all(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut errors = Vec::new();
+        for managed in &self.terminals {
+            let mut terminal = managed.terminal.lock().await;
+            if terminal.state() == ConnectionState::Disconnected {
+                continue;
+            }
+            if let Err(e) = terminal.disconnect().await.map_err(|e| e.to_string()) {
+                errors.push(format!("{}: {}", managed.settings.name, e));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; ").into())
+        }
+    }
+}
+
+/// Connects a terminal that isn't connected yet. A persistent link is checked
+/// and reconnected once if the check fails. Lazy and per-command links are
+/// opened and pinged, as nothing else tells whether the terminal is there.
+async fn ensure_reachable(terminal: &mut Terminal, policy: ConnectionPolicy) -> Result<(), String> {
+    let persistent = policy == ConnectionPolicy::Persistent;
+    match terminal.state() {
+        ConnectionState::Reconnecting { attempt } => {
+            return Err(format!("Reconnecting, attempt {}", attempt));
+        }
+        ConnectionState::Disconnected | ConnectionState::Failed(_) => {
+            terminal.connect().await.map_err(|e| e.to_string())?;
+            if persistent {
+                return Ok(());
+            }
+        }
+        ConnectionState::Connected | ConnectionState::OnDemand if persistent => {
+            if terminal.check().await.is_err() {
+                terminal.connect().await.map_err(|e| e.to_string())?;
+            }
+            return Ok(());
+        }
+        ConnectionState::Connected | ConnectionState::OnDemand => {}
+    }
+    terminal.probe().await.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::simulator::{Scenario, TtkSimulator};
+
+    fn terminal(
+        name: &str,
+        lane: Option<&str>,
+        backup: Option<&str>,
+        port: u16,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "lane": lane,
+            "backup": backup,
+            "connection_type": "tcp",
+            "protocol": "ttk",
+            "serial_number": "1",
+            "address": "127.0.0.1",
+            "port": port,
+            "timeout": 500,
+            "policy": "lazy"
+        })
+    }
+
+    fn manager(terminals: Vec<serde_json::Value>) -> TerminalManager {
+        let config = serde_json::json!({ "terminals": terminals }).to_string();
+        TerminalManager::new(TerminalManagerConfig::from_json(&config).unwrap()).unwrap()
+    }
+
+    async fn simulator(simulator: TtkSimulator) -> u16 {
+        let (addr, _) = Arc::new(simulator).listen_tcp("127.0.0.1:0").await.unwrap();
+        addr.port()
+    }
+
+    /// A port nothing listens on.
+    fn closed_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn routes_follow_lane_order_then_chained_backups() {
+        let manager = manager(vec![
+            terminal("lane1-a", Some("1"), Some("mobile"), 1),
+            terminal("lane2", Some("2"), None, 2),
+            terminal("lane1-b", Some("1"), Some("lane2"), 3),
+            terminal("mobile", None, Some("lane1-b"), 4),
+        ]);
+
+        assert_eq!(manager.route("1").unwrap(), ["lane1-a", "lane1-b", "mobile", "lane2"]);
+        assert_eq!(manager.route("lane1-a").unwrap(), ["lane1-a", "mobile", "lane1-b", "lane2"]);
+        assert_eq!(manager.route("2").unwrap(), ["lane2"]);
+        assert_eq!(manager.route("3").unwrap_err(), "Unknown terminal or lane: 3");
+    }
+
+    #[tokio::test]
+    async fn acquire_fails_over_from_a_dead_primary() {
+        let silent = simulator(
+            TtkSimulator::new(Scenario::Approve).with_scenario("SRV", Scenario::Timeout),
+        )
+        .await;
+        let live = simulator(TtkSimulator::new(Scenario::Approve)).await;
+        let manager = manager(vec![
+            terminal("down", Some("1"), Some("silent"), closed_port()),
+            terminal("silent", None, Some("live"), silent),
+            terminal("live", None, None, live),
+        ]);
+
+        let mut routed = manager.acquire("1").await.unwrap();
+        assert_eq!(routed.name(), "live");
+        assert!(routed.payment(1000, None).await.unwrap().success);
+        drop(routed);
+
+        // The TCP connect to `silent` succeeds; only the ping shows it's gone.
+        assert_eq!(manager.state("down"), Some(ConnectionState::OnDemand));
+        assert_eq!(manager.state("silent"), Some(ConnectionState::OnDemand));
+        assert_eq!(manager.state("live"), Some(ConnectionState::Connected));
+    }
+
+    #[tokio::test]
+    async fn acquire_reports_every_failure() {
+        let manager = manager(vec![terminal("down", Some("1"), None, closed_port())]);
+        let error = manager.acquire("1").await.err().unwrap().to_string();
+        assert!(error.starts_with("No terminal available for 1: down: "), "{}", error);
+    }
+}
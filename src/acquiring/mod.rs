@@ -1,10 +1,25 @@
 pub mod commands;
 pub mod connection;
+pub mod discovery;
+pub mod journal;
+pub mod manager;
 pub mod protocol;
 pub mod response;
+pub mod simulator;
+pub mod supervisor;
 pub mod terminal;
 pub mod types;
 
+pub use connection::{list_serial_terminals, SerialPortDescription};
+pub use discovery::{discover_terminals, DiscoveredTerminal, DiscoveryOptions};
+pub use journal::{
+    JsonLinesStore, TransactionQuery, TransactionRecord, TransactionState, TransactionStore,
+};
+pub use manager::{ManagedTerminalConfig, RoutedTerminal, TerminalManager, TerminalManagerConfig};
+pub use supervisor::ConnectionState;
 pub use terminal::Terminal;
-pub use types::{ConnectionConfig, ConnectionType, ProtocolType, TerminalResponse};
+pub use types::{
+    ConnectionConfig, ConnectionConfigBuilder, ConnectionPolicy, ConnectionType, DcClientConfig, ProtocolType,
+    ReconnectConfig, SberConfig, SerialConfig, SerialFlowControl, SerialParity, TerminalResponse, TlsConfig,
+};
 
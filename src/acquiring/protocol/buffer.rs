@@ -47,6 +47,25 @@ impl TtkBuffer {
         Ok((msg_type, items))
     }
 
+    /// Total size of the frame starting at `data[0]`, once its header is
+    /// available.
+    pub fn frame_length(data: &[u8]) -> Option<usize> {
+        if data.len() < 2 {
+            return None;
+        }
+        Some((((data[0] as usize) << 8) | (data[1] as usize)) + 2)
+    }
+
+    /// Removes and returns the first complete frame from `pending`, leaving
+    /// any partial data in place for the next read.
+    pub fn take_frame(pending: &mut Vec<u8>) -> Option<Vec<u8>> {
+        let length = Self::frame_length(pending)?;
+        if pending.len() < length {
+            return None;
+        }
+        Some(pending.drain(..length).collect())
+    }
+
     pub fn items_to_object(items: &[TlvItem]) -> std::collections::HashMap<String, String> {
         let mut result = std::collections::HashMap::new();
 
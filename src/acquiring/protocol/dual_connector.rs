@@ -0,0 +1,230 @@
+use crate::acquiring::types::{ConnectionConfig, DcClientConfig};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const DEFAULT_ENCODING: &str = "windows-1251";
+const DEFAULT_TIMEOUT_MS: u32 = 30000;
+const DEFAULT_CONNECT_TIMEOUT_MS: u32 = 5000;
+/// DualConnector only answers once the terminal is done, so the HTTP timeout
+/// leaves room for its own processing on top of the operation timeout.
+const RESPONSE_GRACE_MS: u32 = 10000;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ClientKey {
+  ca_cert: Option<String>,
+  accept_invalid_certs: bool,
+  connect_timeout: u32,
+}
+
+static CLIENTS: Lazy<Mutex<HashMap<ClientKey, reqwest::Client>>> = Lazy::new(Default::default);
+
+/// HTTP client for one DualConnector endpoint. The underlying connection pool
+/// is shared between all endpoints with the same TLS and connect settings.
+#[derive(Clone)]
+pub struct DualConnectorClient {
+  client: reqwest::Client,
+  url: reqwest::Url,
+  credentials: Option<(String, Option<String>)>,
+  timeout: Duration,
+}
+
+impl DualConnectorClient {
+  pub fn from_config(config: &ConnectionConfig) -> Result<Self, Box<dyn std::error::Error>> {
+    let dc_host = config
+      .dc_host
+      .as_deref()
+      .ok_or("dcHost property is required for inpas protocol")?;
+    let settings = config.dc_client.clone().unwrap_or_default();
+
+    let mut url = normalize_dc_url(dc_host, &settings)?;
+    let credentials = take_credentials(&mut url).or_else(|| {
+      settings
+        .username
+        .clone()
+        .map(|username| (username, settings.password.clone()))
+    });
+
+    let timeout = config
+      .timeout
+      .unwrap_or(DEFAULT_TIMEOUT_MS)
+      .saturating_add(RESPONSE_GRACE_MS);
+    let client = shared_client(ClientKey {
+      ca_cert: settings.ca_cert.clone(),
+      accept_invalid_certs: settings.accept_invalid_certs,
+      connect_timeout: settings
+        .connect_timeout
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS)
+        .min(timeout),
+    })?;
+
+    Ok(Self {
+      client,
+      url,
+      credentials,
+      timeout: Duration::from_millis(timeout as u64),
+    })
+  }
+
+  /// Endpoint URL without credentials.
+  pub fn url(&self) -> &reqwest::Url {
+    &self.url
+  }
+
+  pub async fn post_xml(&self, xml_body: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let body_bytes: Vec<u8> = encoding_rs::WINDOWS_1251.encode(xml_body).0.to_vec();
+
+    let mut request = self
+      .client
+      .post(self.url.clone())
+      .timeout(self.timeout)
+      .header(
+        "Content-Type",
+        format!("text/xml; charset={}", DEFAULT_ENCODING),
+      )
+      .header("Accept", "text/xml")
+      .header("Accept-Charset", DEFAULT_ENCODING)
+      .header("User-Agent", "corex-ttk2")
+      .body(body_bytes);
+    if let Some((username, password)) = &self.credentials {
+      request = request.basic_auth(username, password.as_ref());
+    }
+
+    let response = request.send().await.map_err(|e| self.describe_error(e))?;
+
+    let status = response.status();
+    let content_type_header = response
+      .headers()
+      .get("content-type")
+      .and_then(|h| h.to_str().ok())
+      .map(|s| s.to_string());
+    let bytes = response.bytes().await.map_err(|e| self.describe_error(e))?;
+
+    if status.is_client_error() || status.is_server_error() {
+      let text = String::from_utf8_lossy(&bytes);
+      return Err(format!("DualConnector HTTP error {}: {}", status.as_u16(), text).into());
+    }
+    let charset = extract_charset(content_type_header.as_deref());
+    let decoded = match charset {
+      Some("windows-1251") | Some("cp1251") => encoding_rs::WINDOWS_1251.decode(&bytes).0.to_string(),
+      _ => String::from_utf8_lossy(&bytes).to_string(),
+    };
+
+    Ok(decoded)
+  }
+
+  fn describe_error(&self, error: reqwest::Error) -> Box<dyn std::error::Error> {
+    if error.is_connect() {
+      format!("Failed to connect to DualConnector at {}: {}", self.url, error).into()
+    } else if error.is_timeout() {
+      format!(
+        "DualConnector at {} did not respond within {} ms",
+        self.url,
+        self.timeout.as_millis()
+      )
+      .into()
+    } else {
+      error.into()
+    }
+  }
+}
+
+fn shared_client(key: ClientKey) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+  let mut clients = CLIENTS.lock().unwrap();
+  if let Some(client) = clients.get(&key) {
+    return Ok(client.clone());
+  }
+
+  let mut builder = reqwest::Client::builder()
+    .connect_timeout(Duration::from_millis(key.connect_timeout as u64))
+    .danger_accept_invalid_certs(key.accept_invalid_certs);
+  if let Some(path) = &key.ca_cert {
+    let pem = std::fs::read(path)
+      .map_err(|e| format!("Failed to read DualConnector CA certificate {}: {}", path, e))?;
+    builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem)?);
+  }
+
+  let client = builder.build()?;
+  clients.insert(key, client.clone());
+  Ok(client)
+}
+
+/// Accepts `host:port`, `host:port/path` or a full http(s) URL. Bare hosts get
+/// `https://` when TLS settings are present and `http://` otherwise.
+pub fn normalize_dc_url(
+  host: &str,
+  settings: &DcClientConfig,
+) -> Result<reqwest::Url, Box<dyn std::error::Error>> {
+  let host = host.trim();
+  if host.is_empty() {
+    return Err("dcHost property is empty".into());
+  }
+
+  let url: reqwest::Url = if host.contains("://") {
+    host.parse()?
+  } else {
+    let scheme = if settings.ca_cert.is_some() || settings.accept_invalid_certs {
+      "https"
+    } else {
+      "http"
+    };
+    format!("{}://{}", scheme, host).parse()?
+  };
+
+  if url.scheme() != "http" && url.scheme() != "https" {
+    return Err(format!("Unsupported DualConnector URL scheme: {}", url.scheme()).into());
+  }
+  if url.host_str().is_none_or(str::is_empty) {
+    return Err(format!("DualConnector URL has no host: {}", host).into());
+  }
+
+  Ok(url)
+}
+
+fn take_credentials(url: &mut reqwest::Url) -> Option<(String, Option<String>)> {
+  if url.username().is_empty() {
+    return None;
+  }
+  let credentials = (
+    percent_decode(url.username()),
+    url.password().map(percent_decode),
+  );
+  let _ = url.set_username("");
+  let _ = url.set_password(None);
+  Some(credentials)
+}
+
+fn percent_decode(value: &str) -> String {
+  let bytes = value.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let hex = bytes
+      .get(i + 1..i + 3)
+      .and_then(|h| std::str::from_utf8(h).ok())
+      .and_then(|h| u8::from_str_radix(h, 16).ok());
+    match (bytes[i], hex) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        i += 3;
+      }
+      (byte, _) => {
+        decoded.push(byte);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn extract_charset(content_type: Option<&str>) -> Option<&str> {
+  content_type?.split(';').find_map(|part| {
+    let part = part.trim();
+    if part.to_lowercase().starts_with("charset=") {
+      Some(&part[8..])
+    } else {
+      None
+    }
+  })
+}
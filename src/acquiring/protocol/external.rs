@@ -0,0 +1,516 @@
+use crate::acquiring::response::build_terminal_response;
+use crate::acquiring::types::{
+    ExternalOutputFormat, ExternalProcessConfig, FixedWidthField, NormalizedTransactionData,
+    TerminalResponse,
+};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+/// Default for `ExternalProcessConfig::timeout`.
+const DEFAULT_TIMEOUT_MS: u32 = 120000;
+pub const EXIT_CODE_KEY: &str = "exit_code";
+
+static PLACEHOLDER: Lazy<regex::Regex> = Lazy::new(|| regex::Regex::new(r"\{(\w+)\}").unwrap());
+
+/// Operation parameters substituted into the command template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalRequest {
+    pub operation: String,
+    pub amount: Option<u64>,
+    pub currency: Option<String>,
+}
+
+impl ExternalRequest {
+    pub fn new(operation: &str, amount: Option<u64>, currency: Option<String>) -> Self {
+        Self {
+            operation: operation.to_string(),
+            amount,
+            currency,
+        }
+    }
+}
+
+pub struct ExternalProcessProtocol {
+    config: ExternalProcessConfig,
+}
+
+impl ExternalProcessProtocol {
+    pub fn new(config: ExternalProcessConfig) -> Result<Self, String> {
+        if config.program.trim().is_empty() {
+            return Err("External process program is not configured".to_string());
+        }
+        normalize_mapped(&HashMap::new(), &config.mapping)?;
+        Ok(Self { config })
+    }
+
+    pub async fn execute(
+        &self,
+        request: &ExternalRequest,
+        ern: u64,
+        serial_number: &str,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        let mut values = HashMap::new();
+        values.insert(
+            "operation",
+            self.config
+                .operations
+                .get(&request.operation)
+                .cloned()
+                .unwrap_or_else(|| request.operation.clone()),
+        );
+        values.insert(
+            "amount",
+            request.amount.map(|a| a.to_string()).unwrap_or_default(),
+        );
+        values.insert("currency", request.currency.clone().unwrap_or_default());
+        values.insert("ern", ern.to_string());
+        values.insert("serial_number", serial_number.to_string());
+        let render = |template: &str| render_template(template, &values);
+
+        let dir = self
+            .config
+            .work_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let output_file = self
+            .config
+            .output
+            .file
+            .as_ref()
+            .map(|file| dir.join(render(file)));
+        if let Some(path) = &output_file {
+            remove_if_exists(path)?;
+        }
+
+        let mut stdin_data = None;
+        if let Some(input) = &self.config.input {
+            let data = encode_text(&render(&input.template), input.encoding.as_deref())?;
+            match &input.file {
+                Some(file) => std::fs::write(dir.join(render(file)), data)?,
+                None => stdin_data = Some(data),
+            }
+        }
+
+        let mut child = Command::new(&self.config.program)
+            .args(self.config.args.iter().map(|arg| render(arg)))
+            .current_dir(&dir)
+            .stdin(if stdin_data.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", self.config.program, e))?;
+
+        // Input is written while the output is collected, so a child that
+        // fills its stdout before reading stdin can't stall the exchange.
+        let stdin = child.stdin.take();
+        let write_input = async move {
+            let (Some(data), Some(mut stdin)) = (stdin_data, stdin) else {
+                return Ok(());
+            };
+            match stdin.write_all(&data).await {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+                _ => Ok(()),
+            }
+        };
+
+        let timeout_ms = self.config.timeout.unwrap_or(DEFAULT_TIMEOUT_MS);
+        let (written, output) = timeout(Duration::from_millis(timeout_ms as u64), async {
+            tokio::join!(write_input, child.wait_with_output())
+        })
+        .await
+        .map_err(|_| {
+            format!(
+                "{} did not finish within {} ms",
+                self.config.program, timeout_ms
+            )
+        })?;
+        written.map_err(|e| format!("Failed to write input to {}: {}", self.config.program, e))?;
+        let output = output?;
+
+        // A crash or a kill must never read as an approval; only a mapping
+        // that uses `exit_code` gives a non-zero exit a meaning.
+        let maps_exit_code = self
+            .config
+            .mapping
+            .values()
+            .any(|source| source == EXIT_CODE_KEY);
+        match output.status.code() {
+            Some(0) => {}
+            Some(_) if maps_exit_code => {}
+            _ => {
+                return Err(
+                    format!("{} exited with {}", self.config.program, output.status).into(),
+                );
+            }
+        }
+
+        let data = match &output_file {
+            Some(path) => std::fs::read(path).map_err(|e| {
+                format!(
+                    "{} exited with {} without writing {}: {}",
+                    self.config.program,
+                    output.status,
+                    path.display(),
+                    e
+                )
+            })?,
+            None => output.stdout,
+        };
+        let text = decode_text(&data, self.config.output.encoding.as_deref())?;
+
+        let mut raw = parse_output(&text, &self.config.output.format)?;
+        if raw.is_empty() && !maps_exit_code {
+            return Err(format!("{} returned no result", self.config.program).into());
+        }
+        if let Some(code) = output.status.code() {
+            raw.insert(EXIT_CODE_KEY.to_string(), code.to_string());
+        }
+
+        let data = normalize_mapped(&raw, &self.config.mapping)?;
+        Ok(build_terminal_response(data))
+    }
+}
+
+/// Replaces `{name}` placeholders in one pass, so substituted values are
+/// never expanded again. Unknown placeholders are left as they are.
+pub fn render_template(template: &str, values: &HashMap<&str, String>) -> String {
+    PLACEHOLDER
+        .replace_all(template, |caps: &regex::Captures| {
+            values
+                .get(&caps[1])
+                .cloned()
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .to_string()
+}
+
+pub fn parse_output(
+    text: &str,
+    format: &ExternalOutputFormat,
+) -> Result<HashMap<String, String>, String> {
+    match format {
+        ExternalOutputFormat::KeyValue { separator } => Ok(parse_key_value(text, separator)),
+        ExternalOutputFormat::FixedWidth { fields } => Ok(parse_fixed_width(text, fields)),
+        ExternalOutputFormat::Xml => parse_xml(text),
+    }
+}
+
+fn parse_key_value(text: &str, separator: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(separator))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn parse_fixed_width(text: &str, fields: &[FixedWidthField]) -> HashMap<String, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    fields
+        .iter()
+        .filter_map(|field| {
+            let line = lines.get(field.line)?;
+            let value: String = line.chars().skip(field.start).take(field.length).collect();
+            let value = value.trim();
+            (!value.is_empty()).then(|| (field.name.clone(), value.to_string()))
+        })
+        .collect()
+}
+
+fn parse_xml(text: &str) -> Result<HashMap<String, String>, String> {
+    use quick_xml::events::{BytesStart, Event};
+
+    fn segment(element: &BytesStart) -> Result<(String, Vec<(String, String)>), String> {
+        let name = String::from_utf8_lossy(element.local_name().as_ref()).to_string();
+        let mut attributes = Vec::new();
+        let mut id = None;
+        for attribute in element.attributes() {
+            let attribute = attribute.map_err(|e| format!("Invalid XML attribute: {}", e))?;
+            let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_string();
+            let value = attribute
+                .unescape_value()
+                .map_err(|e| format!("Invalid XML attribute value: {}", e))?
+                .to_string();
+            if key == "id" {
+                id = Some(value);
+            } else {
+                attributes.push((key, value));
+            }
+        }
+        let segment = match id {
+            Some(id) => format!("{}[{}]", name, id),
+            None => name,
+        };
+        Ok((segment, attributes))
+    }
+
+    let mut reader = quick_xml::Reader::from_str(text);
+    reader.trim_text(true);
+    let mut path: Vec<String> = Vec::new();
+    let mut raw = HashMap::new();
+
+    let key = |path: &[String]| path.iter().skip(1).cloned().collect::<Vec<_>>().join(".");
+    let add_attributes =
+        |raw: &mut HashMap<String, String>, path: &[String], attributes: Vec<(String, String)>| {
+            let element = key(path);
+            for (name, value) in attributes {
+                raw.insert(format!("{}@{}", element, name), value);
+            }
+        };
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                let (segment, attributes) = segment(&element)?;
+                path.push(segment);
+                add_attributes(&mut raw, &path, attributes);
+            }
+            Ok(Event::Empty(element)) => {
+                let (segment, attributes) = segment(&element)?;
+                path.push(segment);
+                add_attributes(&mut raw, &path, attributes);
+                if path.len() > 1 {
+                    raw.entry(key(&path)).or_insert_with(String::new);
+                }
+                path.pop();
+            }
+            Ok(Event::End(_)) => {
+                path.pop();
+            }
+            Ok(Event::Text(text)) => {
+                let value = text
+                    .unescape()
+                    .map_err(|e| format!("Invalid XML text: {}", e))?;
+                if path.len() > 1 {
+                    raw.insert(key(&path), value.to_string());
+                }
+            }
+            Ok(Event::CData(data)) => {
+                if path.len() > 1 {
+                    raw.insert(
+                        key(&path),
+                        String::from_utf8_lossy(&data.into_inner()).to_string(),
+                    );
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                return Err(format!(
+                    "Invalid XML at position {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+        }
+    }
+    Ok(raw)
+}
+
+/// Builds the normalized data from `mapping` (normalized field name to
+/// output key). Output keys that aren't mapped end up in `extras`.
+pub fn normalize_mapped(
+    raw: &HashMap<String, String>,
+    mapping: &HashMap<String, String>,
+) -> Result<NormalizedTransactionData, String> {
+    let known = normalized_field_names();
+    let mut unknown: Vec<&String> = mapping
+        .keys()
+        .filter(|field| !known.contains(*field))
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        return Err(format!(
+            "Unknown normalized fields in external process mapping: {:?}",
+            unknown
+        ));
+    }
+
+    let object: serde_json::Map<String, serde_json::Value> = mapping
+        .iter()
+        .filter_map(|(field, source)| {
+            let value = raw.get(source)?;
+            Some((field.clone(), serde_json::Value::String(value.clone())))
+        })
+        .collect();
+    let mut data: NormalizedTransactionData =
+        serde_json::from_value(serde_json::Value::Object(object))
+            .map_err(|e| format!("Invalid external process mapping: {}", e))?;
+
+    data.raw = raw.clone();
+    let extras: HashMap<String, String> = raw
+        .iter()
+        .filter(|(key, _)| !mapping.values().any(|source| source == *key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    if !extras.is_empty() {
+        data.extras = Some(extras);
+    }
+    Ok(data)
+}
+
+/// Names of the string fields of `NormalizedTransactionData`.
+pub fn normalized_field_names() -> Vec<String> {
+    let empty: NormalizedTransactionData =
+        serde_json::from_value(serde_json::json!({})).expect("all normalized fields are optional");
+    match serde_json::to_value(empty) {
+        Ok(serde_json::Value::Object(fields)) => fields
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name != "extras")
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn encoding_for(label: &str) -> Result<&'static encoding_rs::Encoding, String> {
+    encoding_rs::Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| format!("Unknown encoding: {}", label))
+}
+
+fn encode_text(text: &str, encoding: Option<&str>) -> Result<Vec<u8>, String> {
+    match encoding {
+        Some(label) => Ok(encoding_for(label)?.encode(text).0.to_vec()),
+        None => Ok(text.as_bytes().to_vec()),
+    }
+}
+
+fn decode_text(data: &[u8], encoding: Option<&str>) -> Result<String, String> {
+    if let Some(label) = encoding {
+        return Ok(encoding_for(label)?.decode(data).0.to_string());
+    }
+    Ok(match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1251.decode(data).0.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::types::ExternalInput;
+
+    #[test]
+    fn templates_render_in_one_pass() {
+        let mut values = HashMap::new();
+        values.insert("operation", "{amount}".to_string());
+        values.insert("amount", "1000".to_string());
+
+        assert_eq!(
+            render_template("op={operation} sum={amount} {unknown}", &values),
+            "op={amount} sum=1000 {unknown}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn input_is_written_while_output_is_read() {
+        // The child fills its stdout pipe before it reads any input.
+        let config = ExternalProcessConfig {
+            program: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "yes x | head -c 262144; cat > /dev/null; echo code=00".to_string(),
+            ],
+            input: Some(ExternalInput {
+                template: format!("{}\namount={{amount}}\n", "x".repeat(1 << 20)),
+                ..Default::default()
+            }),
+            mapping: [("response_code".to_string(), "code".to_string())].into(),
+            timeout: Some(5000),
+            ..Default::default()
+        };
+        let protocol = ExternalProcessProtocol::new(config).unwrap();
+        let request = ExternalRequest::new("payment", Some(1000), None);
+
+        let response = protocol.execute(&request, 1, "1").await.unwrap();
+        assert!(response.success);
+        assert_eq!(response.code.as_deref(), Some("00"));
+    }
+
+    #[cfg(unix)]
+    async fn run_script(script: &str, mapping: &[(&str, &str)]) -> Result<TerminalResponse, String> {
+        let config = ExternalProcessConfig {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            mapping: mapping
+                .iter()
+                .map(|(field, source)| (field.to_string(), source.to_string()))
+                .collect(),
+            timeout: Some(2000),
+            ..Default::default()
+        };
+        let request = ExternalRequest::new("payment", Some(1000), None);
+        ExternalProcessProtocol::new(config)
+            .unwrap()
+            .execute(&request, 1, "1")
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_or_killed_programs_are_errors() {
+        let mapping = [("response_code", "code")];
+
+        let error = run_script("echo code=00; exit 3", &mapping).await.unwrap_err();
+        assert_eq!(error, "sh exited with exit status: 3");
+
+        let error = run_script("kill -9 $$", &mapping).await.unwrap_err();
+        assert!(error.starts_with("sh exited with signal: 9"), "{}", error);
+
+        let error = run_script("true", &mapping).await.unwrap_err();
+        assert_eq!(error, "sh returned no result");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn mapped_exit_codes_decide_the_outcome() {
+        let mapping = [("response_code", "exit_code")];
+
+        let response = run_script("exit 5", &mapping).await.unwrap();
+        assert!(!response.success);
+        assert_eq!(response.code.as_deref(), Some("5"));
+
+        let response = run_script("exit 0", &mapping).await.unwrap();
+        assert!(response.success);
+
+        let error = run_script("kill -9 $$", &mapping).await.unwrap_err();
+        assert!(error.starts_with("sh exited with signal: 9"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn own_timeout_limits_the_program() {
+        let config = ExternalProcessConfig {
+            program: "sleep".to_string(),
+            args: vec!["1".to_string()],
+            timeout: Some(50),
+            ..Default::default()
+        };
+        let request = ExternalRequest::new("payment", Some(1000), None);
+        let error = ExternalProcessProtocol::new(config)
+            .unwrap()
+            .execute(&request, 1, "1")
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "sleep did not finish within 50 ms");
+    }
+}
@@ -1,95 +1,197 @@
+use crate::acquiring::protocol::dual_connector::{DualConnectorClient, DEFAULT_ENCODING};
 use crate::acquiring::response::build_terminal_response_from_raw;
-use crate::acquiring::types::{ConnectionConfig, TerminalResponse};
-use quick_xml::events::{BytesStart, Event};
-use quick_xml::Writer;
-use std::io::Cursor;
+use crate::acquiring::types::{ConnectionConfig, InpasFieldId, InpasOperation, TerminalResponse};
+use serde::de::{Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InpasField {
+  #[serde(rename = "@id")]
   pub id: String,
+  #[serde(rename = "$text", default)]
   pub value: String,
 }
 
-pub struct EnvelopeOptions {
-  timeout: Option<u32>,
-  ipaddr: Option<String>,
-  ncom: Option<String>,
-  baudrate: Option<u32>,
+impl InpasField {
+  pub fn new(id: InpasFieldId, value: impl Into<String>) -> Self {
+    Self {
+      id: id.code().to_string(),
+      value: value.into(),
+    }
+  }
+
+  pub fn operation(operation: InpasOperation) -> Self {
+    Self::new(InpasFieldId::OperationCode, operation.code().to_string())
+  }
+
+  pub fn field_id(&self) -> Option<InpasFieldId> {
+    InpasFieldId::from_code(&self.id)
+  }
 }
 
-const DEFAULT_ENCODING: &str = "windows-1251";
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename = "request")]
+pub struct InpasRequest {
+  #[serde(rename = "field", default)]
+  pub fields: Vec<InpasField>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub timeout: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ipaddr: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ncom: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub baudrate: Option<u32>,
+}
 
-pub fn build_inpas_xml(
-  fields: &[InpasField],
-  meta: EnvelopeOptions,
-) -> Result<String, Box<dyn std::error::Error>> {
-  let mut writer = Writer::new(Cursor::new(Vec::new()));
-  writer.write_event(Event::Decl(quick_xml::events::BytesDecl::new(
-    "1.0",
-    Some(DEFAULT_ENCODING),
-    None,
-  )))?;
-
-  let request_elem = BytesStart::new("request");
-  writer.write_event(Event::Start(request_elem.clone()))?;
+#[derive(Debug, Clone, Default)]
+pub struct InpasResponse {
+  pub fields: Vec<InpasField>,
+  pub error_code: Option<String>,
+  pub error_description: Option<String>,
+  /// Envelope elements other than field/errorcode/errordescription, by name,
+  /// with their own text. Their children are skipped.
+  pub unknown: Vec<(String, String)>,
+}
 
-  for field in fields {
-    let mut field_elem = BytesStart::new("field");
-    field_elem.push_attribute(("id", field.id.as_str()));
-    writer.write_event(Event::Start(field_elem.clone()))?;
-    writer.write_event(Event::Text(quick_xml::events::BytesText::new(&field.value)))?;
-    writer.write_event(Event::End(field_elem.to_end()))?;
-  }
+#[derive(Deserialize)]
+struct ElementText {
+  #[serde(rename = "$text", default)]
+  text: String,
+}
 
-  if let Some(timeout) = meta.timeout {
-    writer.write_event(Event::Start(BytesStart::new("timeout")))?;
-    writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-      &timeout.to_string(),
-    )))?;
-    writer.write_event(Event::End(BytesStart::new("timeout").to_end()))?;
-  }
+/// Text of an element nobody asked for. Attributes and child elements are
+/// skipped, so a nested unknown element doesn't fail the whole envelope.
+struct UnknownElement(String);
 
-  if let Some(ipaddr) = &meta.ipaddr {
-    writer.write_event(Event::Start(BytesStart::new("ipaddr")))?;
-    writer.write_event(Event::Text(quick_xml::events::BytesText::new(ipaddr)))?;
-    writer.write_event(Event::End(BytesStart::new("ipaddr").to_end()))?;
-  }
+impl<'de> Deserialize<'de> for UnknownElement {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    struct UnknownVisitor;
+
+    impl<'de> Visitor<'de> for UnknownVisitor {
+      type Value = UnknownElement;
+
+      fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an XML element")
+      }
+
+      fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<UnknownElement, E> {
+        Ok(UnknownElement(value.to_string()))
+      }
+
+      fn visit_unit<E: serde::de::Error>(self) -> Result<UnknownElement, E> {
+        Ok(UnknownElement(String::new()))
+      }
 
-  if let Some(ncom) = &meta.ncom {
-    writer.write_event(Event::Start(BytesStart::new("ncom")))?;
-    writer.write_event(Event::Text(quick_xml::events::BytesText::new(ncom)))?;
-    writer.write_event(Event::End(BytesStart::new("ncom").to_end()))?;
+      fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UnknownElement, A::Error> {
+        let mut text = String::new();
+        while let Some(key) = map.next_key::<String>()? {
+          if key == "$text" {
+            text.push_str(&map.next_value::<String>()?);
+          } else {
+            map.next_value::<IgnoredAny>()?;
+          }
+        }
+        Ok(UnknownElement(text))
+      }
+    }
+
+    deserializer.deserialize_map(UnknownVisitor)
   }
+}
+
+impl<'de> Deserialize<'de> for InpasResponse {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    struct ResponseVisitor;
+
+    impl<'de> Visitor<'de> for ResponseVisitor {
+      type Value = InpasResponse;
+
+      fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a DualConnector response envelope")
+      }
+
+      fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<InpasResponse, A::Error> {
+        let mut response = InpasResponse::default();
+        while let Some(key) = map.next_key::<String>()? {
+          match key.as_str() {
+            "field" => response.fields.push(map.next_value()?),
+            "errorcode" => response.error_code = Some(map.next_value::<ElementText>()?.text),
+            "errordescription" | "errorDescription" => {
+              response.error_description = Some(map.next_value::<ElementText>()?.text)
+            }
+            _ => {
+              let UnknownElement(text) = map.next_value()?;
+              response.unknown.push((key, text));
+            }
+          }
+        }
+        Ok(response)
+      }
+    }
 
-  if let Some(baudrate) = meta.baudrate {
-    writer.write_event(Event::Start(BytesStart::new("baudrate")))?;
-    writer.write_event(Event::Text(quick_xml::events::BytesText::new(
-      &baudrate.to_string(),
-    )))?;
-    writer.write_event(Event::End(BytesStart::new("baudrate").to_end()))?;
+    deserializer.deserialize_map(ResponseVisitor)
   }
+}
 
-  writer.write_event(Event::End(request_elem.to_end()))?;
+pub fn build_inpas_xml(request: &InpasRequest) -> Result<String, Box<dyn std::error::Error>> {
+  let body = quick_xml::se::to_string(request)?;
+  Ok(format!(
+    "<?xml version=\"1.0\" encoding=\"{}\"?>{}",
+    DEFAULT_ENCODING, body
+  ))
+}
+
+/// Checks known fields against their format and that financial operations
+/// carry an amount and a currency. Fields outside the catalogue pass as is.
+pub fn validate_inpas_fields(fields: &[InpasField]) -> Result<(), String> {
+  for field in fields {
+    let Some(id) = field.field_id() else {
+      continue;
+    };
+    let format = id.format();
+    if !format.validate(&field.value) {
+      return Err(format!(
+        "Inpas field {} ({}): \"{}\" is not a valid {:?} value",
+        id.code(),
+        id.name(),
+        field.value,
+        format
+      ));
+    }
+  }
 
-  let result = writer.into_inner().into_inner();
-  let xml_string = encoding_rs::WINDOWS_1251.decode(&result).0.to_string();
-  Ok(xml_string)
+  let operation = fields
+    .iter()
+    .find(|field| field.field_id() == Some(InpasFieldId::OperationCode))
+    .and_then(|field| field.value.parse().ok())
+    .map(InpasOperation::from_code);
+  if let Some(operation) = operation.filter(InpasOperation::is_financial) {
+    for id in [InpasFieldId::Amount, InpasFieldId::Currency] {
+      if !fields.iter().any(|field| field.field_id() == Some(id)) {
+        return Err(format!(
+          "Inpas operation {:?} requires field {} ({})",
+          operation,
+          id.code(),
+          id.name()
+        ));
+      }
+    }
+  }
+  Ok(())
 }
 
 pub async fn send_inpas_request(
   config: &ConnectionConfig,
   fields: &[InpasField],
 ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-  let dc_host = config
-    .dc_host
-    .as_ref()
-    .ok_or("dcHost property is required for inpas protocol")?;
+  validate_inpas_fields(fields)?;
+  let client = DualConnectorClient::from_config(config)?;
 
-  let mut envelope = EnvelopeOptions {
+  let mut envelope = InpasRequest {
+    fields: fields.to_vec(),
     timeout: config.timeout,
-    ipaddr: None,
-    ncom: None,
-    baudrate: None,
+    ..Default::default()
   };
 
   match config.connection_type {
@@ -116,105 +218,39 @@ pub async fn send_inpas_request(
     _ => {}
   }
 
-  let xml_body = build_inpas_xml(fields, envelope)?;
-  let response = post_xml(dc_host, &xml_body).await?;
+  let xml_body = build_inpas_xml(&envelope)?;
+  let response = client.post_xml(&xml_body).await?;
   parse_inpas_response(&response)
 }
 
-fn parse_inpas_response(xml: &str) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-  use quick_xml::events::Event;
-  use quick_xml::Reader;
-
-  let mut reader = Reader::from_str(xml);
-  reader.trim_text(true);
-
-  let mut data = std::collections::HashMap::new();
-  let mut error_code: Option<String> = None;
-  let mut error_description: Option<String> = None;
-  let mut buf = Vec::new();
-
-  loop {
-    match reader.read_event_into(&mut buf)? {
-      Event::Start(e) => match e.name().as_ref() {
-        b"field" => {
-          let mut id = None;
-          let mut value = String::new();
-
-          for attr in e.attributes() {
-            let attr = attr?;
-            if attr.key.as_ref() == b"id" {
-              id = Some(String::from_utf8_lossy(&attr.value).to_string());
-            }
-          }
-
-          let mut text_buf = Vec::new();
-          loop {
-            match reader.read_event_into(&mut text_buf)? {
-              Event::Text(t) => {
-                value.push_str(&String::from_utf8_lossy(&t.into_inner()));
-              }
-              Event::End(e) if e.name().as_ref() == b"field" => break,
-              _ => {}
-            }
-          }
-
-          if let Some(id) = id {
-            data.insert(format!("{:0>2}", id), value);
-          }
-        }
-        b"errorcode" => {
-          let mut text_buf = Vec::new();
-          loop {
-            match reader.read_event_into(&mut text_buf)? {
-              Event::Text(t) => {
-                error_code = Some(String::from_utf8_lossy(&t.into_inner()).to_string());
-              }
-              Event::End(e) if e.name().as_ref() == b"errorcode" => break,
-              _ => {}
-            }
-          }
-        }
-        b"errordescription" | b"errorDescription" => {
-          let mut text_buf = Vec::new();
-          loop {
-            match reader.read_event_into(&mut text_buf)? {
-              Event::Text(t) => {
-                error_description = Some(String::from_utf8_lossy(&t.into_inner()).to_string());
-              }
-              Event::End(e)
-                if e.name().as_ref() == b"errordescription"
-                  || e.name().as_ref() == b"errorDescription" =>
-              {
-                break
-              }
-              _ => {}
-            }
-          }
-        }
-        _ => {}
-      },
-      Event::Eof => break,
-      _ => {}
-    }
-    buf.clear();
+pub fn parse_inpas_response(xml: &str) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+  let response: InpasResponse = quick_xml::de::from_str(xml)?;
+
+  if let Some(code) = response.error_code.filter(|code| !code.is_empty()) {
+    let error_description = response.error_description.filter(|d| !d.is_empty());
+    let error_msg = error_description
+      .clone()
+      .unwrap_or_else(|| format!("DualConnector error code {}", code));
+    return Ok(TerminalResponse {
+      success: false,
+      error: Some(error_msg),
+      code: Some(code),
+      message: error_description,
+      data: None,
+    });
   }
 
-  if let Some(code) = error_code {
-    if !code.is_empty() {
-      let error_msg = error_description
-        .as_ref()
-        .filter(|d| !d.is_empty())
-        .map(|d| d.clone())
-        .unwrap_or_else(|| format!("DualConnector error code {}", code));
-      return Ok(TerminalResponse {
-        success: false,
-        error: Some(error_msg),
-        code: Some(code),
-        message: error_description,
-        data: None,
-      });
-    }
-  }
+  let mut data: std::collections::HashMap<String, String> = response
+    .fields
+    .into_iter()
+    .map(|field| (format!("{:0>2}", field.id), field.value))
+    .collect();
+  data.extend(
+    response
+      .unknown
+      .into_iter()
+      .map(|(name, value)| (format!("envelope.{}", name), value)),
+  );
 
   Ok(build_terminal_response_from_raw(
     crate::acquiring::types::ProtocolType::Inpas,
@@ -222,60 +258,124 @@ fn parse_inpas_response(xml: &str) -> Result<TerminalResponse, Box<dyn std::erro
   ))
 }
 
-async fn post_xml(url_str: &str, xml_body: &str) -> Result<String, Box<dyn std::error::Error>> {
-  let url = normalize_dc_url(url_str)?;
-  let body_bytes: Vec<u8> = encoding_rs::WINDOWS_1251.encode(xml_body).0.to_vec();
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unknown_elements_are_kept_or_skipped() {
+    let xml = r#"<?xml version="1.0" encoding="windows-1251"?>
+<response>
+  <field id="15">00</field>
+  <version>2.1</version>
+  <session id="7"><opened>1</opened><host>dc</host></session>
+  <field id="19">ОДОБРЕНО</field>
+</response>"#;
+    let response: InpasResponse = quick_xml::de::from_str(xml).unwrap();
+
+    assert_eq!(response.fields.len(), 2);
+    assert_eq!(
+      response.unknown,
+      vec![
+        ("version".to_string(), "2.1".to_string()),
+        ("session".to_string(), String::new()),
+      ]
+    );
+  }
 
-  let client = reqwest::Client::new();
-  let request = client
-    .post(url.clone())
-    .header(
-      "Content-Type",
-      format!("text/xml; charset={}", DEFAULT_ENCODING),
-    )
-    .header("Accept", "text/xml")
-    .header("Accept-Charset", DEFAULT_ENCODING)
-    .header("User-Agent", "corex-ttk2")
-    .body(body_bytes);
-
-  let response = request.send().await?;
-
-  let status = response.status();
-  let content_type_header = response
-    .headers()
-    .get("content-type")
-    .and_then(|h| h.to_str().ok())
-    .map(|s| s.to_string());
-  let bytes = response.bytes().await?;
-
-  if status.is_client_error() || status.is_server_error() {
-    let text = String::from_utf8_lossy(&bytes);
-    return Err(format!("DualConnector HTTP error {}: {}", status.as_u16(), text).into());
+  #[test]
+  fn self_closing_elements() {
+    let xml = r#"<response><field id="15">00</field><field id="10"/><errorcode/><flag/></response>"#;
+    let response: InpasResponse = quick_xml::de::from_str(xml).unwrap();
+
+    assert_eq!(response.fields[1].id, "10");
+    assert_eq!(response.fields[1].value, "");
+    assert_eq!(response.error_code.as_deref(), Some(""));
+    assert_eq!(response.unknown, vec![("flag".to_string(), String::new())]);
+
+    let terminal_response = parse_inpas_response(xml).unwrap();
+    assert!(terminal_response.success);
   }
-  let charset = extract_charset(content_type_header.as_deref());
-  let decoded = match charset {
-    Some("windows-1251") | Some("cp1251") => encoding_rs::WINDOWS_1251.decode(&bytes).0.to_string(),
-    _ => String::from_utf8_lossy(&bytes).to_string(),
-  };
 
-  Ok(decoded)
-}
+  fn fields(fields: &[(InpasFieldId, &str)]) -> Vec<InpasField> {
+    fields
+      .iter()
+      .map(|(id, value)| InpasField::new(*id, *value))
+      .collect()
+  }
 
-fn normalize_dc_url(host: &str) -> Result<reqwest::Url, Box<dyn std::error::Error>> {
-  if host.starts_with("http://") || host.starts_with("https://") {
-    Ok(host.parse()?)
-  } else {
-    Ok(format!("http://{}", host).parse()?)
+  #[test]
+  fn fields_are_validated_against_their_format() {
+    let mut sale = fields(&[
+      (InpasFieldId::Amount, "1000"),
+      (InpasFieldId::Currency, "643"),
+      (InpasFieldId::Timestamp, "20261019120000"),
+      (InpasFieldId::TerminalId, "10285694"),
+    ]);
+    sale.push(InpasField::operation(InpasOperation::Sale));
+    sale.push(InpasField {
+      id: "99".to_string(),
+      value: "anything".to_string(),
+    });
+    assert_eq!(validate_inpas_fields(&sale), Ok(()));
+
+    let mut invalid = sale.clone();
+    invalid[0].value = "10.00".to_string();
+    assert_eq!(
+      validate_inpas_fields(&invalid).unwrap_err(),
+      "Inpas field 00 (Amount): \"10.00\" is not a valid Amount value"
+    );
+    let mut invalid = sale.clone();
+    invalid[1].value = "RUB".to_string();
+    assert!(validate_inpas_fields(&invalid).is_err());
+    let mut invalid = sale.clone();
+    invalid[2].value = "2026-10-19".to_string();
+    assert!(validate_inpas_fields(&invalid).is_err());
   }
-}
 
-fn extract_charset(content_type: Option<&str>) -> Option<&str> {
-  content_type?.split(';').find_map(|part| {
-    let part = part.trim();
-    if part.to_lowercase().starts_with("charset=") {
-      Some(&part[8..])
-    } else {
-      None
-    }
-  })
+  #[test]
+  fn financial_operations_need_amount_and_currency() {
+    let sale = vec![
+      InpasField::new(InpasFieldId::Amount, "1000"),
+      InpasField::operation(InpasOperation::Refund),
+    ];
+    assert_eq!(
+      validate_inpas_fields(&sale).unwrap_err(),
+      "Inpas operation Refund requires field 04 (Currency)"
+    );
+    assert_eq!(
+      validate_inpas_fields(&[InpasField::operation(InpasOperation::Reconciliation)]),
+      Ok(())
+    );
+  }
+
+  #[tokio::test]
+  async fn invalid_requests_are_not_sent() {
+    let config = crate::acquiring::types::ConnectionConfigBuilder::inpas_dc_tcp(
+      "127.0.0.1:1",
+      "127.0.0.1",
+      27015,
+    )
+    .serial_number("1")
+    .build()
+    .unwrap();
+    let sale = vec![
+      InpasField::new(InpasFieldId::Amount, "-5"),
+      InpasField::new(InpasFieldId::Currency, "643"),
+      InpasField::operation(InpasOperation::Sale),
+    ];
+    let error = send_inpas_request(&config, &sale).await.unwrap_err();
+    assert!(error.to_string().starts_with("Inpas field 00 (Amount)"), "{}", error);
+  }
+
+  #[test]
+  fn multi_line_receipt() {
+    let xml = "<response><field id=\"15\">00</field><field id=\"90\">ОПЛАТА\nСУММА: 10.00\n\nПОДПИСЬ НЕ ТРЕБУЕТСЯ</field></response>";
+    let response = parse_inpas_response(xml).unwrap();
+
+    assert_eq!(
+      response.data.unwrap().receipt.as_deref(),
+      Some("ОПЛАТА\nСУММА: 10.00\n\nПОДПИСЬ НЕ ТРЕБУЕТСЯ")
+    );
+  }
 }
@@ -0,0 +1,226 @@
+use crate::acquiring::protocol::inpas::InpasField;
+use std::collections::HashMap;
+
+pub const STX: u8 = 0x02;
+pub const EOT: u8 = 0x04;
+pub const ENQ: u8 = 0x05;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+
+/// One unit of the SA link layer: either a single control byte or a framed
+/// payload. Frames with a bad checksum carry the error instead of the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaUnit {
+  Control(u8),
+  Frame(Result<Vec<u8>, String>),
+}
+
+/// Fields are encoded as a 1-byte ID, a 2-byte little-endian length and the
+/// windows-1251 value.
+pub fn encode_sa_fields(fields: &[InpasField]) -> Result<Vec<u8>, String> {
+  let mut data = Vec::new();
+  for field in fields {
+    let id: u8 = field
+      .id
+      .parse()
+      .map_err(|_| format!("Invalid Inpas field ID: {}", field.id))?;
+    let (value, _, unmappable) = encoding_rs::WINDOWS_1251.encode(&field.value);
+    if unmappable {
+      return Err(format!("Field {} can't be encoded as windows-1251", field.id));
+    }
+    let len = u16::try_from(value.len())
+      .map_err(|_| format!("Field {} is too long: {} bytes", field.id, value.len()))?;
+    data.push(id);
+    data.extend_from_slice(&len.to_le_bytes());
+    data.extend_from_slice(&value);
+  }
+  Ok(data)
+}
+
+pub fn decode_sa_fields(data: &[u8]) -> Result<Vec<InpasField>, String> {
+  let mut fields = Vec::new();
+  let mut offset = 0;
+  while offset < data.len() {
+    if offset + 3 > data.len() {
+      return Err(format!("Truncated field header at offset {}", offset));
+    }
+    let id = data[offset];
+    let len = u16::from_le_bytes([data[offset + 1], data[offset + 2]]) as usize;
+    let start = offset + 3;
+    if start + len > data.len() {
+      return Err(format!(
+        "Field {:02} at offset {} declares {} bytes, only {} left",
+        id,
+        offset,
+        len,
+        data.len() - start
+      ));
+    }
+    fields.push(InpasField {
+      id: format!("{:02}", id),
+      value: encoding_rs::WINDOWS_1251
+        .decode(&data[start..start + len])
+        .0
+        .to_string(),
+    });
+    offset = start + len;
+  }
+  Ok(fields)
+}
+
+pub fn fields_to_raw(fields: Vec<InpasField>) -> HashMap<String, String> {
+  fields
+    .into_iter()
+    .map(|field| (format!("{:0>2}", field.id), field.value))
+    .collect()
+}
+
+/// STX, 2-byte little-endian payload length, payload, then CRC-16/CCITT over
+/// the length and payload, little-endian.
+pub fn build_sa_frame(payload: &[u8]) -> Result<Vec<u8>, String> {
+  let len = u16::try_from(payload.len())
+    .map_err(|_| format!("SA payload is too long: {} bytes", payload.len()))?;
+  let mut frame = Vec::with_capacity(payload.len() + 5);
+  frame.push(STX);
+  frame.extend_from_slice(&len.to_le_bytes());
+  frame.extend_from_slice(payload);
+  let crc = crc16(&frame[1..]);
+  frame.extend_from_slice(&crc.to_le_bytes());
+  Ok(frame)
+}
+
+/// Takes the next complete unit off the front of `buffer`, dropping any
+/// line noise before it. Returns `None` until a whole frame has arrived.
+pub fn take_sa_unit(buffer: &mut Vec<u8>) -> Option<SaUnit> {
+  let start = buffer
+    .iter()
+    .position(|b| matches!(*b, STX | EOT | ENQ | ACK | NAK))?;
+  buffer.drain(..start);
+
+  if buffer[0] != STX {
+    return Some(SaUnit::Control(buffer.remove(0)));
+  }
+  if buffer.len() < 3 {
+    return None;
+  }
+  let len = u16::from_le_bytes([buffer[1], buffer[2]]) as usize;
+  let total = 3 + len + 2;
+  if buffer.len() < total {
+    return None;
+  }
+
+  let frame: Vec<u8> = buffer.drain(..total).collect();
+  let expected = crc16(&frame[1..3 + len]);
+  let actual = u16::from_le_bytes([frame[3 + len], frame[4 + len]]);
+  if expected != actual {
+    return Some(SaUnit::Frame(Err(format!(
+      "SA frame checksum mismatch: expected {:04X}, got {:04X}",
+      expected, actual
+    ))));
+  }
+  Some(SaUnit::Frame(Ok(frame[3..3 + len].to_vec())))
+}
+
+/// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF.
+pub fn crc16(data: &[u8]) -> u16 {
+  let mut crc: u16 = 0xffff;
+  for byte in data {
+    crc ^= (*byte as u16) << 8;
+    for _ in 0..8 {
+      crc = if crc & 0x8000 != 0 {
+        (crc << 1) ^ 0x1021
+      } else {
+        crc << 1
+      };
+    }
+  }
+  crc
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::acquiring::types::InpasFieldId;
+
+  /// Field 00 (amount) = "1000" in an SA frame.
+  const AMOUNT_FRAME: [u8; 12] = [
+    0x02, 0x07, 0x00, 0x00, 0x04, 0x00, 0x31, 0x30, 0x30, 0x30, 0x46, 0x03,
+  ];
+
+  #[test]
+  fn crc_matches_the_ccitt_false_check_value() {
+    assert_eq!(crc16(b"123456789"), 0x29b1);
+  }
+
+  #[test]
+  fn frames_a_known_payload() {
+    let payload = encode_sa_fields(&[InpasField::new(InpasFieldId::Amount, "1000")]).unwrap();
+    assert_eq!(payload, vec![0x00, 0x04, 0x00, 0x31, 0x30, 0x30, 0x30]);
+    assert_eq!(build_sa_frame(&payload).unwrap(), AMOUNT_FRAME.to_vec());
+  }
+
+  #[test]
+  fn units_are_taken_across_noise_and_partial_reads() {
+    let mut buffer = vec![0xff, 0x13, ACK];
+    buffer.extend_from_slice(&AMOUNT_FRAME[..5]);
+    assert_eq!(take_sa_unit(&mut buffer), Some(SaUnit::Control(ACK)));
+    assert_eq!(take_sa_unit(&mut buffer), None);
+
+    buffer.extend_from_slice(&AMOUNT_FRAME[5..]);
+    buffer.push(EOT);
+    assert_eq!(
+      take_sa_unit(&mut buffer),
+      Some(SaUnit::Frame(Ok(vec![0x00, 0x04, 0x00, 0x31, 0x30, 0x30, 0x30])))
+    );
+    assert_eq!(take_sa_unit(&mut buffer), Some(SaUnit::Control(EOT)));
+    assert!(buffer.is_empty());
+  }
+
+  #[test]
+  fn corrupted_frames_carry_the_checksum_error() {
+    let mut buffer = AMOUNT_FRAME.to_vec();
+    buffer[6] = b'9';
+    assert_eq!(
+      take_sa_unit(&mut buffer),
+      Some(SaUnit::Frame(Err(
+        "SA frame checksum mismatch: expected 8685, got 0346".to_string()
+      )))
+    );
+    assert!(buffer.is_empty());
+  }
+
+  #[test]
+  fn fields_round_trip_in_windows_1251() {
+    let fields = vec![
+      InpasField::new(InpasFieldId::ResponseCode, "00"),
+      InpasField::new(InpasFieldId::TextResponse, "ОДОБРЕНО"),
+      InpasField::new(InpasFieldId::Receipt, ""),
+    ];
+    let payload = encode_sa_fields(&fields).unwrap();
+    assert_eq!(&payload[5..8], &[19, 8, 0]);
+
+    let decoded = decode_sa_fields(&payload).unwrap();
+    let raw = fields_to_raw(decoded);
+    assert_eq!(raw["15"], "00");
+    assert_eq!(raw["19"], "ОДОБРЕНО");
+    assert_eq!(raw["90"], "");
+  }
+
+  #[test]
+  fn malformed_fields_are_rejected() {
+    assert_eq!(
+      decode_sa_fields(&[0x0f, 0x02]).unwrap_err(),
+      "Truncated field header at offset 0"
+    );
+    assert_eq!(
+      decode_sa_fields(&[0x0f, 0x05, 0x00, b'0']).unwrap_err(),
+      "Field 15 at offset 0 declares 5 bytes, only 1 left"
+    );
+    let field = InpasField {
+      id: "x".to_string(),
+      value: String::new(),
+    };
+    assert_eq!(encode_sa_fields(&[field]).unwrap_err(), "Invalid Inpas field ID: x");
+    assert!(encode_sa_fields(&[InpasField::new(InpasFieldId::Receipt, "日本")]).is_err());
+  }
+}
@@ -0,0 +1,203 @@
+use crate::acquiring::protocol::{TlvItem, TlvValue, TtkBuffer};
+use crate::acquiring::types::{get_tag_definition, DataType, MessageType, TagDefinition};
+
+pub trait TtkMessage: Sized {
+    fn to_items(&self) -> Result<Vec<TlvItem>, String>;
+    fn from_items(items: &[TlvItem]) -> Result<Self, String>;
+
+    fn encode(&self, message_type: MessageType) -> Result<Vec<u8>, String> {
+        Ok(TtkBuffer::create_message(message_type, &self.to_items()?))
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, String> {
+        let (_, items) = TtkBuffer::parse_message(data)?;
+        Self::from_items(&items)
+    }
+}
+
+pub trait TtkField: Sized {
+    fn to_value(&self) -> Option<TlvValue>;
+    fn from_value(value: Option<TlvValue>) -> Result<Self, String>;
+}
+
+impl TtkField for u64 {
+    fn to_value(&self) -> Option<TlvValue> {
+        Some(TlvValue::Number(*self))
+    }
+
+    fn from_value(value: Option<TlvValue>) -> Result<Self, String> {
+        let value = value.ok_or("missing value")?;
+        value
+            .as_u64()
+            .ok_or_else(|| format!("{:?} is not numeric", value))
+    }
+}
+
+impl TtkField for u32 {
+    fn to_value(&self) -> Option<TlvValue> {
+        Some(TlvValue::Number(*self as u64))
+    }
+
+    fn from_value(value: Option<TlvValue>) -> Result<Self, String> {
+        let number = u64::from_value(value)?;
+        u32::try_from(number).map_err(|_| format!("{} does not fit into u32", number))
+    }
+}
+
+impl TtkField for String {
+    fn to_value(&self) -> Option<TlvValue> {
+        Some(TlvValue::Text(self.clone()))
+    }
+
+    fn from_value(value: Option<TlvValue>) -> Result<Self, String> {
+        match value.ok_or("missing value")? {
+            TlvValue::Text(text) | TlvValue::Digits(text) => Ok(text),
+            TlvValue::Number(number) => Ok(number.to_string()),
+            TlvValue::Bytes(bytes) => Ok(String::from_utf8_lossy(&bytes).to_string()),
+        }
+    }
+}
+
+impl TtkField for Vec<u8> {
+    fn to_value(&self) -> Option<TlvValue> {
+        Some(TlvValue::Bytes(self.clone()))
+    }
+
+    fn from_value(value: Option<TlvValue>) -> Result<Self, String> {
+        match value.ok_or("missing value")? {
+            TlvValue::Bytes(bytes) => Ok(bytes),
+            other => Err(format!("{:?} is not binary", other)),
+        }
+    }
+}
+
+impl<T: TtkField> TtkField for Option<T> {
+    fn to_value(&self) -> Option<TlvValue> {
+        self.as_ref().and_then(T::to_value)
+    }
+
+    fn from_value(value: Option<TlvValue>) -> Result<Self, String> {
+        value.map(|v| T::from_value(Some(v))).transpose()
+    }
+}
+
+/// Definition used for a declared field: the data type comes from the
+/// `#[ttk(...)]` attribute, name and text encoding from the tag registry.
+pub fn field_definition(tag: u32, data_type: DataType) -> TagDefinition {
+    match get_tag_definition(tag) {
+        Some(definition) => TagDefinition {
+            data_type,
+            ..definition.clone()
+        },
+        None => TagDefinition {
+            tag,
+            name: format!("TAG_{:X}", tag),
+            data_type,
+            encoding: None,
+        },
+    }
+}
+
+pub fn encode_field<T: TtkField>(
+    items: &mut Vec<TlvItem>,
+    field: &T,
+    tag: u32,
+    data_type: DataType,
+    len: Option<usize>,
+) -> Result<(), String> {
+    if let Some(value) = field.to_value() {
+        let definition = field_definition(tag, data_type);
+        items.push(TlvItem::new(tag, value.encode(&definition, len)?));
+    }
+    Ok(())
+}
+
+pub fn decode_field<T: TtkField>(
+    items: &[TlvItem],
+    tag: u32,
+    data_type: DataType,
+) -> Result<T, String> {
+    let definition = field_definition(tag, data_type);
+    let value = items
+        .iter()
+        .find(|item| item.tag == tag)
+        .map(|item| TlvValue::decode(&definition, &item.value))
+        .transpose()?;
+    T::from_value(value).map_err(|e| format!("{} (0x{:X}): {}", definition.name, tag, e))
+}
+
+/// Declares a TTK message struct and implements [`TtkMessage`] for it.
+///
+/// ```ignore
+/// ttk_message! {
+///     pub struct PurchaseRequest {
+///         #[ttk(tag = 0x01, ascii)]
+///         pub message_id: String,
+///         #[ttk(tag = 0x04, bcd, len = 12)]
+///         pub amount: u64,
+///         #[ttk(tag = 0x1b, bcd, len = 3)]
+///         pub currency: Option<u64>,
+///     }
+/// }
+/// ```
+///
+/// Supported kinds are `ascii`, `bcd`, `hex`, `binary`, `dword_le` and
+/// `dword_be`. `Option` fields are skipped when `None` and absent tags decode
+/// to `None`.
+#[macro_export]
+macro_rules! ttk_message {
+    (@kind ascii) => { $crate::acquiring::types::DataType::String };
+    (@kind bcd) => { $crate::acquiring::types::DataType::Bcd };
+    (@kind hex) => { $crate::acquiring::types::DataType::Hex };
+    (@kind binary) => { $crate::acquiring::types::DataType::Binary };
+    (@kind dword_le) => { $crate::acquiring::types::DataType::DwordLe };
+    (@kind dword_be) => { $crate::acquiring::types::DataType::DwordBe };
+    (@len) => { None };
+    (@len $len:expr) => { Some($len) };
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                #[ttk(tag = $tag:expr, $kind:ident $(, len = $len:expr)?)]
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty,)*
+        }
+
+        impl $crate::acquiring::protocol::message::TtkMessage for $name {
+            fn to_items(
+                &self,
+            ) -> Result<Vec<$crate::acquiring::protocol::TlvItem>, String> {
+                let mut items = Vec::new();
+                $(
+                    $crate::acquiring::protocol::message::encode_field(
+                        &mut items,
+                        &self.$field,
+                        $tag,
+                        $crate::ttk_message!(@kind $kind),
+                        $crate::ttk_message!(@len $($len)?),
+                    )?;
+                )*
+                Ok(items)
+            }
+
+            fn from_items(
+                items: &[$crate::acquiring::protocol::TlvItem],
+            ) -> Result<Self, String> {
+                Ok(Self {
+                    $(
+                        $field: $crate::acquiring::protocol::message::decode_field(
+                            items,
+                            $tag,
+                            $crate::ttk_message!(@kind $kind),
+                        )?,
+                    )*
+                })
+            }
+        }
+    };
+}
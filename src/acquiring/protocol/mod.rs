@@ -1,8 +1,21 @@
 pub mod buffer;
+pub mod dual_connector;
+pub mod external;
 pub mod inpas;
+pub mod inpas_sa;
+pub mod message;
+pub mod sber;
 pub mod tlv;
+pub mod value;
 
 pub use buffer::TtkBuffer;
-pub use inpas::{build_inpas_xml, send_inpas_request, InpasField};
-pub use tlv::{TlvEncoder, TlvItem};
+pub use dual_connector::DualConnectorClient;
+pub use external::{ExternalProcessProtocol, ExternalRequest};
+pub use inpas::{
+    build_inpas_xml, parse_inpas_response, send_inpas_request, InpasField, InpasRequest,
+    InpasResponse,
+};
+pub use message::TtkMessage;
+pub use tlv::{TlvEncoder, TlvItem, TlvNode};
+pub use value::TlvValue;
 
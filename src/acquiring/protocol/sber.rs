@@ -0,0 +1,225 @@
+use crate::acquiring::response::build_terminal_response_from_raw;
+use crate::acquiring::types::{ConnectionConfig, ProtocolType, TerminalResponse};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+const DEFAULT_PILOT: &str = "sb_pilot";
+/// Default for `SberConfig::timeout`.
+const DEFAULT_TIMEOUT_MS: u32 = 120000;
+const RESULT_FILE: &str = "e";
+const SLIP_FILE: &str = "p";
+
+/// Lines of the `e` file after the `code,text` header, in order.
+const RESULT_LINES: &[&str] = &[
+    "PAN",
+    "Expiry Date",
+    "Authorization Code",
+    "Operation Number",
+    "Card Type",
+    "Sberbank Card",
+    "Terminal ID",
+    "Timestamp",
+    "RRN",
+    "Card Hash",
+];
+
+/// Operation numbers passed as the first sb_pilot argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SberOperation {
+    Sale,
+    Refund,
+    CloseDay,
+    Cancel,
+    ShortReport,
+    FullReport,
+}
+
+impl SberOperation {
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::Sale => 1,
+            Self::Refund => 3,
+            Self::CloseDay => 7,
+            Self::Cancel => 8,
+            Self::ShortReport => 9,
+            Self::FullReport => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SberRequest {
+    pub operation: SberOperation,
+    /// Amount in kopecks, for operations that take one.
+    pub amount: Option<u64>,
+}
+
+impl SberRequest {
+    pub fn new(operation: SberOperation, amount: Option<u64>) -> Self {
+        Self { operation, amount }
+    }
+
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![self.operation.code().to_string()];
+        if let Some(amount) = self.amount {
+            args.push(amount.to_string());
+        }
+        args
+    }
+}
+
+pub fn pilot_path(config: &ConnectionConfig) -> PathBuf {
+    config
+        .sber
+        .as_ref()
+        .and_then(|sber| sber.pilot_path.clone())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_PILOT))
+}
+
+/// Directory sb_pilot writes `e` and `p` into: the configured one, else the
+/// directory of the executable.
+pub fn work_dir(config: &ConnectionConfig) -> PathBuf {
+    if let Some(dir) = config.sber.as_ref().and_then(|sber| sber.work_dir.clone()) {
+        return PathBuf::from(dir);
+    }
+    pilot_path(config)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub async fn run_sb_pilot(
+    config: &ConnectionConfig,
+    request: &SberRequest,
+) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+    let pilot = pilot_path(config);
+    let dir = work_dir(config);
+    for name in [RESULT_FILE, SLIP_FILE] {
+        match std::fs::remove_file(dir.join(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+
+    let mut child = Command::new(&pilot)
+        .args(request.args())
+        .current_dir(&dir)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", pilot.display(), e))?;
+
+    let timeout_ms = config
+        .sber
+        .as_ref()
+        .and_then(|sber| sber.timeout)
+        .unwrap_or(DEFAULT_TIMEOUT_MS);
+    let status = match timeout(Duration::from_millis(timeout_ms as u64), child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            let _ = child.kill().await;
+            return Err(format!("sb_pilot did not finish within {} ms", timeout_ms).into());
+        }
+    };
+
+    let result = std::fs::read(dir.join(RESULT_FILE)).map_err(|e| {
+        format!(
+            "sb_pilot exited with {} without a result file: {}",
+            status, e
+        )
+    })?;
+    let slip = std::fs::read(dir.join(SLIP_FILE)).ok();
+
+    let mut raw = parse_result_file(&decode_text(&result))?;
+    if let Some(slip) = slip {
+        raw.insert("Receipt".to_string(), decode_text(&slip));
+    }
+    if let Some(amount) = request.amount {
+        raw.insert("Amount".to_string(), amount.to_string());
+    }
+    raw.insert("Operation".to_string(), request.operation.code().to_string());
+
+    Ok(build_terminal_response_from_raw(ProtocolType::Sber, raw))
+}
+
+/// The first line is `code,text`; the following lines are positional, see
+/// `RESULT_LINES`. Empty lines are skipped, extra lines are kept by number.
+pub fn parse_result_file(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut lines = text.lines();
+    let header = lines
+        .next()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .ok_or("sb_pilot result file is empty")?;
+
+    let (code, message) = header.split_once(',').unwrap_or((header, ""));
+    let mut raw = HashMap::new();
+    raw.insert("Result Code".to_string(), code.trim().to_string());
+    if !message.trim().is_empty() {
+        raw.insert("Result Text".to_string(), message.trim().to_string());
+    }
+
+    for (index, line) in lines.enumerate() {
+        let value = line.trim();
+        if value.is_empty() {
+            continue;
+        }
+        let key = RESULT_LINES
+            .get(index)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("Line {}", index + 2));
+        raw.insert(key, value.to_string());
+    }
+    Ok(raw)
+}
+
+/// UPOS writes windows-1251 on Windows and UTF-8 on newer Linux builds.
+fn decode_text(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1251.decode(data).0.to_string(),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::acquiring::types::{ConnectionConfigBuilder, ConnectionType, SberConfig};
+    use std::os::unix::fs::PermissionsExt;
+
+    #[tokio::test]
+    async fn read_timeout_does_not_limit_sb_pilot() {
+        let dir = std::env::temp_dir().join(format!("sb-pilot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pilot = dir.join("sb_pilot");
+        std::fs::write(&pilot, "#!/bin/sh\nsleep 0.3\nprintf '0,ОДОБРЕНО\\n' > e\n").unwrap();
+        std::fs::set_permissions(&pilot, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut sber = SberConfig {
+            pilot_path: Some(pilot.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let config = ConnectionConfigBuilder::new(ProtocolType::Sber, ConnectionType::Usb)
+            .timeout(50)
+            .sber(sber.clone())
+            .build()
+            .unwrap();
+        let request = SberRequest::new(SberOperation::Sale, Some(1000));
+
+        let response = run_sb_pilot(&config, &request).await.unwrap();
+        assert!(response.success);
+
+        sber.timeout = Some(50);
+        let config = ConnectionConfigBuilder::new(ProtocolType::Sber, ConnectionType::Usb)
+            .sber(sber)
+            .build()
+            .unwrap();
+        let error = run_sb_pilot(&config, &request).await.unwrap_err();
+        assert_eq!(error.to_string(), "sb_pilot did not finish within 50 ms");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
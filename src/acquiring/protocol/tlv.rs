@@ -1,4 +1,5 @@
-use crate::acquiring::types::{get_tag_definition, DataType, Encoding, TagDefinition};
+use crate::acquiring::protocol::value::TlvValue;
+use crate::acquiring::types::{get_tag_definition, DataType, TagDefinition};
 
 #[derive(Debug, Clone)]
 pub struct TlvItem {
@@ -8,6 +9,93 @@ pub struct TlvItem {
     pub definition: Option<&'static TagDefinition>,
 }
 
+impl TlvItem {
+    pub fn new(tag: u32, value: Vec<u8>) -> Self {
+        Self {
+            tag,
+            length: value.len(),
+            value,
+            definition: get_tag_definition(tag),
+        }
+    }
+
+    /// Packed BCD value of exactly `len` digits, left-padded with zeros.
+    pub fn bcd(tag: u32, value: u64, len: usize) -> Result<Self, String> {
+        Self::typed(tag, DataType::Bcd, TlvValue::Number(value), Some(len))
+    }
+
+    pub fn ascii(tag: u32, value: &str) -> Result<Self, String> {
+        Self::typed(tag, DataType::String, TlvValue::Text(value.to_string()), None)
+    }
+
+    pub fn dword_le(tag: u32, value: u32) -> Result<Self, String> {
+        Self::typed(tag, DataType::DwordLe, TlvValue::Number(value as u64), None)
+    }
+
+    pub fn typed(
+        tag: u32,
+        data_type: DataType,
+        value: TlvValue,
+        len: Option<usize>,
+    ) -> Result<Self, String> {
+        let bytes = match get_tag_definition(tag) {
+            Some(definition) => value.encode(definition, len)?,
+            None => {
+                let definition = TagDefinition {
+                    tag,
+                    name: format!("TAG_{:X}", tag),
+                    data_type,
+                    encoding: None,
+                };
+                value.encode(&definition, len)?
+            }
+        };
+        Ok(Self::new(tag, bytes))
+    }
+
+    pub fn typed_value(&self) -> Result<TlvValue, String> {
+        let definition = self
+            .definition
+            .ok_or_else(|| format!("Tag 0x{:X} has no definition", self.tag))?;
+        TlvValue::decode(definition, &self.value)
+    }
+
+    pub fn as_u64(&self) -> Result<u64, String> {
+        self.typed_value()?
+            .as_u64()
+            .ok_or_else(|| format!("Tag 0x{:X} is not numeric", self.tag))
+    }
+
+    pub fn as_str(&self) -> Result<String, String> {
+        let value = self.typed_value()?;
+        value
+            .as_str()
+            .map(|s| s.to_string())
+            .ok_or_else(|| format!("Tag 0x{:X} is not textual", self.tag))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TlvNode {
+    pub offset: usize,
+    pub value_offset: usize,
+    pub constructed: bool,
+    pub item: TlvItem,
+    pub children: Vec<TlvNode>,
+}
+
+impl TlvNode {
+    pub fn find(&self, tag: u32) -> Option<&TlvNode> {
+        self.children.iter().find_map(|child| {
+            if child.item.tag == tag {
+                Some(child)
+            } else {
+                child.find(tag)
+            }
+        })
+    }
+}
+
 pub struct TlvEncoder;
 
 impl TlvEncoder {
@@ -22,18 +110,9 @@ impl TlvEncoder {
     }
 
     fn encode_tag(tag: u32) -> Vec<u8> {
-        if tag <= 0xff {
-            vec![tag as u8]
-        } else if tag <= 0xffff {
-            vec![((tag >> 8) & 0xff) as u8, (tag & 0xff) as u8]
-        } else {
-            vec![
-                ((tag >> 24) & 0xff) as u8,
-                ((tag >> 16) & 0xff) as u8,
-                ((tag >> 8) & 0xff) as u8,
-                (tag & 0xff) as u8,
-            ]
-        }
+        let bytes = tag.to_be_bytes();
+        let skip = bytes.iter().take(3).take_while(|b| **b == 0).count();
+        bytes[skip..].to_vec()
     }
 
     fn encode_length(length: usize) -> Vec<u8> {
@@ -47,68 +126,144 @@ impl TlvEncoder {
                 ((length >> 8) & 0xff) as u8,
                 (length & 0xff) as u8,
             ]
-        } else {
+        } else if length <= 0xffffff {
             vec![
                 0x83,
                 ((length >> 16) & 0xff) as u8,
                 ((length >> 8) & 0xff) as u8,
                 (length & 0xff) as u8,
             ]
+        } else {
+            vec![
+                0x84,
+                ((length >> 24) & 0xff) as u8,
+                ((length >> 16) & 0xff) as u8,
+                ((length >> 8) & 0xff) as u8,
+                (length & 0xff) as u8,
+            ]
         }
     }
 
+    pub fn encode_node(node: &TlvNode) -> Vec<u8> {
+        if !node.constructed {
+            return Self::encode(node.item.tag, &node.item.value);
+        }
+
+        let value: Vec<u8> = node.children.iter().flat_map(Self::encode_node).collect();
+        Self::encode(node.item.tag, &value)
+    }
+
     pub fn decode(data: &[u8]) -> Result<Vec<TlvItem>, String> {
         let mut items = Vec::new();
         let mut offset = 0;
 
         while offset < data.len() {
-            let (tag, tag_length) = Self::decode_tag(data, offset)?;
-            offset += tag_length;
+            let (item, next) = Self::decode_item(data, offset)?;
+            items.push(item);
+            offset = next;
+        }
+
+        Ok(items)
+    }
+
+    /// Decodes `data` into a tree, descending into constructed tags (bit 0x20
+    /// of the first tag byte). Tags registered as primitive are kept opaque,
+    /// since TTK reuses 0xA0..0xA2 for plain values.
+    pub fn decode_tree(data: &[u8]) -> Result<Vec<TlvNode>, String> {
+        Self::decode_nodes(data, 0, data.len())
+    }
 
-            let (length, length_length) = Self::decode_length(data, offset)?;
-            offset += length_length;
+    fn decode_nodes(data: &[u8], start: usize, end: usize) -> Result<Vec<TlvNode>, String> {
+        let mut nodes = Vec::new();
+        let mut offset = start;
 
-            if offset + length > data.len() {
-                return Err("Invalid TLV data: length exceeds buffer".to_string());
-            }
+        while offset < end {
+            let (item, next) = Self::decode_item(&data[..end], offset)?;
+            let value_offset = next - item.length;
+
+            let constructed = Self::is_constructed(item.tag) && item.definition.is_none();
+            let children = if constructed {
+                Self::decode_nodes(data, value_offset, next)?
+            } else {
+                Vec::new()
+            };
 
-            let value = data[offset..offset + length].to_vec();
-            offset += length;
+            nodes.push(TlvNode {
+                offset,
+                value_offset,
+                constructed,
+                item,
+                children,
+            });
+            offset = next;
+        }
+
+        Ok(nodes)
+    }
 
-            let definition = get_tag_definition(tag);
+    fn decode_item(data: &[u8], offset: usize) -> Result<(TlvItem, usize), String> {
+        let (tag, tag_length) = Self::decode_tag(data, offset)?;
+        let length_offset = offset + tag_length;
 
-            items.push(TlvItem {
+        let (length, length_length) = Self::decode_length(data, length_offset)?;
+        let value_offset = length_offset + length_length;
+
+        if value_offset + length > data.len() {
+            return Err(format!(
+                "Invalid TLV data: tag 0x{:X} at offset {} declares {} bytes, only {} available",
                 tag,
+                offset,
                 length,
-                value,
-                definition,
-            });
+                data.len() - value_offset
+            ));
         }
 
-        Ok(items)
+        let item = TlvItem {
+            tag,
+            length,
+            value: data[value_offset..value_offset + length].to_vec(),
+            definition: get_tag_definition(tag),
+        };
+
+        Ok((item, value_offset + length))
+    }
+
+    pub fn is_constructed(tag: u32) -> bool {
+        let first_byte = Self::encode_tag(tag)[0];
+        (first_byte & 0x20) == 0x20
     }
 
     fn decode_tag(data: &[u8], offset: usize) -> Result<(u32, usize), String> {
         if offset >= data.len() {
-            return Err("Invalid TLV data: offset out of bounds".to_string());
+            return Err(format!("Invalid TLV data: missing tag at offset {}", offset));
         }
 
         let mut tag = data[offset] as u32;
         let mut tag_length = 1;
 
         if (tag & 0x1f) == 0x1f {
-            if offset + 1 >= data.len() {
-                return Err("Invalid TLV data: incomplete tag".to_string());
-            }
-            tag_length = 2;
-            tag = (tag << 8) | (data[offset + 1] as u32);
+            loop {
+                if tag_length == 4 {
+                    return Err(format!(
+                        "Invalid TLV data: tag at offset {} exceeds 4 bytes",
+                        offset
+                    ));
+                }
+                let position = offset + tag_length;
+                if position >= data.len() {
+                    return Err(format!(
+                        "Invalid TLV data: incomplete tag at offset {}",
+                        offset
+                    ));
+                }
+
+                let byte = data[position];
+                tag = (tag << 8) | (byte as u32);
+                tag_length += 1;
 
-            if (data[offset + 1] & 0x80) == 0x80 {
-                if offset + 2 >= data.len() {
-                    return Err("Invalid TLV data: incomplete tag".to_string());
+                if (byte & 0x80) == 0 {
+                    break;
                 }
-                tag_length = 3;
-                tag = (tag << 8) | (data[offset + 2] as u32);
             }
         }
 
@@ -117,26 +272,41 @@ impl TlvEncoder {
 
     fn decode_length(data: &[u8], offset: usize) -> Result<(usize, usize), String> {
         if offset >= data.len() {
-            return Err("Invalid TLV data: offset out of bounds".to_string());
+            return Err(format!("Invalid TLV data: missing length at offset {}", offset));
         }
 
         let first_byte = data[offset];
 
         if (first_byte & 0x80) == 0 {
-            Ok((first_byte as usize, 1))
-        } else {
-            let num_bytes = (first_byte & 0x7f) as usize;
-            if offset + num_bytes >= data.len() {
-                return Err("Invalid TLV data: incomplete length".to_string());
-            }
+            return Ok((first_byte as usize, 1));
+        }
 
-            let mut length = 0usize;
-            for i in 0..num_bytes {
-                length = (length << 8) | (data[offset + 1 + i] as usize);
-            }
+        let num_bytes = (first_byte & 0x7f) as usize;
+        if num_bytes == 0 {
+            return Err(format!(
+                "Invalid TLV data: indefinite length at offset {} is not supported",
+                offset
+            ));
+        }
+        if num_bytes > 4 {
+            return Err(format!(
+                "Invalid TLV data: length at offset {} uses {} bytes, at most 4 are supported",
+                offset, num_bytes
+            ));
+        }
+        if offset + num_bytes >= data.len() {
+            return Err(format!(
+                "Invalid TLV data: incomplete length at offset {}",
+                offset
+            ));
+        }
 
-            Ok((length, 1 + num_bytes))
+        let mut length = 0usize;
+        for i in 0..num_bytes {
+            length = (length << 8) | (data[offset + 1 + i] as usize);
         }
+
+        Ok((length, 1 + num_bytes))
     }
 
     pub fn value_to_string(item: &TlvItem) -> String {
@@ -144,37 +314,11 @@ impl TlvEncoder {
             return Self::bytes_to_hex(&item.value);
         };
 
-        match def.data_type {
-            DataType::String => {
-                let encoding = def.encoding.unwrap_or(Encoding::Ascii);
-                match encoding {
-                    Encoding::Cp1251 => {
-                        encoding_rs::WINDOWS_1251
-                            .decode(&item.value)
-                            .0
-                            .to_string()
-                    }
-                    Encoding::Cp866 => {
-                        encoding_rs::IBM866.decode(&item.value).0.to_string()
-                    }
-                    Encoding::Ascii => String::from_utf8_lossy(&item.value).to_string(),
-                }
-            }
-            DataType::Bcd => item
-                .value
-                .iter()
-                .map(|byte| format!("{:02}", byte))
-                .collect::<String>(),
-            DataType::Hex => Self::bytes_to_hex(&item.value),
-            DataType::DwordLe => {
-                let mut bytes = item.value.iter().take(4).copied().collect::<Vec<_>>();
-                bytes.reverse();
-                format!("0x{}", Self::bytes_to_hex(&bytes))
-            }
-            DataType::DwordBe => {
-                format!("0x{}", Self::bytes_to_hex(&item.value.iter().take(4).copied().collect::<Vec<_>>()))
-            }
-            DataType::Binary => Self::bytes_to_hex(&item.value),
+        match TlvValue::decode(def, &item.value) {
+            Ok(TlvValue::Text(text)) | Ok(TlvValue::Digits(text)) => text,
+            Ok(TlvValue::Number(number)) => format!("0x{:08X}", number),
+            Ok(TlvValue::Bytes(bytes)) => Self::bytes_to_hex(&bytes),
+            Err(_) => Self::bytes_to_hex(&item.value),
         }
     }
 
@@ -183,3 +327,120 @@ impl TlvEncoder {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_of_one_to_four_bytes_round_trip() {
+        for (tag, bytes) in [
+            (0x9b, vec![0x9b]),
+            (0x9f26, vec![0x9f, 0x26]),
+            (0x1f8101, vec![0x1f, 0x81, 0x01]),
+            (0xdf818202, vec![0xdf, 0x81, 0x82, 0x02]),
+        ] {
+            assert_eq!(TlvEncoder::encode_tag(tag), bytes);
+            let encoded = TlvEncoder::encode(tag, &[0x42]);
+            let items = TlvEncoder::decode(&encoded).unwrap();
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].tag, tag);
+            assert_eq!(items[0].value, vec![0x42]);
+        }
+    }
+
+    #[test]
+    fn long_lengths_round_trip() {
+        for length in [0x7f, 0x80, 0xff, 0x100, 0x10000] {
+            let value = vec![0x30; length];
+            let items = TlvEncoder::decode(&TlvEncoder::encode(0x9c, &value)).unwrap();
+            assert_eq!(items[0].length, length);
+            assert_eq!(items[0].value, value);
+        }
+    }
+
+    #[test]
+    fn constructed_tags_decode_into_a_tree() {
+        let pan = TlvEncoder::encode(0x5a, &[0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02]);
+        let record = TlvEncoder::encode(0x70, &pan);
+        let cryptogram = TlvEncoder::encode(0x9f26, &[0x11; 8]);
+        let template = TlvEncoder::encode(0x77, &[cryptogram, record].concat());
+
+        let tree = TlvEncoder::decode_tree(&template).unwrap();
+        assert_eq!(tree.len(), 1);
+        let root = &tree[0];
+        assert!(root.constructed);
+        assert_eq!(root.item.tag, 0x77);
+        assert_eq!(root.value_offset, 2);
+        assert_eq!(
+            root.children.iter().map(|child| child.item.tag).collect::<Vec<_>>(),
+            vec![0x9f26, 0x70]
+        );
+
+        let record = root.find(0x70).unwrap();
+        assert!(record.constructed);
+        assert_eq!(record.offset, 13);
+        let pan = root.find(0x5a).unwrap();
+        assert!(!pan.constructed);
+        assert_eq!(pan.offset, 15);
+        assert_eq!(pan.item.length, 8);
+
+        assert_eq!(TlvEncoder::encode_node(root), template);
+    }
+
+    #[test]
+    fn registered_primitive_tags_stay_opaque() {
+        // 0xA0 has the constructed bit but TTK uses it for a text value.
+        let data = TlvEncoder::encode(0xa0, b"APPROVED");
+        let tree = TlvEncoder::decode_tree(&data).unwrap();
+        assert!(!tree[0].constructed);
+        assert!(tree[0].children.is_empty());
+        assert_eq!(TlvEncoder::value_to_string(&tree[0].item), "APPROVED");
+    }
+
+    #[test]
+    fn malformed_data_is_rejected_with_offsets() {
+        let error = TlvEncoder::decode(&[0x9b, 0x80, 0x00, 0x00]).unwrap_err();
+        assert_eq!(error, "Invalid TLV data: indefinite length at offset 1 is not supported");
+
+        let mut data = TlvEncoder::encode(0x9b, b"00");
+        data.push(0x9f);
+        assert_eq!(
+            TlvEncoder::decode(&data).unwrap_err(),
+            "Invalid TLV data: incomplete tag at offset 4"
+        );
+
+        let mut data = TlvEncoder::encode(0x9b, b"00");
+        data.push(0x9b);
+        assert_eq!(
+            TlvEncoder::decode(&data).unwrap_err(),
+            "Invalid TLV data: missing length at offset 5"
+        );
+
+        let mut data = TlvEncoder::encode(0x9b, b"00");
+        data.extend([0xa0, 0x05, b'O', b'K']);
+        assert_eq!(
+            TlvEncoder::decode(&data).unwrap_err(),
+            "Invalid TLV data: tag 0xA0 at offset 4 declares 5 bytes, only 2 available"
+        );
+
+        assert_eq!(
+            TlvEncoder::decode(&[0x9b, 0x85, 0, 0, 0, 0, 1]).unwrap_err(),
+            "Invalid TLV data: length at offset 1 uses 5 bytes, at most 4 are supported"
+        );
+        assert_eq!(
+            TlvEncoder::decode(&[0xdf, 0x81, 0x82, 0x83, 0x04, 0x00]).unwrap_err(),
+            "Invalid TLV data: tag at offset 0 exceeds 4 bytes"
+        );
+    }
+
+    #[test]
+    fn constructed_children_cannot_overrun_their_parent() {
+        // The child claims 4 bytes but the parent only holds 3.
+        let data = [0x70, 0x03, 0x5a, 0x04, 0x40, 0x00, 0x00];
+        assert_eq!(
+            TlvEncoder::decode_tree(&data).unwrap_err(),
+            "Invalid TLV data: tag 0x5A at offset 2 declares 4 bytes, only 1 available"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use crate::acquiring::types::{DataType, Encoding, TagDefinition};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlvValue {
+    Text(String),
+    Digits(String),
+    Number(u64),
+    Bytes(Vec<u8>),
+}
+
+impl TlvValue {
+    /// Encodes the value according to `definition`. For BCD tags `len` is the
+    /// number of digits; the result is packed two digits per byte.
+    pub fn encode(&self, definition: &TagDefinition, len: Option<usize>) -> Result<Vec<u8>, String> {
+        match (definition.data_type, self) {
+            (DataType::Bcd, TlvValue::Number(number)) => {
+                Self::encode_bcd(&number.to_string(), len, definition)
+            }
+            (DataType::Bcd, TlvValue::Digits(digits) | TlvValue::Text(digits)) => {
+                Self::encode_bcd(digits, len, definition)
+            }
+            (DataType::String, TlvValue::Text(text)) => {
+                let encoding = definition.encoding.unwrap_or(Encoding::Ascii);
+                let bytes = Self::encode_text(text, encoding, definition)?;
+                if let Some(len) = len.filter(|len| bytes.len() > *len) {
+                    return Err(format!(
+                        "{}: \"{}\" does not fit into {} bytes",
+                        definition.name, text, len
+                    ));
+                }
+                Ok(bytes)
+            }
+            (DataType::DwordLe | DataType::DwordBe, TlvValue::Number(number)) => {
+                let number = u32::try_from(*number).map_err(|_| {
+                    format!("{}: {} does not fit into a DWORD", definition.name, number)
+                })?;
+                if definition.data_type == DataType::DwordLe {
+                    Ok(number.to_le_bytes().to_vec())
+                } else {
+                    Ok(number.to_be_bytes().to_vec())
+                }
+            }
+            (DataType::Hex | DataType::Binary, TlvValue::Bytes(bytes)) => Ok(bytes.clone()),
+            (data_type, value) => Err(format!(
+                "{}: cannot encode {:?} as {:?}",
+                definition.name, value, data_type
+            )),
+        }
+    }
+
+    pub fn decode(definition: &TagDefinition, bytes: &[u8]) -> Result<Self, String> {
+        match definition.data_type {
+            DataType::String => {
+                let text = match definition.encoding.unwrap_or(Encoding::Ascii) {
+                    Encoding::Cp1251 => encoding_rs::WINDOWS_1251.decode(bytes).0.to_string(),
+                    Encoding::Cp866 => encoding_rs::IBM866.decode(bytes).0.to_string(),
+                    Encoding::Ascii => String::from_utf8_lossy(bytes).to_string(),
+                };
+                Ok(TlvValue::Text(text))
+            }
+            DataType::Bcd => {
+                let mut digits = String::with_capacity(bytes.len() * 2);
+                for byte in bytes {
+                    for nibble in [byte >> 4, byte & 0x0f] {
+                        if nibble > 9 {
+                            return Err(format!(
+                                "{}: invalid BCD byte 0x{:02X}",
+                                definition.name, byte
+                            ));
+                        }
+                        digits.push((b'0' + nibble) as char);
+                    }
+                }
+                Ok(TlvValue::Digits(digits))
+            }
+            DataType::DwordLe | DataType::DwordBe => {
+                let array: [u8; 4] = bytes.try_into().map_err(|_| {
+                    format!(
+                        "{}: DWORD must be 4 bytes, got {}",
+                        definition.name,
+                        bytes.len()
+                    )
+                })?;
+                let number = if definition.data_type == DataType::DwordLe {
+                    u32::from_le_bytes(array)
+                } else {
+                    u32::from_be_bytes(array)
+                };
+                Ok(TlvValue::Number(number as u64))
+            }
+            DataType::Hex | DataType::Binary => Ok(TlvValue::Bytes(bytes.to_vec())),
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            TlvValue::Number(number) => Some(*number),
+            TlvValue::Digits(digits) => digits.parse().ok(),
+            TlvValue::Text(text) => text.trim().parse().ok(),
+            TlvValue::Bytes(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TlvValue::Text(text) | TlvValue::Digits(text) => Some(text),
+            TlvValue::Number(_) | TlvValue::Bytes(_) => None,
+        }
+    }
+
+    fn encode_bcd(
+        digits: &str,
+        len: Option<usize>,
+        definition: &TagDefinition,
+    ) -> Result<Vec<u8>, String> {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("{}: \"{}\" is not a decimal number", definition.name, digits));
+        }
+
+        let len = len.unwrap_or(digits.len());
+        if digits.len() > len {
+            return Err(format!(
+                "{}: {} does not fit into {} BCD digits",
+                definition.name, digits, len
+            ));
+        }
+
+        let padded = format!("{:0>width$}", digits, width = len + len % 2);
+        Ok(padded
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| ((pair[0] - b'0') << 4) | (pair[1] - b'0'))
+            .collect())
+    }
+
+    fn encode_text(
+        text: &str,
+        encoding: Encoding,
+        definition: &TagDefinition,
+    ) -> Result<Vec<u8>, String> {
+        let (bytes, had_errors) = match encoding {
+            Encoding::Ascii => (text.as_bytes().to_vec(), !text.is_ascii()),
+            Encoding::Cp1251 => {
+                let (bytes, _, had_errors) = encoding_rs::WINDOWS_1251.encode(text);
+                (bytes.to_vec(), had_errors)
+            }
+            Encoding::Cp866 => {
+                let (bytes, _, had_errors) = encoding_rs::IBM866.encode(text);
+                (bytes.to_vec(), had_errors)
+            }
+        };
+
+        if had_errors {
+            return Err(format!(
+                "{}: \"{}\" cannot be encoded as {:?}",
+                definition.name, text, encoding
+            ));
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::protocol::TlvItem;
+
+    fn definition(data_type: DataType) -> TagDefinition {
+        TagDefinition {
+            tag: 0xdf01,
+            name: "Test".to_string(),
+            data_type,
+            encoding: None,
+        }
+    }
+
+    #[test]
+    fn packed_bcd_round_trips() {
+        let bcd = definition(DataType::Bcd);
+        let bytes = TlvValue::Number(1000).encode(&bcd, Some(12)).unwrap();
+        assert_eq!(bytes, vec![0x00, 0x00, 0x00, 0x00, 0x10, 0x00]);
+        let value = TlvValue::decode(&bcd, &bytes).unwrap();
+        assert_eq!(value, TlvValue::Digits("000000001000".to_string()));
+        assert_eq!(value.as_u64(), Some(1000));
+
+        // An odd digit count gets a leading zero nibble.
+        let bytes = TlvValue::Digits("643".to_string()).encode(&bcd, Some(3)).unwrap();
+        assert_eq!(bytes, vec![0x06, 0x43]);
+        assert_eq!(TlvValue::decode(&bcd, &bytes).unwrap().as_u64(), Some(643));
+
+        assert_eq!(
+            TlvValue::decode(&bcd, &[0x4f]).unwrap_err(),
+            "Test: invalid BCD byte 0x4F"
+        );
+        assert!(TlvValue::Text("12a".to_string()).encode(&bcd, None).is_err());
+    }
+
+    #[test]
+    fn dwords_round_trip_in_both_byte_orders() {
+        let le = definition(DataType::DwordLe);
+        let be = definition(DataType::DwordBe);
+        let value = TlvValue::Number(0x0102_0304);
+
+        let bytes = value.encode(&le, None).unwrap();
+        assert_eq!(bytes, vec![0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(TlvValue::decode(&le, &bytes).unwrap(), value);
+
+        let bytes = value.encode(&be, None).unwrap();
+        assert_eq!(bytes, vec![0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(TlvValue::decode(&be, &bytes).unwrap(), value);
+
+        assert!(TlvValue::Number(u32::MAX as u64 + 1).encode(&le, None).is_err());
+        assert_eq!(
+            TlvValue::decode(&le, &[0x01, 0x02]).unwrap_err(),
+            "Test: DWORD must be 4 bytes, got 2"
+        );
+    }
+
+    #[test]
+    fn bcd_items_reject_values_that_overflow_their_length() {
+        let item = TlvItem::bcd(0x04, 999_999_999_999, 12).unwrap();
+        assert_eq!(item.value, vec![0x99; 6]);
+        assert_eq!(item.as_u64().unwrap(), 999_999_999_999);
+
+        assert_eq!(
+            TlvItem::bcd(0x04, 1_000_000_000_000, 12).unwrap_err(),
+            "Transaction Amount: 1000000000000 does not fit into 12 BCD digits"
+        );
+        assert!(TlvItem::bcd(0x03, u64::MAX, 10).is_err());
+    }
+
+    #[test]
+    fn text_is_encoded_with_the_tag_encoding() {
+        let mut cp1251 = definition(DataType::String);
+        cp1251.encoding = Some(Encoding::Cp1251);
+        let bytes = TlvValue::Text("Оплата".to_string()).encode(&cp1251, None).unwrap();
+        assert_eq!(bytes.len(), 6);
+        assert_eq!(
+            TlvValue::decode(&cp1251, &bytes).unwrap(),
+            TlvValue::Text("Оплата".to_string())
+        );
+
+        let ascii = definition(DataType::String);
+        assert!(TlvValue::Text("Оплата".to_string()).encode(&ascii, None).is_err());
+        assert!(TlvValue::Text("12345".to_string()).encode(&ascii, Some(4)).is_err());
+    }
+}
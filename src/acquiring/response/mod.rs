@@ -1,4 +1,6 @@
 mod normalize;
 
-pub use normalize::{build_terminal_response_from_raw, normalize_terminal_response};
+pub use normalize::{
+    build_terminal_response, build_terminal_response_from_raw, normalize_terminal_response,
+};
 
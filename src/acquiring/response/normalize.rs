@@ -1,13 +1,24 @@
-use crate::acquiring::types::{NormalizedTransactionData, ProtocolType, TerminalResponse};
+use crate::acquiring::types::{
+    InpasFieldId, NormalizedTransactionData, ProtocolType, TerminalResponse,
+};
+use crate::acquiring::protocol::external::{normalize_mapped, normalized_field_names};
+use once_cell::sync::Lazy;
 use std::collections::HashMap;
 
 const SUCCESS_CODE_REGEXP: &str = r"^0+$";
+const APPROVED_STATUS: &str = "1";
+
+static SUCCESS_CODE: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(SUCCESS_CODE_REGEXP).unwrap());
 
 pub fn build_terminal_response_from_raw(
     protocol: ProtocolType,
     raw: HashMap<String, String>,
 ) -> TerminalResponse {
-    let data = normalize_terminal_response(protocol, &raw);
+    build_terminal_response(normalize_terminal_response(protocol, &raw))
+}
+
+pub fn build_terminal_response(data: NormalizedTransactionData) -> TerminalResponse {
     let success = determine_success(&data);
     let code = data
         .response_code
@@ -43,6 +54,8 @@ pub fn normalize_terminal_response(
     match protocol {
         ProtocolType::Inpas => normalize_inpas(raw),
         ProtocolType::Ttk => normalize_ttk(raw),
+        ProtocolType::Sber => normalize_sber(raw),
+        ProtocolType::External => normalize_external(raw),
     }
 }
 
@@ -121,46 +134,105 @@ fn normalize_ttk(raw: &HashMap<String, String>) -> NormalizedTransactionData {
 }
 
 fn normalize_inpas(raw: &HashMap<String, String>) -> NormalizedTransactionData {
+    let known_keys: std::collections::HashSet<&str> =
+        InpasFieldId::ALL.iter().map(|id| id.code()).collect();
+    let field = |id: InpasFieldId| raw.get(id.code()).cloned();
+
+    let mut data = NormalizedTransactionData {
+        raw: raw.clone(),
+        response_code: field(InpasFieldId::ResponseCode),
+        text_response: field(InpasFieldId::TextResponse),
+        amount: field(InpasFieldId::Amount),
+        additional_amount: field(InpasFieldId::AdditionalAmount),
+        currency: field(InpasFieldId::Currency),
+        host_timestamp: field(InpasFieldId::HostTimestamp),
+        card_entry_mode: field(InpasFieldId::CardEntryMode),
+        cardholder_verification: field(InpasFieldId::PinCodingMode).map(map_pin_coding_mode),
+        pan_masked: field(InpasFieldId::Pan),
+        authorization_code: field(InpasFieldId::AuthorizationCode),
+        rrn: field(InpasFieldId::Rrn),
+        timestamp: field(InpasFieldId::Timestamp),
+        transaction_id: field(InpasFieldId::TransactionId),
+        operation_code: field(InpasFieldId::OperationCode),
+        invoice_number: field(InpasFieldId::InvoiceNumber),
+        terminal_id: field(InpasFieldId::TerminalId),
+        merchant_id: field(InpasFieldId::MerchantId),
+        status: field(InpasFieldId::Status),
+        cashier_request: field(InpasFieldId::CashierRequest),
+        cashier_response: field(InpasFieldId::CashierResponse),
+        provider_code: field(InpasFieldId::ProviderCode),
+        receipt: field(InpasFieldId::Receipt),
+        message_id: None,
+        ecr_number: None,
+        approve: None,
+        status_text: None,
+        date: None,
+        time: None,
+        batch_number: None,
+        application_label: None,
+        issuer_name: None,
+        extras: None,
+    };
+
+    let extras = collect_extras(raw, &known_keys);
+    if !extras.is_empty() {
+        data.extras = Some(extras);
+    }
+
+    data
+}
+
+fn normalize_sber(raw: &HashMap<String, String>) -> NormalizedTransactionData {
     let known_keys: std::collections::HashSet<&str> = [
-        "00", "01", "04", "06", "08", "09", "10", "13", "14", "15", "19", "21", "23", "25",
-        "26", "27", "28", "39", "76", "77", "82", "90",
+        "Result Code",
+        "Result Text",
+        "PAN",
+        "Authorization Code",
+        "Operation Number",
+        "Card Type",
+        "Terminal ID",
+        "Timestamp",
+        "RRN",
+        "Receipt",
+        "Amount",
+        "Operation",
     ]
     .into_iter()
     .collect();
 
     let mut data = NormalizedTransactionData {
         raw: raw.clone(),
-        response_code: raw.get("15").cloned(),
-        text_response: raw.get("19").cloned(),
-        amount: raw.get("00").cloned(),
-        additional_amount: raw.get("01").cloned(),
-        currency: raw.get("04").cloned(),
-        host_timestamp: raw.get("06").cloned(),
-        card_entry_mode: raw.get("08").cloned(),
-        cardholder_verification: raw.get("09").map(|s| map_pin_coding_mode(s.clone())),
-        pan_masked: raw.get("10").cloned(),
-        authorization_code: raw.get("13").cloned(),
-        rrn: raw.get("14").cloned(),
-        timestamp: raw.get("21").cloned(),
-        transaction_id: raw.get("23").cloned(),
-        operation_code: raw.get("25").cloned(),
-        invoice_number: raw.get("26").cloned(),
-        terminal_id: raw.get("27").cloned(),
-        merchant_id: raw.get("28").cloned(),
-        status: raw.get("39").cloned(),
-        cashier_request: raw.get("76").cloned(),
-        cashier_response: raw.get("77").cloned(),
-        provider_code: raw.get("82").cloned(),
-        receipt: raw.get("90").cloned(),
+        response_code: raw.get("Result Code").cloned(),
+        text_response: raw.get("Result Text").cloned(),
+        pan_masked: raw.get("PAN").cloned(),
+        authorization_code: raw.get("Authorization Code").cloned(),
+        transaction_id: raw.get("Operation Number").cloned(),
+        application_label: raw.get("Card Type").cloned(),
+        terminal_id: raw.get("Terminal ID").cloned(),
+        timestamp: raw.get("Timestamp").cloned(),
+        rrn: raw.get("RRN").cloned(),
+        receipt: raw.get("Receipt").cloned(),
+        amount: raw.get("Amount").cloned(),
+        operation_code: raw.get("Operation").cloned(),
         message_id: None,
         ecr_number: None,
         approve: None,
+        status: None,
         status_text: None,
+        additional_amount: None,
+        currency: None,
+        invoice_number: None,
+        merchant_id: None,
+        batch_number: None,
         date: None,
         time: None,
-        batch_number: None,
-        application_label: None,
+        host_timestamp: None,
+        card_entry_mode: None,
+        cardholder_verification: None,
         issuer_name: None,
+        cashier_request: None,
+        cashier_response: None,
+        provider_code: None,
         extras: None,
     };
 
@@ -172,6 +244,17 @@ fn normalize_inpas(raw: &HashMap<String, String>) -> NormalizedTransactionData {
     data
 }
 
+/// Without a configured mapping, output keys that match normalized field
+/// names are taken as is.
+fn normalize_external(raw: &HashMap<String, String>) -> NormalizedTransactionData {
+    let mapping: HashMap<String, String> = normalized_field_names()
+        .into_iter()
+        .filter(|name| raw.contains_key(name))
+        .map(|name| (name.clone(), name))
+        .collect();
+    normalize_mapped(raw, &mapping).expect("identity mapping uses known field names")
+}
+
 fn build_ttk_timestamp(date: Option<&String>, time: Option<&String>) -> Option<String> {
     match (date, time) {
         (Some(d), Some(t)) => Some(format!("{}{}", d, t)),
@@ -189,28 +272,24 @@ fn collect_extras(
         .collect()
 }
 
+/// A response code or approval flag decides when the terminal sent either;
+/// otherwise the status does (Inpas reports "1" for approved). A response
+/// with none of them is not an approval.
 fn determine_success(data: &NormalizedTransactionData) -> bool {
-    if let Some(ref response_code) = data.response_code {
-        let re = regex::Regex::new(SUCCESS_CODE_REGEXP).unwrap();
-        if re.is_match(response_code) {
-            return true;
-        }
-    }
-
-    if let Some(ref approve) = data.approve {
-        if approve.to_uppercase() == "Y" {
-            return true;
-        }
-    }
-
-    if let Some(ref status) = data.status {
-        let re = regex::Regex::new(SUCCESS_CODE_REGEXP).unwrap();
-        if re.is_match(status) {
-            return true;
-        }
+    if data.response_code.is_some() || data.approve.is_some() {
+        return data
+            .response_code
+            .as_ref()
+            .is_some_and(|code| SUCCESS_CODE.is_match(code))
+            || data
+                .approve
+                .as_ref()
+                .is_some_and(|approve| approve.eq_ignore_ascii_case("Y"));
     }
 
-    true
+    data.status
+        .as_ref()
+        .is_some_and(|status| status == APPROVED_STATUS || SUCCESS_CODE.is_match(status))
 }
 
 fn map_pin_coding_mode(value: String) -> String {
@@ -220,3 +299,66 @@ fn map_pin_coding_mode(value: String) -> String {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(fields: &[(&str, &str)]) -> TerminalResponse {
+        let raw = fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        build_terminal_response_from_raw(ProtocolType::Ttk, raw)
+    }
+
+    fn inpas(fields: &[(InpasFieldId, &str)]) -> TerminalResponse {
+        let raw = fields
+            .iter()
+            .map(|(id, value)| (id.code().to_string(), value.to_string()))
+            .collect();
+        build_terminal_response_from_raw(ProtocolType::Inpas, raw)
+    }
+
+    #[test]
+    fn response_code_alone_decides() {
+        assert!(response(&[("Response Code", "00")]).success);
+        assert!(response(&[("Response Code", "000")]).success);
+        let declined = response(&[("Response Code", "51")]);
+        assert!(!declined.success);
+        assert_eq!(declined.error.as_deref(), Some("Response code: 51"));
+    }
+
+    #[test]
+    fn approve_flag_alone_decides() {
+        assert!(response(&[("Approve", "Y")]).success);
+        assert!(response(&[("Approve", "y")]).success);
+        assert!(!response(&[("Approve", "N")]).success);
+    }
+
+    #[test]
+    fn code_or_flag_outrank_the_status() {
+        assert!(response(&[("Response Code", "51"), ("Approve", "Y")]).success);
+        assert!(!response(&[("Response Code", "51"), ("Approve", "N")]).success);
+        assert!(!inpas(&[(InpasFieldId::ResponseCode, "05"), (InpasFieldId::Status, "1")]).success);
+        assert!(inpas(&[(InpasFieldId::ResponseCode, "00"), (InpasFieldId::Status, "16")]).success);
+    }
+
+    #[test]
+    fn status_alone_decides() {
+        assert!(inpas(&[(InpasFieldId::Status, "1")]).success);
+        assert!(inpas(&[(InpasFieldId::Status, "0")]).success);
+        let declined = inpas(&[(InpasFieldId::Status, "16")]);
+        assert!(!declined.success);
+        assert_eq!(declined.code.as_deref(), Some("16"));
+        assert!(!inpas(&[(InpasFieldId::Status, "53")]).success);
+    }
+
+    #[test]
+    fn empty_responses_are_not_approvals() {
+        let empty = response(&[]);
+        assert!(!empty.success);
+        assert_eq!(empty.error.as_deref(), Some("Unknown terminal error"));
+        assert!(!response(&[("Terminal ID", "00000001")]).success);
+    }
+}
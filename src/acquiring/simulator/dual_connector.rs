@@ -0,0 +1,247 @@
+use crate::acquiring::protocol::InpasRequest;
+use crate::acquiring::types::InpasFieldId;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DcScenario {
+    /// Approves the operation. `fields` override or extend the default reply.
+    Success { fields: Vec<(String, String)> },
+    Error { code: String, description: String },
+    HttpError { status: u16, body: String },
+    /// Replies with a UTF-8 body while declaring windows-1251.
+    WrongCharset,
+    Slow { delay_ms: u64, then: Box<DcScenario> },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReceivedRequest {
+    pub request: InpasRequest,
+    pub fields: HashMap<String, String>,
+    pub content_type: Option<String>,
+    pub authorization: Option<String>,
+}
+
+struct MockState {
+    script: VecDeque<DcScenario>,
+    default_scenario: DcScenario,
+    received: Vec<ReceivedRequest>,
+}
+
+/// Local stand-in for the DualConnector HTTP service. Each request takes the
+/// next scripted scenario, or the default one once the script is exhausted.
+#[derive(Clone)]
+pub struct DualConnectorMock {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl DualConnectorMock {
+    pub fn new(default_scenario: DcScenario) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                script: VecDeque::new(),
+                default_scenario,
+                received: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn script(&self, scenario: DcScenario) -> &Self {
+        self.state.lock().unwrap().script.push_back(scenario);
+        self
+    }
+
+    pub fn received(&self) -> Vec<ReceivedRequest> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Binds `addr` and serves requests until the returned task is aborted.
+    /// The bound address can be used as `dc_host` directly.
+    pub async fn listen(
+        &self,
+        addr: &str,
+    ) -> std::io::Result<(std::net::SocketAddr, JoinHandle<()>)> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let mock = self.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mock = mock.clone();
+                tokio::spawn(async move {
+                    let _ = mock.handle(stream).await;
+                });
+            }
+        });
+        Ok((local_addr, handle))
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let Some((headers, body)) = Self::read_request(&mut stream).await? else {
+            return Ok(());
+        };
+
+        let content_type = headers.get("content-type").cloned();
+        let authorization = headers.get("authorization").cloned();
+        let xml = encoding_rs::WINDOWS_1251.decode(&body).0.to_string();
+        let request: InpasRequest = quick_xml::de::from_str(&xml).unwrap_or_default();
+        let fields: HashMap<String, String> = request
+            .fields
+            .iter()
+            .map(|field| (field.id.clone(), field.value.clone()))
+            .collect();
+
+        let scenario = {
+            let mut state = self.state.lock().unwrap();
+            state.received.push(ReceivedRequest {
+                request,
+                fields: fields.clone(),
+                content_type,
+                authorization,
+            });
+            state
+                .script
+                .pop_front()
+                .unwrap_or_else(|| state.default_scenario.clone())
+        };
+
+        let mut scenario = scenario;
+        while let DcScenario::Slow { delay_ms, then } = scenario {
+            sleep(Duration::from_millis(delay_ms)).await;
+            scenario = *then;
+        }
+
+        let (status, content_type, body) = match scenario {
+            DcScenario::Success { fields: overrides } => {
+                let xml = success_xml(&fields, &overrides);
+                (200, "text/xml; charset=windows-1251", encode_cp1251(&xml))
+            }
+            DcScenario::Error { code, description } => {
+                let xml = format!(
+                    "<?xml version=\"1.0\" encoding=\"windows-1251\"?>\n<response><errorcode>{}</errorcode><errordescription>{}</errordescription></response>",
+                    quick_xml::escape::escape(&code),
+                    quick_xml::escape::escape(&description)
+                );
+                (200, "text/xml; charset=windows-1251", encode_cp1251(&xml))
+            }
+            DcScenario::HttpError { status, body } => {
+                (status, "text/plain; charset=utf-8", body.into_bytes())
+            }
+            DcScenario::WrongCharset => {
+                let xml = success_xml(&fields, &[]);
+                (200, "text/xml; charset=windows-1251", xml.into_bytes())
+            }
+            DcScenario::Slow { .. } => unreachable!(),
+        };
+
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            if status < 400 { "OK" } else { "Error" },
+            content_type,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&body).await?;
+        stream.flush().await?;
+        stream.shutdown().await
+    }
+
+    async fn read_request(
+        stream: &mut TcpStream,
+    ) -> std::io::Result<Option<(HashMap<String, String>, Vec<u8>)>> {
+        let mut data = Vec::new();
+        let mut buffer = [0u8; 4096];
+
+        let header_end = loop {
+            if let Some(position) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                break position + 4;
+            }
+            let n = stream.read(&mut buffer).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            data.extend_from_slice(&buffer[..n]);
+        };
+
+        let headers: HashMap<String, String> = String::from_utf8_lossy(&data[..header_end])
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+
+        let content_length = headers
+            .get("content-length")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(0);
+        while data.len() < header_end + content_length {
+            let n = stream.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buffer[..n]);
+        }
+
+        Ok(Some((headers, data[header_end..].to_vec())))
+    }
+}
+
+fn success_xml(request: &HashMap<String, String>, overrides: &[(String, String)]) -> String {
+    let now = chrono::Local::now();
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut set = |id: &str, value: String| {
+        if let Some(field) = fields.iter_mut().find(|(existing, _)| existing == id) {
+            field.1 = value;
+        } else {
+            fields.push((id.to_string(), value));
+        }
+    };
+
+    for id in [
+        InpasFieldId::Amount,
+        InpasFieldId::Currency,
+        InpasFieldId::OperationCode,
+        InpasFieldId::TerminalId,
+    ] {
+        if let Some(value) = request.get(id.code()) {
+            set(id.code(), value.clone());
+        }
+    }
+    set(InpasFieldId::AuthorizationCode.code(), "123456".to_string());
+    set(InpasFieldId::Rrn.code(), now.format("%y%m%d%H%M%S").to_string());
+    set(InpasFieldId::ResponseCode.code(), "00".to_string());
+    set(InpasFieldId::TextResponse.code(), "ОДОБРЕНО".to_string());
+    set(InpasFieldId::Timestamp.code(), now.format("%Y%m%d%H%M%S").to_string());
+    set(InpasFieldId::Status.code(), "1".to_string());
+    let amount = request
+        .get(InpasFieldId::Amount.code())
+        .map(String::as_str)
+        .unwrap_or("0");
+    set(InpasFieldId::Receipt.code(), format!("ОПЛАТА\nСУММА: {}", amount));
+    for (id, value) in overrides {
+        set(id, value.clone());
+    }
+
+    let body: String = fields
+        .iter()
+        .map(|(id, value)| {
+            format!(
+                "<field id=\"{}\">{}</field>",
+                quick_xml::escape::escape(id),
+                quick_xml::escape::escape(value)
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"windows-1251\"?>\n<response>{}</response>",
+        body
+    )
+}
+
+fn encode_cp1251(text: &str) -> Vec<u8> {
+    encoding_rs::WINDOWS_1251.encode(text).0.to_vec()
+}
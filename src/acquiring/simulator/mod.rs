@@ -0,0 +1,7 @@
+pub mod dual_connector;
+#[cfg(unix)]
+pub mod pty;
+pub mod ttk;
+
+pub use dual_connector::{DcScenario, DualConnectorMock};
+pub use ttk::{Scenario, TtkSimulator};
@@ -0,0 +1,135 @@
+use crate::acquiring::connection::fd::FdStream;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Non-blocking pseudo-terminal master in raw mode. A slave descriptor is
+/// kept open so that clients can close and reopen the device without the
+/// master reporting a hang-up.
+pub struct Pty {
+    master: FdStream,
+    _slave: OwnedFd,
+    pub slave_path: PathBuf,
+}
+
+/// `ptsname_r` fills a caller buffer, unlike `ptsname` and its static one.
+#[cfg(target_os = "linux")]
+fn slave_name(fd: i32) -> io::Result<CString> {
+    let mut buffer = [0 as libc::c_char; 128];
+    let result = unsafe { libc::ptsname_r(fd, buffer.as_mut_ptr(), buffer.len()) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+    Ok(unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_owned())
+}
+
+/// Without `ptsname_r` the static buffer is guarded until it is copied.
+#[cfg(not(target_os = "linux"))]
+fn slave_name(fd: i32) -> io::Result<CString> {
+    static PTSNAME: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let _guard = PTSNAME.lock().unwrap_or_else(|e| e.into_inner());
+    let name = unsafe { libc::ptsname(fd) };
+    if name.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { CStr::from_ptr(name) }.to_owned())
+}
+
+impl Pty {
+    pub fn open() -> io::Result<Self> {
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        if master < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let master = unsafe { OwnedFd::from_raw_fd(master) };
+        let fd = master.as_raw_fd();
+
+        if unsafe { libc::grantpt(fd) } != 0 || unsafe { libc::unlockpt(fd) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let name = slave_name(fd)?;
+        let slave_path = PathBuf::from(name.to_string_lossy().to_string());
+
+        let slave = Self::open_slave(&name)?;
+        Self::make_raw(fd)?;
+
+        Ok(Self {
+            master: FdStream::new(master)?,
+            _slave: slave,
+            slave_path,
+        })
+    }
+
+    fn open_slave(name: &CString) -> io::Result<OwnedFd> {
+        let slave = unsafe { libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY) };
+        if slave < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(slave) })
+    }
+
+    fn make_raw(fd: i32) -> io::Result<()> {
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { libc::cfmakeraw(&mut termios) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for Pty {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.master).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Pty {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.master).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.master).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.master).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn ptys_opened_in_parallel_get_their_own_names() {
+        let opened: Vec<_> = (0..16).map(|_| tokio::spawn(async { Pty::open() })).collect();
+        let mut paths = Vec::new();
+        let mut ptys = Vec::new();
+        for task in opened {
+            let pty = task.await.unwrap().unwrap();
+            paths.push(pty.slave_path.clone());
+            ptys.push(pty);
+        }
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), ptys.len());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::acquiring::protocol::{TlvItem, TtkBuffer};
+#[cfg(unix)]
+use crate::acquiring::simulator::pty::Pty;
+use crate::acquiring::types::MessageType;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scenario {
+    Approve,
+    Decline { code: String },
+    Timeout,
+    SplitFrames { chunk: usize, delay_ms: u64 },
+    IntermediatePrompts { count: usize },
+    Garbage,
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    /// `approve`, `decline[:CODE]`, `timeout`, `split[:CHUNK[:DELAY_MS]]`,
+    /// `prompts[:COUNT]` or `garbage`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let args: Vec<&str> = parts.collect();
+        let number = |index: usize, default: u64| -> Result<u64, String> {
+            args.get(index)
+                .map(|v| v.parse().map_err(|_| format!("Invalid number \"{}\" in scenario {}", v, s)))
+                .unwrap_or(Ok(default))
+        };
+
+        match name {
+            "approve" => Ok(Scenario::Approve),
+            "decline" => Ok(Scenario::Decline {
+                code: args.first().unwrap_or(&"05").to_string(),
+            }),
+            "timeout" => Ok(Scenario::Timeout),
+            "split" => Ok(Scenario::SplitFrames {
+                chunk: number(0, 3)?.max(1) as usize,
+                delay_ms: number(1, 50)?,
+            }),
+            "prompts" => Ok(Scenario::IntermediatePrompts {
+                count: number(0, 2)? as usize,
+            }),
+            "garbage" => Ok(Scenario::Garbage),
+            _ => Err(format!("Unknown scenario: {}", s)),
+        }
+    }
+}
+
+/// In-process TTK terminal: reads client requests framed by `TtkBuffer` and
+/// answers them according to the scenario configured for the message ID
+/// (PUR, REF, SRV, VOI, ...), falling back to the default scenario.
+pub struct TtkSimulator {
+    default_scenario: Scenario,
+    scenarios: HashMap<String, Scenario>,
+    terminal_id: String,
+}
+
+impl TtkSimulator {
+    pub fn new(default_scenario: Scenario) -> Self {
+        Self {
+            default_scenario,
+            scenarios: HashMap::new(),
+            terminal_id: "00000001".to_string(),
+        }
+    }
+
+    pub fn with_scenario(mut self, message_id: &str, scenario: Scenario) -> Self {
+        self.scenarios.insert(message_id.to_uppercase(), scenario);
+        self
+    }
+
+    pub fn with_terminal_id(mut self, terminal_id: &str) -> Self {
+        self.terminal_id = terminal_id.to_string();
+        self
+    }
+
+    pub fn scenario_for(&self, message_id: &str) -> &Scenario {
+        self.scenarios
+            .get(message_id)
+            .unwrap_or(&self.default_scenario)
+    }
+
+    /// Binds `addr` and serves every accepted connection on its own task.
+    /// Returns the bound address, so `127.0.0.1:0` can be used in tests.
+    pub async fn listen_tcp(
+        self: Arc<Self>,
+        addr: &str,
+    ) -> std::io::Result<(std::net::SocketAddr, JoinHandle<()>)> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let simulator = Arc::clone(&self);
+                tokio::spawn(async move {
+                    let _ = simulator.serve(stream).await;
+                });
+            }
+        });
+        Ok((local_addr, handle))
+    }
+
+    /// Like `listen_tcp`, with every connection wrapped in TLS. See
+    /// `connection::tls::server_config`.
+    pub async fn listen_tls(
+        self: Arc<Self>,
+        addr: &str,
+        config: ServerConfig,
+    ) -> std::io::Result<(std::net::SocketAddr, JoinHandle<()>)> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let simulator = Arc::clone(&self);
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        let _ = simulator.serve(stream).await;
+                    }
+                });
+            }
+        });
+        Ok((local_addr, handle))
+    }
+
+    /// Opens a pseudo-terminal and serves it. The returned path is the slave
+    /// device to put into `ConnectionConfig::ncom`.
+    #[cfg(unix)]
+    pub fn open_pty(self: Arc<Self>) -> std::io::Result<(std::path::PathBuf, JoinHandle<()>)> {
+        let pty = Pty::open()?;
+        let path = pty.slave_path.clone();
+        let handle = tokio::spawn(async move {
+            let _ = self.serve(pty).await;
+        });
+        Ok((path, handle))
+    }
+
+    pub async fn serve<S>(&self, mut stream: S) -> std::io::Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut pending = Vec::new();
+        let mut buffer = vec![0u8; 4096];
+
+        loop {
+            let n = stream.read(&mut buffer).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            pending.extend_from_slice(&buffer[..n]);
+
+            while let Some(frame) = TtkBuffer::take_frame(&mut pending) {
+                let Ok((MessageType::ClientRequest, items)) = TtkBuffer::parse_message(&frame)
+                else {
+                    continue;
+                };
+                self.reply(&mut stream, &items).await?;
+            }
+        }
+    }
+
+    async fn reply<S>(&self, stream: &mut S, request: &[TlvItem]) -> std::io::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        let message_id = Self::text(request, 0x01).unwrap_or_default();
+
+        match self.scenario_for(&message_id) {
+            Scenario::Approve => {
+                let response = self.response(request, "00", "Y", "APPROVED");
+                stream.write_all(&response).await?;
+            }
+            Scenario::Decline { code } => {
+                let response = self.response(request, code, "N", "DECLINED");
+                stream.write_all(&response).await?;
+            }
+            Scenario::Timeout => {}
+            Scenario::SplitFrames { chunk, delay_ms } => {
+                let response = self.response(request, "00", "Y", "APPROVED");
+                for part in response.chunks(*chunk) {
+                    stream.write_all(part).await?;
+                    stream.flush().await?;
+                    sleep(Duration::from_millis(*delay_ms)).await;
+                }
+            }
+            Scenario::IntermediatePrompts { count } => {
+                for index in 0..*count {
+                    let prompt = TtkBuffer::create_message(
+                        MessageType::ServerResponse,
+                        &[
+                            TlvItem::new(0x81, message_id.as_bytes().to_vec()),
+                            TlvItem::new(0xa0, format!("PROMPT {}", index + 1).into_bytes()),
+                        ],
+                    );
+                    stream.write_all(&prompt).await?;
+                    stream.flush().await?;
+                    sleep(Duration::from_millis(20)).await;
+                }
+                let response = self.response(request, "00", "Y", "APPROVED");
+                stream.write_all(&response).await?;
+            }
+            Scenario::Garbage => {
+                let garbage: Vec<u8> = (0..32).map(|_| rand::random::<u8>()).collect();
+                stream.write_all(&[0x00, 0x03, 0xde, 0xad, 0xbe]).await?;
+                stream.write_all(&garbage).await?;
+            }
+        }
+
+        stream.flush().await
+    }
+
+    fn response(&self, request: &[TlvItem], code: &str, approve: &str, text: &str) -> Vec<u8> {
+        let now = chrono::Local::now();
+        let message_id = Self::text(request, 0x01).unwrap_or_default();
+        let mut items = vec![TlvItem::new(0x81, message_id.as_bytes().to_vec())];
+
+        for (request_tag, response_tag) in [(0x02, 0x82), (0x03, 0x83), (0x04, 0x84)] {
+            if let Some(item) = request.iter().find(|item| item.tag == request_tag) {
+                items.push(TlvItem::new(response_tag, item.value.clone()));
+            }
+        }
+
+        items.extend([
+            TlvItem::new(0x9b, code.as_bytes().to_vec()),
+            TlvItem::new(0xa1, approve.as_bytes().to_vec()),
+            TlvItem::new(0xa0, text.as_bytes().to_vec()),
+            TlvItem::new(0x9d, self.terminal_id.as_bytes().to_vec()),
+        ]);
+
+        if approve == "Y" && message_id != "SRV" {
+            let rrn = now.format("%y%m%d%H%M%S").to_string();
+            items.extend([
+                TlvItem::bcd(0x98, rrn.parse().unwrap_or_default(), 12).ok(),
+                TlvItem::ascii(0x8c, "123456").ok(),
+                TlvItem::bcd(0x89, 4000_0000_0000_0002, 16).ok(),
+                TlvItem::bcd(0x8d, now.format("%y%m%d").to_string().parse().unwrap_or_default(), 6)
+                    .ok(),
+                TlvItem::bcd(0x8e, now.format("%H%M%S").to_string().parse().unwrap_or_default(), 6)
+                    .ok(),
+            ]
+            .into_iter()
+            .flatten());
+        }
+
+        TtkBuffer::create_message(MessageType::ServerResponse, &items)
+    }
+
+    fn text(items: &[TlvItem], tag: u32) -> Option<String> {
+        items
+            .iter()
+            .find(|item| item.tag == tag)
+            .map(|item| String::from_utf8_lossy(&item.value).to_string())
+    }
+}
@@ -0,0 +1,237 @@
+use crate::acquiring::commands::base::CommandContext;
+use crate::acquiring::commands::PingCommand;
+use crate::acquiring::connection::BaseConnection;
+use crate::acquiring::types::{ProtocolType, ReconnectConfig};
+use std::sync::Arc;
+use tokio::sync::{watch, Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
+
+const DEFAULT_PROBE_INTERVAL_MS: u32 = 5000;
+const DEFAULT_INITIAL_BACKOFF_MS: u32 = 1000;
+const DEFAULT_MAX_BACKOFF_MS: u32 = 30000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connected,
+    /// Lazy or per-command connection that no command has left open; it is
+    /// opened by the next command.
+    OnDemand,
+    Reconnecting { attempt: u32 },
+    /// Reconnecting gave up after `max_attempts`; holds the last error.
+    Failed(String),
+}
+
+/// Background task that probes a connection and reconnects it with
+/// exponential backoff. State changes are published to `state`. Probes and
+/// reconnects hold `operation`, so they never interleave with a command.
+pub struct ConnectionSupervisor {
+    task: JoinHandle<()>,
+    wake: Arc<Notify>,
+}
+
+impl ConnectionSupervisor {
+    pub fn spawn(
+        connection: Arc<Mutex<Box<dyn BaseConnection>>>,
+        operation: Arc<Mutex<()>>,
+        state: Arc<watch::Sender<ConnectionState>>,
+        settings: ReconnectConfig,
+    ) -> Self {
+        let wake = Arc::new(Notify::new());
+        let task = tokio::spawn(supervise(
+            connection,
+            operation,
+            state,
+            settings,
+            Arc::clone(&wake),
+        ));
+        Self { task, wake }
+    }
+
+    /// Runs a probe right away, e.g. after a command failed.
+    pub fn probe_now(&self) {
+        self.wake.notify_one();
+    }
+}
+
+impl Drop for ConnectionSupervisor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn supervise(
+    connection: Arc<Mutex<Box<dyn BaseConnection>>>,
+    operation: Arc<Mutex<()>>,
+    state: Arc<watch::Sender<ConnectionState>>,
+    settings: ReconnectConfig,
+    wake: Arc<Notify>,
+) {
+    let interval = Duration::from_millis(
+        settings
+            .probe_interval
+            .unwrap_or(DEFAULT_PROBE_INTERVAL_MS) as u64,
+    );
+    loop {
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = wake.notified() => {}
+        }
+
+        let result = {
+            let _operation = operation.lock().await;
+            probe(&connection, settings.ping).await
+        };
+        if result.is_err() && !reconnect(&connection, &operation, &state, &settings).await {
+            return;
+        }
+    }
+}
+
+async fn probe(
+    connection: &Arc<Mutex<Box<dyn BaseConnection>>>,
+    ping: bool,
+) -> Result<(), String> {
+    let protocol = {
+        let mut conn = connection.lock().await;
+        conn.check().await.map_err(|e| e.to_string())?;
+        conn.config().protocol
+    };
+    if ping && matches!(protocol, ProtocolType::Ttk) {
+        let context = CommandContext::new(Arc::clone(connection));
+        PingCommand::new()
+            .execute(context)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Returns false once `max_attempts` is exhausted.
+async fn reconnect(
+    connection: &Arc<Mutex<Box<dyn BaseConnection>>>,
+    operation: &Arc<Mutex<()>>,
+    state: &watch::Sender<ConnectionState>,
+    settings: &ReconnectConfig,
+) -> bool {
+    let max_backoff = settings.max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF_MS);
+    let mut backoff = settings
+        .initial_backoff
+        .unwrap_or(DEFAULT_INITIAL_BACKOFF_MS)
+        .min(max_backoff);
+    let mut attempt = 1;
+    loop {
+        state.send_replace(ConnectionState::Reconnecting { attempt });
+        let result = {
+            let _operation = operation.lock().await;
+            let mut conn = connection.lock().await;
+            let _ = conn.disconnect().await;
+            conn.connect().await.map_err(|e| e.to_string())
+        };
+        let error = match result {
+            Ok(_) => {
+                state.send_replace(ConnectionState::Connected);
+                return true;
+            }
+            Err(e) => e,
+        };
+        if settings.max_attempts.is_some_and(|max| attempt >= max) {
+            state.send_replace(ConnectionState::Failed(error));
+            return false;
+        }
+        sleep(Duration::from_millis(backoff as u64)).await;
+        backoff = backoff.saturating_mul(2).min(max_backoff);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::connection::TcpConnection;
+    use crate::acquiring::simulator::{Scenario, TtkSimulator};
+    use crate::acquiring::types::ConnectionConfigBuilder;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinSet;
+    use tokio::time::timeout;
+
+    /// Serves the simulator until aborted; aborting also closes every
+    /// accepted connection.
+    fn start_simulator(listener: TcpListener) -> JoinHandle<()> {
+        let simulator = Arc::new(TtkSimulator::new(Scenario::Approve));
+        tokio::spawn(async move {
+            let mut sessions = JoinSet::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                let simulator = Arc::clone(&simulator);
+                sessions.spawn(async move {
+                    let _ = simulator.serve(stream).await;
+                });
+            }
+        })
+    }
+
+    async fn wait_for(
+        states: &mut watch::Receiver<ConnectionState>,
+        expected: impl FnMut(&ConnectionState) -> bool,
+    ) -> ConnectionState {
+        timeout(Duration::from_secs(5), states.wait_for(expected))
+            .await
+            .expect("state was not published in time")
+            .unwrap()
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn a_dropped_link_is_reconnected_and_then_given_up() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = start_simulator(listener);
+
+        let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", addr.port())
+            .serial_number("1")
+            .timeout(500)
+            .build()
+            .unwrap();
+        let mut tcp = TcpConnection::new(config);
+        tcp.connect().await.unwrap();
+        let connection: Arc<Mutex<Box<dyn BaseConnection>>> = Arc::new(Mutex::new(Box::new(tcp)));
+        let (state, mut states) = watch::channel(ConnectionState::Connected);
+        let _supervisor = ConnectionSupervisor::spawn(
+            connection,
+            Arc::new(Mutex::new(())),
+            Arc::new(state),
+            ReconnectConfig {
+                probe_interval: Some(20),
+                initial_backoff: Some(20),
+                max_backoff: Some(40),
+                max_attempts: Some(5),
+                ping: true,
+            },
+        );
+
+        // Pings keep a live link connected.
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(*states.borrow(), ConnectionState::Connected);
+
+        server.abort();
+        wait_for(&mut states, |state| {
+            matches!(state, ConnectionState::Reconnecting { attempt } if *attempt >= 2)
+        })
+        .await;
+
+        let server = start_simulator(TcpListener::bind(addr).await.unwrap());
+        wait_for(&mut states, |state| *state == ConnectionState::Connected).await;
+
+        server.abort();
+        wait_for(&mut states, |state| {
+            matches!(state, ConnectionState::Reconnecting { .. })
+        })
+        .await;
+        let failed = wait_for(&mut states, |state| {
+            matches!(state, ConnectionState::Failed(_))
+        })
+        .await;
+        assert!(matches!(failed, ConnectionState::Failed(error) if !error.is_empty()));
+    }
+}
@@ -1,47 +1,110 @@
-use crate::acquiring::commands::{PaymentCommand, RefundCommand, TotalsCommand};
-use crate::acquiring::connection::{BaseConnection, InpasConnection, TcpConnection, UsbConnection};
-use crate::acquiring::types::{ConnectionConfig, ConnectionType, TerminalResponse};
+use crate::acquiring::commands::base::CommandContext;
+use crate::acquiring::commands::{PaymentCommand, PingCommand, RefundCommand, TotalsCommand};
+use crate::acquiring::connection::{
+    BaseConnection, BluetoothConnection, ExternalProcessConnection, InpasConnection,
+    Rfc2217Connection, SberConnection, TcpConnection, TcpListenerConnection, UsbConnection,
+};
+use crate::acquiring::journal::{
+    JsonLinesStore, TransactionQuery, TransactionRecord, TransactionStore,
+};
+use crate::acquiring::supervisor::{ConnectionState, ConnectionSupervisor};
+use crate::acquiring::types::{
+    ConnectionConfig, ConnectionPolicy, ConnectionType, ProtocolType, TerminalResponse,
+};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 
 pub struct Terminal {
     connection: Option<Arc<Mutex<Box<dyn BaseConnection>>>>,
     config: ConnectionConfig,
+    state: Arc<watch::Sender<ConnectionState>>,
+    /// Held for the whole of a command, and by supervisor probes.
+    operation: Arc<Mutex<()>>,
+    supervisor: Option<ConnectionSupervisor>,
+    store: Option<Arc<dyn TransactionStore>>,
 }
 
 impl Terminal {
     pub fn new(config: ConnectionConfig) -> Self {
+        let store = config
+            .journal
+            .as_ref()
+            .map(|path| Arc::new(JsonLinesStore::new(path)) as Arc<dyn TransactionStore>);
         Self {
             connection: None,
             config,
+            state: Arc::new(watch::Sender::new(ConnectionState::Disconnected)),
+            operation: Arc::new(Mutex::new(())),
+            supervisor: None,
+            store,
         }
     }
 
     pub async fn connect(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
-        if matches!(
-            self.config.protocol,
-            crate::acquiring::types::ProtocolType::Inpas
-        ) {
-            let mut conn: Box<dyn BaseConnection> =
-                Box::new(InpasConnection::new(self.config.clone()));
-            let result = conn.connect().await?;
-            self.connection = Some(Arc::new(Mutex::new(conn)));
-            return Ok(result);
-        }
-
-        let mut conn: Box<dyn BaseConnection> = match self.config.connection_type {
-            ConnectionType::Tcp => Box::new(TcpConnection::new(self.config.clone())),
-            ConnectionType::Usb => Box::new(UsbConnection::new(self.config.clone())),
-            ConnectionType::Bluetooth => {
-                return Err("Bluetooth connection not yet implemented".into());
-            }
+        self.config.validate()?;
+        let conn: Box<dyn BaseConnection> = match self.config.protocol {
+            ProtocolType::Inpas => Box::new(InpasConnection::new(self.config.clone())),
+            ProtocolType::Sber => Box::new(SberConnection::new(self.config.clone())),
+            ProtocolType::External => Box::new(ExternalProcessConnection::new(self.config.clone())),
+            ProtocolType::Ttk => match self.config.connection_type {
+                ConnectionType::Tcp => Box::new(TcpConnection::new(self.config.clone())),
+                ConnectionType::Usb => Box::new(UsbConnection::new(self.config.clone())),
+                ConnectionType::Bluetooth => Box::new(BluetoothConnection::new(self.config.clone())),
+                ConnectionType::Listener => Box::new(TcpListenerConnection::new(self.config.clone())),
+                ConnectionType::Rfc2217 => Box::new(Rfc2217Connection::new(self.config.clone())),
+            },
         };
 
-        let result = conn.connect().await?;
-        self.connection = Some(Arc::new(Mutex::new(conn)));
+        self.connect_with(conn).await
+    }
+
+    /// Connects through a caller-built connection, e.g. a `RecordingConnection`
+    /// around a real transport or a `ReplayConnection` in tests. With
+    /// `reconnect` in the config, a supervisor keeps a persistent connection
+    /// alive. Lazy and per-command connections are only opened by commands
+    /// and stay `OnDemand` until one leaves the connection open.
+    pub async fn connect_with(
+        &mut self,
+        mut conn: Box<dyn BaseConnection>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        self.supervisor = None;
+        let persistent = self.policy() == ConnectionPolicy::Persistent;
+        let result = if persistent {
+            conn.connect().await?
+        } else {
+            true
+        };
+        let conn = Arc::new(Mutex::new(conn));
+        self.connection = Some(Arc::clone(&conn));
+        self.state.send_replace(if persistent {
+            ConnectionState::Connected
+        } else {
+            ConnectionState::OnDemand
+        });
+        if let (true, Some(settings)) = (persistent, self.config.reconnect.clone()) {
+            self.supervisor = Some(ConnectionSupervisor::spawn(
+                conn,
+                Arc::clone(&self.operation),
+                Arc::clone(&self.state),
+                settings,
+            ));
+        }
         Ok(result)
     }
 
+    fn policy(&self) -> ConnectionPolicy {
+        self.config.policy.unwrap_or_default()
+    }
+
+    /// Current connection state, kept up to date by the supervisor.
+    pub fn state(&self) -> ConnectionState {
+        self.state.borrow().clone()
+    }
+
+    pub fn state_changes(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
     async fn connection_or_err(
         &mut self,
     ) -> Result<Arc<Mutex<Box<dyn BaseConnection + 'static>>>, Box<dyn std::error::Error>> {
@@ -51,7 +114,10 @@ impl Terminal {
             .ok_or("Not connected to terminal")?;
 
         let conn_guard = conn.lock().await;
-        if !conn_guard.is_connected() {
+        if self.policy() == ConnectionPolicy::Persistent && !conn_guard.is_connected() {
+            if self.supervisor.is_none() {
+                self.state.send_replace(ConnectionState::Disconnected);
+            }
             return Err("Not connected to terminal".into());
         }
         drop(conn_guard);
@@ -59,30 +125,102 @@ impl Terminal {
     }
 
     pub async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.supervisor = None;
         if let Some(conn) = &self.connection {
             let mut conn = conn.lock().await;
             conn.disconnect().await?;
         }
         self.connection = None;
+        self.state.send_replace(ConnectionState::Disconnected);
         Ok(())
     }
 
+    /// Failed commands trigger an immediate supervisor probe. On demand
+    /// connections report whether the command left them open.
+    async fn finish<T>(
+        &self,
+        result: Result<T, Box<dyn std::error::Error>>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        if let (Err(_), Some(supervisor)) = (&result, &self.supervisor) {
+            supervisor.probe_now();
+        }
+        if let (ConnectionPolicy::Lazy | ConnectionPolicy::PerCommand, Some(conn)) =
+            (self.policy(), &self.connection)
+        {
+            self.state.send_replace(if conn.lock().await.is_connected() {
+                ConnectionState::Connected
+            } else {
+                ConnectionState::OnDemand
+            });
+        }
+        result
+    }
+
+    /// Runs a command under the operation lock. With a store, the command is
+    /// journaled before it is sent and again with its outcome.
+    async fn run<Fut>(
+        &mut self,
+        name: &str,
+        amount: Option<u64>,
+        currency: Option<String>,
+        order_id: Option<String>,
+        execute: impl FnOnce(CommandContext) -> Fut,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>>
+    where
+        Fut: Future<Output = Result<TerminalResponse, Box<dyn std::error::Error>>>,
+    {
+        let operation = Arc::clone(&self.operation);
+        let _operation = operation.lock().await;
+        let conn = self.connection_or_err().await?;
+        let context = CommandContext::new(conn);
+        let Some(store) = self.store.clone() else {
+            let result = execute(context).await;
+            return self.finish(result).await;
+        };
+
+        let mut record = TransactionRecord::new(name, context.ern, &self.config);
+        record.amount = amount;
+        record.currency = currency;
+        record.order_id = order_id;
+        store
+            .save(&record)
+            .await
+            .map_err(|e| format!("Failed to write transaction journal: {}", e))?;
+
+        let result = execute(context).await.map_err(|e| e.to_string());
+        record.finish(&result);
+        // If this fails the journal keeps the pending entry, which is the
+        // honest state for an outcome we couldn't record.
+        let _ = store.save(&record).await.map_err(|e| e.to_string());
+        self.finish(result.map_err(Into::into)).await
+    }
+
     pub async fn payment(
         &mut self,
         amount: u64,
         currency: Option<String>,
     ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-        let conn = self.connection_or_err().await?;
-        let context = crate::acquiring::commands::base::CommandContext::new(conn);
-        let command = PaymentCommand::new(amount, currency);
-        command.execute(context).await
+        self.payment_with_order(amount, currency, None).await
+    }
+
+    /// Like `payment`, with `order_id` recorded in the journal.
+    pub async fn payment_with_order(
+        &mut self,
+        amount: u64,
+        currency: Option<String>,
+        order_id: Option<String>,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        let command = PaymentCommand::new(amount, currency.clone());
+        self.run("payment", Some(amount), currency, order_id, |context| {
+            command.execute(context)
+        })
+        .await
     }
 
     pub async fn totals(&mut self) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
-        let conn = self.connection_or_err().await?;
-        let context = crate::acquiring::commands::base::CommandContext::new(conn);
         let command = TotalsCommand::new();
-        command.execute(context).await
+        self.run("totals", None, None, None, |context| command.execute(context))
+            .await
     }
 
     pub async fn refund(
@@ -90,19 +228,87 @@ impl Terminal {
         amount: u64,
         currency: Option<String>,
     ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        self.refund_with_order(amount, currency, None).await
+    }
+
+    /// Like `refund`, with `order_id` recorded in the journal.
+    pub async fn refund_with_order(
+        &mut self,
+        amount: u64,
+        currency: Option<String>,
+        order_id: Option<String>,
+    ) -> Result<TerminalResponse, Box<dyn std::error::Error>> {
+        let command = RefundCommand::new(amount, currency.clone());
+        self.run("refund", Some(amount), currency, order_id, |context| {
+            command.execute(context)
+        })
+        .await
+    }
+
+    /// Replaces the store set up from `journal` in the config.
+    pub fn set_store(&mut self, store: Option<Arc<dyn TransactionStore>>) {
+        self.store = store;
+    }
+
+    pub fn store(&self) -> Option<Arc<dyn TransactionStore>> {
+        self.store.clone()
+    }
+
+    /// Journaled commands matching `query`, oldest first.
+    pub async fn transactions(
+        &self,
+        query: &TransactionQuery,
+    ) -> Result<Vec<TransactionRecord>, Box<dyn std::error::Error>> {
+        let store = self.store.as_ref().ok_or("No transaction journal configured")?;
+        store.query(query).await
+    }
+
+    /// Checks the link without sending a request. Only persistent
+    /// connections are checked; the others are opened on demand.
+    pub async fn check(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let operation = Arc::clone(&self.operation);
+        let _operation = operation.lock().await;
         let conn = self.connection_or_err().await?;
-        let context = crate::acquiring::commands::base::CommandContext::new(conn);
-        let command = RefundCommand::new(amount, currency);
-        command.execute(context).await
+        if self.policy() != ConnectionPolicy::Persistent {
+            return Ok(());
+        }
+        let result = conn.lock().await.check().await;
+        self.finish(result).await
     }
 
-    pub fn connected(&self) -> bool {
-        if let Some(conn) = &self.connection {
-            // We can't easily check without blocking, so we'll use a try_lock
-            if let Ok(conn_guard) = conn.try_lock() {
-                return conn_guard.is_connected();
+    /// Opens the link if needed and, for TTK, sends an SRV test-server
+    /// request, so the terminal is known to answer before a command goes to
+    /// it. A per-command link, or an on-demand one that failed, is closed
+    /// again.
+    pub async fn probe(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let operation = Arc::clone(&self.operation);
+        let _operation = operation.lock().await;
+        let conn = self.connection_or_err().await?;
+        let result = async {
+            {
+                let mut conn = conn.lock().await;
+                if !conn.is_connected() {
+                    conn.connect().await?;
+                }
             }
+            if self.config.protocol == ProtocolType::Ttk {
+                PingCommand::new()
+                    .execute(CommandContext::new(Arc::clone(&conn)))
+                    .await?;
+            }
+            Ok(())
+        }
+        .await;
+        let policy = self.policy();
+        if policy == ConnectionPolicy::PerCommand
+            || (policy == ConnectionPolicy::Lazy && result.is_err())
+        {
+            let _ = conn.lock().await.disconnect().await;
         }
-        false
+        self.finish(result).await
+    }
+
+    pub fn connected(&self) -> bool {
+        matches!(*self.state.borrow(), ConnectionState::Connected)
     }
 }
@@ -0,0 +1,658 @@
+use crate::acquiring::connection::bluetooth::parse_mac;
+use crate::acquiring::connection::tls::parse_fingerprint;
+use crate::acquiring::types::{
+    ConnectionConfig, ConnectionPolicy, ConnectionType, DcClientConfig, ExternalProcessConfig,
+    ProtocolType, ReconnectConfig, SberConfig, SerialConfig, TlsConfig,
+};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+const ENUM_FIELDS: [&str; 3] = ["connection_type", "protocol", "policy"];
+const STRING_FIELDS: [&str; 5] = ["serial_number", "address", "dc_host", "ncom", "journal"];
+const NUMBER_FIELDS: [&str; 3] = ["port", "timeout", "baudrate"];
+/// Nested settings and lists, given as JSON in environment variables.
+const SECTION_FIELDS: [&str; 7] = [
+    "serial",
+    "tls",
+    "dc_client",
+    "sber",
+    "external",
+    "reconnect",
+    "allowed_peers",
+];
+
+impl ConnectionConfig {
+    pub fn builder(protocol: ProtocolType, connection_type: ConnectionType) -> ConnectionConfigBuilder {
+        ConnectionConfigBuilder::new(protocol, connection_type)
+    }
+
+    /// Checks that the fields required by the protocol and transport are
+    /// set and sane. All problems are reported at once, separated by `; `.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+
+        if self.timeout == Some(0) {
+            errors.push("timeout must be greater than zero".to_string());
+        }
+        if let Some(serial) = &self.serial {
+            if serial.data_bits.is_some_and(|bits| !(5..=8).contains(&bits)) {
+                errors.push("serial.data_bits must be between 5 and 8".to_string());
+            }
+            if serial.stop_bits.is_some_and(|bits| !(1..=2).contains(&bits)) {
+                errors.push("serial.stop_bits must be 1 or 2".to_string());
+            }
+        }
+
+        match self.protocol {
+            ProtocolType::External => match &self.external {
+                Some(external) if external.program.trim().is_empty() => {
+                    errors.push("external.program is required for external protocol".to_string())
+                }
+                Some(external) if external.timeout == Some(0) => {
+                    errors.push("external.timeout must be greater than zero".to_string())
+                }
+                Some(_) => {}
+                None => errors.push("external is required for external protocol".to_string()),
+            },
+            ProtocolType::Sber => {
+                if self.sber.as_ref().is_some_and(|sber| sber.timeout == Some(0)) {
+                    errors.push("sber.timeout must be greater than zero".to_string());
+                }
+            }
+            ProtocolType::Inpas if self.dc_host.is_some() => self.validate_dual_connector(&mut errors),
+            ProtocolType::Ttk | ProtocolType::Inpas => self.validate_transport(&mut errors),
+        }
+
+        if self.allowed_peers.is_some() && self.connection_type != ConnectionType::Listener {
+            errors.push("allowed_peers is only supported for listener connections".to_string());
+        }
+
+        if let Some(tls) = &self.tls {
+            let direct_tcp = self.connection_type == ConnectionType::Tcp
+                && matches!(self.protocol, ProtocolType::Ttk | ProtocolType::Inpas)
+                && self.dc_host.is_none();
+            if !direct_tcp {
+                errors.push("tls is only supported for direct tcp connections".to_string());
+            }
+            if tls.ca_cert.is_none() && tls.pinned_certs.is_empty() {
+                errors.push("tls requires ca_cert or pinned_certs".to_string());
+            }
+            errors.extend(tls.pinned_certs.iter().filter_map(|pin| parse_fingerprint(pin).err()));
+            if tls.client_cert.is_some() != tls.client_key.is_some() {
+                errors.push("tls.client_cert and tls.client_key must be set together".to_string());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    fn validate_dual_connector(&self, errors: &mut Vec<String>) {
+        if self.dc_host.as_deref().is_some_and(|host| host.trim().is_empty()) {
+            errors.push("dc_host must not be empty".to_string());
+        }
+        match self.connection_type {
+            ConnectionType::Tcp if self.address.is_none() || self.port.is_none() => errors
+                .push("address and port are required for tcp connection via DualConnector".to_string()),
+            ConnectionType::Usb if self.ncom.is_none() || self.baudrate.is_none() => errors
+                .push("ncom and baudrate are required for usb connection via DualConnector".to_string()),
+            _ => {}
+        }
+    }
+
+    fn validate_transport(&self, errors: &mut Vec<String>) {
+        match self.connection_type {
+            ConnectionType::Tcp | ConnectionType::Rfc2217 => {
+                if self.address.as_deref().is_none_or(|address| address.trim().is_empty()) {
+                    errors.push(format!("address is required for {} connection", self.transport_name()));
+                }
+                if self.port.is_none() {
+                    errors.push(format!("port is required for {} connection", self.transport_name()));
+                }
+            }
+            ConnectionType::Listener => {
+                if self.port.is_none() {
+                    errors.push("port is required for listener connection".to_string());
+                }
+            }
+            ConnectionType::Usb => {
+                let by_usb_ids = self.serial.as_ref().is_some_and(|serial| {
+                    serial.usb_vid.is_some() || serial.usb_pid.is_some() || serial.usb_serial.is_some()
+                });
+                if self.ncom.is_none() && self.address.is_none() && !by_usb_ids {
+                    errors.push(
+                        "ncom or serial.usb_vid/usb_pid/usb_serial is required for usb connection"
+                            .to_string(),
+                    );
+                }
+            }
+            ConnectionType::Bluetooth => {
+                if let Some(address) = &self.address {
+                    if let Err(e) = parse_mac(address) {
+                        errors.push(e);
+                    }
+                    if self.port.is_some_and(|channel| !(1..=30).contains(&channel)) {
+                        errors.push("port (RFCOMM channel) must be between 1 and 30".to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    fn transport_name(&self) -> &'static str {
+        match self.connection_type {
+            ConnectionType::Tcp => "tcp",
+            ConnectionType::Usb => "usb",
+            ConnectionType::Bluetooth => "bluetooth",
+            ConnectionType::Listener => "listener",
+            ConnectionType::Rfc2217 => "rfc2217",
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_value(serde_json::from_str(json)?)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_value(toml::from_str(toml)?)
+    }
+
+    /// Reads a `.toml` or `.json` file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_value(read_file(path.as_ref())?)
+    }
+
+    /// Reads `{prefix}_CONNECTION_TYPE`, `{prefix}_ADDRESS`, `{prefix}_PORT`
+    /// and so on. Nested settings such as `{prefix}_TLS` are JSON objects.
+    pub fn from_env(prefix: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_value(Value::Object(env_values(prefix)?))
+    }
+
+    /// Like `from_file`, with the environment variables of `from_env` taking
+    /// precedence over the file.
+    pub fn from_file_and_env(
+        path: impl AsRef<Path>,
+        prefix: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut value = read_file(path.as_ref())?;
+        merge(&mut value, Value::Object(env_values(prefix)?));
+        Self::from_value(value)
+    }
+
+    fn from_value(value: Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: Self = serde_json::from_value(value)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<Value, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?,
+        Some("json") => serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?,
+        _ => return Err(format!("Unsupported config format: {}", path.display()).into()),
+    };
+    Ok(value)
+}
+
+fn env_values(prefix: &str) -> Result<Map<String, Value>, String> {
+    let mut values = Map::new();
+    let var = |field: &str| std::env::var(format!("{}_{}", prefix, field.to_uppercase())).ok();
+
+    for field in ENUM_FIELDS {
+        if let Some(value) = var(field) {
+            values.insert(field.to_string(), Value::String(value.to_lowercase()));
+        }
+    }
+    for field in STRING_FIELDS {
+        if let Some(value) = var(field) {
+            values.insert(field.to_string(), Value::String(value));
+        }
+    }
+    for field in NUMBER_FIELDS {
+        if let Some(value) = var(field) {
+            let number: u64 = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("{}_{} is not a number: {}", prefix, field.to_uppercase(), value))?;
+            values.insert(field.to_string(), Value::from(number));
+        }
+    }
+    for field in SECTION_FIELDS {
+        if let Some(value) = var(field) {
+            let section: Value = serde_json::from_str(&value)
+                .map_err(|e| format!("{}_{} is not valid JSON: {}", prefix, field.to_uppercase(), e))?;
+            values.insert(field.to_string(), section);
+        }
+    }
+    Ok(values)
+}
+
+/// Overlays `overrides` on `base`, merging nested objects key by key.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Builds a `ConnectionConfig` and validates it in `build`.
+#[derive(Debug, Clone)]
+pub struct ConnectionConfigBuilder {
+    config: ConnectionConfig,
+}
+
+impl ConnectionConfigBuilder {
+    pub fn new(protocol: ProtocolType, connection_type: ConnectionType) -> Self {
+        Self {
+            config: ConnectionConfig {
+                connection_type,
+                protocol,
+                serial_number: String::new(),
+                address: None,
+                port: None,
+                timeout: None,
+                dc_host: None,
+                ncom: None,
+                baudrate: None,
+                policy: None,
+                serial: None,
+                tls: None,
+                dc_client: None,
+                sber: None,
+                external: None,
+                reconnect: None,
+                allowed_peers: None,
+                journal: None,
+            },
+        }
+    }
+
+    /// TTK terminal on the network.
+    pub fn ttk_tcp(address: &str, port: u16) -> Self {
+        Self::new(ProtocolType::Ttk, ConnectionType::Tcp).address(address).port(port)
+    }
+
+    /// TTK terminal on a local serial port.
+    pub fn ttk_serial(ncom: &str, baudrate: u32) -> Self {
+        Self::new(ProtocolType::Ttk, ConnectionType::Usb).ncom(ncom).baudrate(baudrate)
+    }
+
+    /// Inpas terminal on the network, driven by DualConnector.
+    pub fn inpas_dc_tcp(dc_host: &str, address: &str, port: u16) -> Self {
+        Self::new(ProtocolType::Inpas, ConnectionType::Tcp)
+            .dc_host(dc_host)
+            .address(address)
+            .port(port)
+    }
+
+    /// Inpas terminal on a serial port of the DualConnector host.
+    pub fn inpas_dc_usb(dc_host: &str, ncom: &str, baudrate: u32) -> Self {
+        Self::new(ProtocolType::Inpas, ConnectionType::Usb)
+            .dc_host(dc_host)
+            .ncom(ncom)
+            .baudrate(baudrate)
+    }
+
+    pub fn serial_number(mut self, serial_number: &str) -> Self {
+        self.config.serial_number = serial_number.to_string();
+        self
+    }
+
+    pub fn address(mut self, address: &str) -> Self {
+        self.config.address = Some(address.to_string());
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = Some(port);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: u32) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    pub fn dc_host(mut self, dc_host: &str) -> Self {
+        self.config.dc_host = Some(dc_host.to_string());
+        self
+    }
+
+    pub fn ncom(mut self, ncom: &str) -> Self {
+        self.config.ncom = Some(ncom.to_string());
+        self
+    }
+
+    pub fn baudrate(mut self, baudrate: u32) -> Self {
+        self.config.baudrate = Some(baudrate);
+        self
+    }
+
+    pub fn policy(mut self, policy: ConnectionPolicy) -> Self {
+        self.config.policy = Some(policy);
+        self
+    }
+
+    pub fn serial(mut self, serial: SerialConfig) -> Self {
+        self.config.serial = Some(serial);
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.config.tls = Some(tls);
+        self
+    }
+
+    pub fn dc_client(mut self, dc_client: DcClientConfig) -> Self {
+        self.config.dc_client = Some(dc_client);
+        self
+    }
+
+    pub fn sber(mut self, sber: SberConfig) -> Self {
+        self.config.sber = Some(sber);
+        self
+    }
+
+    pub fn external(mut self, external: ExternalProcessConfig) -> Self {
+        self.config.external = Some(external);
+        self
+    }
+
+    pub fn reconnect(mut self, reconnect: ReconnectConfig) -> Self {
+        self.config.reconnect = Some(reconnect);
+        self
+    }
+
+    pub fn allowed_peers(mut self, peers: &[&str]) -> Self {
+        self.config.allowed_peers = Some(peers.iter().map(|peer| peer.to_string()).collect());
+        self
+    }
+
+    pub fn journal(mut self, path: &str) -> Self {
+        self.config.journal = Some(path.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<ConnectionConfig, String> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acquiring::types::SerialParity;
+
+    #[test]
+    fn flat_json_config_loads_unchanged() {
+        let config = ConnectionConfig::from_json(
+            r#"{
+                "connection_type": "tcp",
+                "protocol": "inpas",
+                "serial_number": "10285694",
+                "address": "192.168.39.131",
+                "port": 27015,
+                "timeout": 60,
+                "dc_host": "http://localhost:9015",
+                "ncom": null,
+                "baudrate": null
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.connection_type, ConnectionType::Tcp);
+        assert_eq!(config.protocol, ProtocolType::Inpas);
+        assert_eq!(config.serial_number, "10285694");
+        assert_eq!(config.address.as_deref(), Some("192.168.39.131"));
+        assert_eq!(config.port, Some(27015));
+        assert_eq!(config.timeout, Some(60));
+        assert_eq!(config.dc_host.as_deref(), Some("http://localhost:9015"));
+        assert!(config.policy.is_none() && config.serial.is_none() && config.tls.is_none());
+        assert!(config.reconnect.is_none() && config.journal.is_none());
+    }
+
+    #[test]
+    fn toml_sections_are_read() {
+        let config = ConnectionConfig::from_toml(
+            r#"
+            connection_type = "usb"
+            protocol = "ttk"
+            serial_number = "1"
+            ncom = "/dev/ttyACM0"
+            baudrate = 115200
+            policy = "per_command"
+
+            [serial]
+            parity = "even"
+            stop_bits = 2
+
+            [reconnect]
+            probe_interval = 1000
+            ping = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.ncom.as_deref(), Some("/dev/ttyACM0"));
+        assert_eq!(config.baudrate, Some(115200));
+        assert_eq!(config.policy, Some(ConnectionPolicy::PerCommand));
+        let serial = config.serial.unwrap();
+        assert_eq!(serial.parity, Some(SerialParity::Even));
+        assert_eq!(serial.stop_bits, Some(2));
+        let reconnect = config.reconnect.unwrap();
+        assert_eq!(reconnect.probe_interval, Some(1000));
+        assert!(reconnect.ping);
+    }
+
+    #[test]
+    fn loading_validates() {
+        let error = ConnectionConfig::from_json(
+            r#"{"connection_type": "tcp", "protocol": "ttk", "serial_number": "1"}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "address is required for tcp connection; port is required for tcp connection"
+        );
+    }
+
+    fn set_env(prefix: &str, vars: &[(&str, &str)]) {
+        for (name, value) in vars {
+            // SAFETY: every test uses its own prefix, so no other thread reads
+            // these variables.
+            unsafe { std::env::set_var(format!("{}_{}", prefix, name), value) };
+        }
+    }
+
+    #[test]
+    fn config_from_env() {
+        set_env(
+            "CONFIG_TEST_ENV",
+            &[
+                ("CONNECTION_TYPE", "Listener"),
+                ("PROTOCOL", "TTK"),
+                ("SERIAL_NUMBER", "42"),
+                ("PORT", " 27015 "),
+                ("ALLOWED_PEERS", r#"["10.0.0.7"]"#),
+                ("RECONNECT", r#"{"max_attempts": 3}"#),
+            ],
+        );
+        let config = ConnectionConfig::from_env("CONFIG_TEST_ENV").unwrap();
+
+        assert_eq!(config.connection_type, ConnectionType::Listener);
+        assert_eq!(config.protocol, ProtocolType::Ttk);
+        assert_eq!(config.serial_number, "42");
+        assert_eq!(config.port, Some(27015));
+        assert_eq!(config.allowed_peers, Some(vec!["10.0.0.7".to_string()]));
+        assert_eq!(config.reconnect.unwrap().max_attempts, Some(3));
+
+        set_env("CONFIG_TEST_BAD_ENV", &[("TIMEOUT", "soon"), ("SERIAL", "{")]);
+        let error = ConnectionConfig::from_env("CONFIG_TEST_BAD_ENV").unwrap_err();
+        assert_eq!(error.to_string(), "CONFIG_TEST_BAD_ENV_TIMEOUT is not a number: soon");
+    }
+
+    #[test]
+    fn env_overrides_the_file_and_merges_sections() {
+        let path = std::env::temp_dir().join(format!("config-test-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            connection_type = "rfc2217"
+            protocol = "ttk"
+            serial_number = "1"
+            address = "10.0.0.5"
+            port = 4001
+            timeout = 5000
+
+            [serial]
+            data_bits = 7
+            parity = "even"
+            "#,
+        )
+        .unwrap();
+        set_env(
+            "CONFIG_TEST_MERGE",
+            &[("ADDRESS", "10.0.0.6"), ("SERIAL", r#"{"stop_bits": 2}"#)],
+        );
+        let config = ConnectionConfig::from_file_and_env(&path, "CONFIG_TEST_MERGE");
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.address.as_deref(), Some("10.0.0.6"));
+        assert_eq!(config.port, Some(4001));
+        assert_eq!(config.timeout, Some(5000));
+        let serial = config.serial.unwrap();
+        assert_eq!(serial.data_bits, Some(7));
+        assert_eq!(serial.parity, Some(SerialParity::Even));
+        assert_eq!(serial.stop_bits, Some(2));
+    }
+
+    #[test]
+    fn merge_replaces_values_and_merges_objects() {
+        let mut base = serde_json::json!({"port": 1, "tls": {"ca_cert": "a.pem", "pinned_certs": ["aa"]}});
+        merge(
+            &mut base,
+            serde_json::json!({"port": 2, "tls": {"pinned_certs": ["bb"]}, "journal": "j"}),
+        );
+        assert_eq!(
+            base,
+            serde_json::json!({
+                "port": 2,
+                "tls": {"ca_cert": "a.pem", "pinned_certs": ["bb"]},
+                "journal": "j"
+            })
+        );
+    }
+
+    fn errors(builder: ConnectionConfigBuilder) -> String {
+        builder.serial_number("1").build().unwrap_err()
+    }
+
+    #[test]
+    fn missing_fields_are_reported_together() {
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::Ttk, ConnectionType::Tcp).timeout(0)),
+            "timeout must be greater than zero; address is required for tcp connection; \
+             port is required for tcp connection"
+        );
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::Ttk, ConnectionType::Rfc2217).port(4001)),
+            "address is required for rfc2217 connection"
+        );
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::Ttk, ConnectionType::Usb).serial(
+                SerialConfig {
+                    data_bits: Some(9),
+                    stop_bits: Some(3),
+                    ..Default::default()
+                }
+            )),
+            "serial.data_bits must be between 5 and 8; serial.stop_bits must be 1 or 2; \
+             ncom or serial.usb_vid/usb_pid/usb_serial is required for usb connection"
+        );
+        assert_eq!(
+            errors(
+                ConnectionConfig::builder(ProtocolType::Ttk, ConnectionType::Listener)
+                    .allowed_peers(&["10.0.0.7"])
+            ),
+            "port is required for listener connection"
+        );
+        assert_eq!(
+            errors(ConnectionConfigBuilder::ttk_tcp("10.0.0.5", 27015).allowed_peers(&["10.0.0.7"])),
+            "allowed_peers is only supported for listener connections"
+        );
+        assert_eq!(
+            errors(
+                ConnectionConfig::builder(ProtocolType::Ttk, ConnectionType::Bluetooth)
+                    .address("00:11:22:33:44")
+                    .port(31)
+            ),
+            "Invalid Bluetooth address: 00:11:22:33:44; port (RFCOMM channel) must be between 1 and 30"
+        );
+    }
+
+    #[test]
+    fn protocol_fields_are_reported_together() {
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::Inpas, ConnectionType::Tcp).dc_host(" ")),
+            "dc_host must not be empty; address and port are required for tcp connection via DualConnector"
+        );
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::Inpas, ConnectionType::Usb).dc_host("dc:9015")),
+            "ncom and baudrate are required for usb connection via DualConnector"
+        );
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::Inpas, ConnectionType::Tcp)),
+            "address is required for tcp connection; port is required for tcp connection"
+        );
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::Sber, ConnectionType::Tcp).sber(
+                SberConfig {
+                    timeout: Some(0),
+                    ..Default::default()
+                }
+            )),
+            "sber.timeout must be greater than zero"
+        );
+        assert_eq!(
+            errors(ConnectionConfig::builder(ProtocolType::External, ConnectionType::Tcp)),
+            "external is required for external protocol"
+        );
+        assert_eq!(
+            errors(
+                ConnectionConfig::builder(ProtocolType::External, ConnectionType::Tcp)
+                    .external(ExternalProcessConfig::default())
+                    .tls(TlsConfig::default())
+            ),
+            "external.program is required for external protocol; \
+             tls is only supported for direct tcp connections; tls requires ca_cert or pinned_certs"
+        );
+        assert_eq!(
+            errors(ConnectionConfigBuilder::ttk_tcp("10.0.0.5", 27015).tls(TlsConfig {
+                pinned_certs: vec!["zz".to_string()],
+                client_cert: Some("client.pem".to_string()),
+                ..Default::default()
+            })),
+            "Invalid SHA-256 certificate fingerprint: zz; \
+             tls.client_cert and tls.client_key must be set together"
+        );
+    }
+}
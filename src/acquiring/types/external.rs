@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Drives an acquirer through an external program.
+///
+/// `args`, `input.template` and file paths may contain `{operation}`,
+/// `{amount}`, `{currency}`, `{ern}` and `{serial_number}`. `{operation}` is
+/// looked up in `operations` first (e.g. `"sale": "1"`), falling back to the
+/// operation name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalProcessConfig {
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub work_dir: Option<String>,
+    #[serde(default)]
+    pub operations: HashMap<String, String>,
+    pub input: Option<ExternalInput>,
+    #[serde(default)]
+    pub output: ExternalOutput,
+    /// `NormalizedTransactionData` field name to output key, e.g.
+    /// `"response_code": "exit_code"`.
+    #[serde(default)]
+    pub mapping: HashMap<String, String>,
+    /// How long the program may run, in milliseconds, 120 s by default. It
+    /// drives the whole cardholder flow, so `timeout` (a single read) doesn't
+    /// apply.
+    pub timeout: Option<u32>,
+}
+
+/// Request written for the program, to `file` or to stdin.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalInput {
+    pub file: Option<String>,
+    pub template: String,
+    pub encoding: Option<String>,
+}
+
+/// Where the result is read from: `file` or stdout. Without `encoding`,
+/// UTF-8 is assumed with a windows-1251 fallback.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalOutput {
+    pub file: Option<String>,
+    pub encoding: Option<String>,
+    #[serde(default)]
+    pub format: ExternalOutputFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExternalOutputFormat {
+    /// One `key<separator>value` pair per line.
+    KeyValue {
+        #[serde(default = "default_separator")]
+        separator: String,
+    },
+    FixedWidth { fields: Vec<FixedWidthField> },
+    /// Element text keyed by its path below the root, e.g. `result.code`.
+    /// Elements with an `id` attribute are keyed as `field[15]`, attributes
+    /// as `result@status`.
+    Xml,
+}
+
+impl Default for ExternalOutputFormat {
+    fn default() -> Self {
+        Self::KeyValue {
+            separator: default_separator(),
+        }
+    }
+}
+
+fn default_separator() -> String {
+    "=".to_string()
+}
+
+/// A value at character `start` (0-based) of output line `line` (0-based).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixedWidthField {
+    pub name: String,
+    #[serde(default)]
+    pub line: usize,
+    pub start: usize,
+    pub length: usize,
+}
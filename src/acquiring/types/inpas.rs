@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+
+/// DualConnector operation codes, sent in field 25.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InpasOperation {
+  Sale,
+  PreAuthorization,
+  Completion,
+  Cancel,
+  TestConnection,
+  Refund,
+  Reconciliation,
+  LastTransactionStatus,
+  Reports,
+  Custom(u16),
+}
+
+impl InpasOperation {
+  pub fn code(&self) -> u16 {
+    match self {
+      Self::Sale => 1,
+      Self::PreAuthorization => 2,
+      Self::Completion => 3,
+      Self::Cancel => 4,
+      Self::TestConnection => 26,
+      Self::Refund => 29,
+      Self::Reconciliation => 59,
+      Self::LastTransactionStatus => 61,
+      Self::Reports => 63,
+      Self::Custom(code) => *code,
+    }
+  }
+
+  pub fn from_code(code: u16) -> Self {
+    match code {
+      1 => Self::Sale,
+      2 => Self::PreAuthorization,
+      3 => Self::Completion,
+      4 => Self::Cancel,
+      26 => Self::TestConnection,
+      29 => Self::Refund,
+      59 => Self::Reconciliation,
+      61 => Self::LastTransactionStatus,
+      63 => Self::Reports,
+      other => Self::Custom(other),
+    }
+  }
+
+  /// Whether the operation moves money and therefore needs amount and
+  /// currency fields.
+  pub fn is_financial(&self) -> bool {
+    matches!(
+      self,
+      Self::Sale | Self::PreAuthorization | Self::Completion | Self::Cancel | Self::Refund
+    )
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InpasFieldFormat {
+  /// Amount in minor currency units.
+  Amount,
+  /// ISO 4217 numeric currency code.
+  Currency,
+  Numeric,
+  Text,
+  /// `YYYYMMDDhhmmss`.
+  Timestamp,
+}
+
+impl InpasFieldFormat {
+  pub fn validate(&self, value: &str) -> bool {
+    let digits = !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+    match self {
+      Self::Amount | Self::Numeric => digits,
+      Self::Currency => digits && value.len() == 3,
+      Self::Timestamp => digits && value.len() == 14,
+      Self::Text => true,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InpasFieldId {
+  Amount,
+  AdditionalAmount,
+  Currency,
+  HostTimestamp,
+  CardEntryMode,
+  PinCodingMode,
+  Pan,
+  AuthorizationCode,
+  Rrn,
+  ResponseCode,
+  TextResponse,
+  Timestamp,
+  TransactionId,
+  OperationCode,
+  InvoiceNumber,
+  TerminalId,
+  MerchantId,
+  Status,
+  CashierRequest,
+  CashierResponse,
+  ProviderCode,
+  Receipt,
+}
+
+impl InpasFieldId {
+  pub const ALL: &'static [InpasFieldId] = &[
+    Self::Amount,
+    Self::AdditionalAmount,
+    Self::Currency,
+    Self::HostTimestamp,
+    Self::CardEntryMode,
+    Self::PinCodingMode,
+    Self::Pan,
+    Self::AuthorizationCode,
+    Self::Rrn,
+    Self::ResponseCode,
+    Self::TextResponse,
+    Self::Timestamp,
+    Self::TransactionId,
+    Self::OperationCode,
+    Self::InvoiceNumber,
+    Self::TerminalId,
+    Self::MerchantId,
+    Self::Status,
+    Self::CashierRequest,
+    Self::CashierResponse,
+    Self::ProviderCode,
+    Self::Receipt,
+  ];
+
+  /// Two-digit ID as used in `<field id="..">` and as the raw response key.
+  pub fn code(&self) -> &'static str {
+    match self {
+      Self::Amount => "00",
+      Self::AdditionalAmount => "01",
+      Self::Currency => "04",
+      Self::HostTimestamp => "06",
+      Self::CardEntryMode => "08",
+      Self::PinCodingMode => "09",
+      Self::Pan => "10",
+      Self::AuthorizationCode => "13",
+      Self::Rrn => "14",
+      Self::ResponseCode => "15",
+      Self::TextResponse => "19",
+      Self::Timestamp => "21",
+      Self::TransactionId => "23",
+      Self::OperationCode => "25",
+      Self::InvoiceNumber => "26",
+      Self::TerminalId => "27",
+      Self::MerchantId => "28",
+      Self::Status => "39",
+      Self::CashierRequest => "76",
+      Self::CashierResponse => "77",
+      Self::ProviderCode => "82",
+      Self::Receipt => "90",
+    }
+  }
+
+  pub fn from_code(code: &str) -> Option<Self> {
+    let code = format!("{:0>2}", code);
+    Self::ALL.iter().copied().find(|id| id.code() == code)
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Amount => "Amount",
+      Self::AdditionalAmount => "Additional Amount",
+      Self::Currency => "Currency",
+      Self::HostTimestamp => "Host Timestamp",
+      Self::CardEntryMode => "Card Entry Mode",
+      Self::PinCodingMode => "PIN Coding Mode",
+      Self::Pan => "PAN",
+      Self::AuthorizationCode => "Authorization Code",
+      Self::Rrn => "RRN",
+      Self::ResponseCode => "Response Code",
+      Self::TextResponse => "Text Response",
+      Self::Timestamp => "Timestamp",
+      Self::TransactionId => "Transaction ID",
+      Self::OperationCode => "Operation Code",
+      Self::InvoiceNumber => "Invoice Number",
+      Self::TerminalId => "Terminal ID",
+      Self::MerchantId => "Merchant ID",
+      Self::Status => "Status",
+      Self::CashierRequest => "Cashier Request",
+      Self::CashierResponse => "Cashier Response",
+      Self::ProviderCode => "Provider Code",
+      Self::Receipt => "Receipt",
+    }
+  }
+
+  pub fn format(&self) -> InpasFieldFormat {
+    match self {
+      Self::Amount | Self::AdditionalAmount => InpasFieldFormat::Amount,
+      Self::Currency => InpasFieldFormat::Currency,
+      Self::HostTimestamp | Self::Timestamp => InpasFieldFormat::Timestamp,
+      Self::CardEntryMode | Self::PinCodingMode | Self::OperationCode | Self::Status => {
+        InpasFieldFormat::Numeric
+      }
+      _ => InpasFieldFormat::Text,
+    }
+  }
+}
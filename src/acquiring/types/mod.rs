@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 pub enum ProtocolType {
     Ttk,
     Inpas,
+    Sber,
+    External,
 }
 
 impl Default for ProtocolType {
@@ -19,6 +21,24 @@ pub enum ConnectionType {
     Tcp,
     Usb,
     Bluetooth,
+    /// The terminal connects to the POS, see `TcpListenerConnection`.
+    Listener,
+    /// Serial port on a network access server, see `Rfc2217Connection`.
+    Rfc2217,
+}
+
+/// When the link to the terminal is opened and closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionPolicy {
+    /// Opened by `Terminal::connect` and kept open.
+    #[default]
+    Persistent,
+    /// Opened and closed around every command, for terminals that drop the
+    /// session after each transaction.
+    PerCommand,
+    /// Opened by the first command and kept open.
+    Lazy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +52,115 @@ pub struct ConnectionConfig {
     pub dc_host: Option<String>,
     pub ncom: Option<String>,
     pub baudrate: Option<u32>,
+    #[serde(default)]
+    pub policy: Option<ConnectionPolicy>,
+    #[serde(default)]
+    pub serial: Option<SerialConfig>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub dc_client: Option<DcClientConfig>,
+    #[serde(default)]
+    pub sber: Option<SberConfig>,
+    #[serde(default)]
+    pub external: Option<ExternalProcessConfig>,
+    #[serde(default)]
+    pub reconnect: Option<ReconnectConfig>,
+    /// Listener only: IP addresses or host names the terminal may connect
+    /// from. Any peer is accepted when unset.
+    #[serde(default)]
+    pub allowed_peers: Option<Vec<String>>,
+    /// JSON-lines file every command is journaled to, see `JsonLinesStore`.
+    #[serde(default)]
+    pub journal: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialParity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SerialFlowControl {
+    None,
+    Software,
+    Hardware,
+}
+
+/// Serial line settings. Unset values keep the driver defaults (8N1, no flow
+/// control, exclusive access on unix).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SerialConfig {
+    pub data_bits: Option<u8>,
+    pub parity: Option<SerialParity>,
+    pub stop_bits: Option<u8>,
+    pub flow_control: Option<SerialFlowControl>,
+    pub dtr: Option<bool>,
+    pub rts: Option<bool>,
+    pub exclusive: Option<bool>,
+    /// Without `ncom`, the port is looked up by these USB attributes.
+    pub usb_vid: Option<u16>,
+    pub usb_pid: Option<u16>,
+    pub usb_serial: Option<String>,
+}
+
+/// TLS for TCP connections. The server certificate is checked against
+/// `ca_cert`, `pinned_certs` or both; at least one is required. Paths point
+/// to PEM files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub ca_cert: Option<String>,
+    /// SHA-256 fingerprints of accepted server certificates, hex with
+    /// optional colons.
+    #[serde(default)]
+    pub pinned_certs: Vec<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    /// Name the certificate is issued for, `address` by default.
+    pub server_name: Option<String>,
+}
+
+/// Connection supervision. Intervals are in milliseconds; without
+/// `max_attempts` reconnecting never gives up.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconnectConfig {
+    pub probe_interval: Option<u32>,
+    pub initial_backoff: Option<u32>,
+    pub max_backoff: Option<u32>,
+    pub max_attempts: Option<u32>,
+    /// Also send an SRV test-server request on every probe (TTK only).
+    #[serde(default)]
+    pub ping: bool,
+}
+
+/// HTTP client settings for DualConnector. Timeouts are in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DcClientConfig {
+    /// PEM file with the CA that signed the DualConnector certificate.
+    pub ca_cert: Option<String>,
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub connect_timeout: Option<u32>,
+}
+
+/// Sberbank UPOS settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SberConfig {
+    /// sb_pilot executable, `sb_pilot` from `PATH` by default.
+    pub pilot_path: Option<String>,
+    /// Where sb_pilot writes its `e` and `p` files. Defaults to the
+    /// executable's directory.
+    pub work_dir: Option<String>,
+    /// How long sb_pilot may run, in milliseconds, 120 s by default. It only
+    /// exits once the customer is done with the card, so `timeout` (a single
+    /// read) doesn't apply.
+    pub timeout: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -130,6 +259,14 @@ impl ResponseCode {
     }
 }
 
+pub mod config;
+pub mod external;
+pub mod inpas;
 pub mod protocol;
 
+pub use config::ConnectionConfigBuilder;
+pub use external::{
+    ExternalInput, ExternalOutput, ExternalOutputFormat, ExternalProcessConfig, FixedWidthField,
+};
+pub use inpas::{InpasFieldFormat, InpasFieldId, InpasOperation};
 pub use protocol::get_tag_definition;
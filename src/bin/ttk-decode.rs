@@ -0,0 +1,290 @@
+use corex_payment::acquiring::connection::recording::SessionFrame;
+use corex_payment::acquiring::protocol::{parse_inpas_response, TlvEncoder, TlvNode, TtkBuffer};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage:
+  ttk-decode <hex>            decode a hex dump given on the command line
+  ttk-decode -f <file>        decode a file (hex text, raw binary capture, recorded session
+                              JSON-lines or DualConnector XML)
+  ttk-decode --xml <file>     decode a DualConnector XML response
+  ttk-decode --tlv <hex>      decode bare TLV data without the TTK frame header";
+
+enum Input {
+    Frames(Vec<u8>),
+    Tlv(Vec<u8>),
+    Session(Vec<SessionFrame>),
+    Xml(String),
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let input = match parse_args(&args) {
+        Ok(input) => input,
+        Err(e) if e.is_empty() => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let ok = match input {
+        Input::Frames(data) => decode_frames(&data),
+        Input::Tlv(data) => print_tlv(&data, 0),
+        Input::Session(frames) => decode_session(&frames),
+        Input::Xml(xml) => decode_xml(&xml),
+    };
+
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Input, String> {
+    match args {
+        [flag, path] if flag == "-f" || flag == "--file" => {
+            let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            Ok(classify_file(bytes))
+        }
+        [flag, path] if flag == "--xml" => {
+            let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+            Ok(Input::Xml(decode_text(&bytes)))
+        }
+        [flag, hex @ ..] if flag == "--tlv" && !hex.is_empty() => {
+            Ok(Input::Tlv(parse_hex(&hex.join(""))?))
+        }
+        [flag] if flag == "-h" || flag == "--help" => Err(String::new()),
+        hex if !hex.is_empty() => Ok(Input::Frames(parse_hex(&hex.join(""))?)),
+        _ => Err("No input given".to_string()),
+    }
+}
+
+fn classify_file(bytes: Vec<u8>) -> Input {
+    let text = decode_text(&bytes);
+    let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+    if trimmed.starts_with('<') {
+        return Input::Xml(trimmed.to_string());
+    }
+    if trimmed.starts_with('{') {
+        let frames: Result<Vec<SessionFrame>, _> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect();
+        if let Ok(frames) = frames {
+            return Input::Session(frames);
+        }
+    }
+    match parse_hex(&text) {
+        Ok(data) => Input::Frames(data),
+        Err(_) => Input::Frames(bytes),
+    }
+}
+
+fn decode_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::WINDOWS_1251.decode(bytes).0.to_string(),
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text
+        .split_whitespace()
+        .map(|chunk| chunk.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect::<String>()
+        .chars()
+        .filter(|c| !matches!(c, ':' | '-' | ','))
+        .collect();
+
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err("Hex input must contain an even number of digits".to_string());
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hex byte \"{}\" at position {}", &digits[i..i + 2], i / 2))
+        })
+        .collect()
+}
+
+fn decode_frames(data: &[u8]) -> bool {
+    let mut ok = true;
+    let mut offset = 0;
+    let mut index = 1;
+
+    while offset < data.len() {
+        let rest = &data[offset..];
+        let Some(frame_length) = TtkBuffer::frame_length(rest).filter(|len| *len >= 4) else {
+            println!(
+                "Framing error at offset {}: {} trailing byte(s) do not form a frame header",
+                offset,
+                rest.len()
+            );
+            return false;
+        };
+
+        if frame_length > rest.len() {
+            println!(
+                "Framing error at offset {}: frame declares {} bytes, only {} available",
+                offset,
+                frame_length,
+                rest.len()
+            );
+            // A cut-off dump may end inside the header itself.
+            if let Some(tlv) = rest.get(4..) {
+                print_tlv(tlv, offset + 4);
+            }
+            return false;
+        }
+
+        let frame = &rest[..frame_length];
+        let message_type = ((frame[2] as u16) << 8) | (frame[3] as u16);
+        match TtkBuffer::parse_message(frame) {
+            Ok((kind, _)) => println!(
+                "Frame #{} at offset {}: {} bytes, {:?} (0x{:04X})",
+                index, offset, frame_length, kind, message_type
+            ),
+            Err(e) => {
+                println!(
+                    "Frame #{} at offset {}: {} bytes, message type 0x{:04X}: {}",
+                    index, offset, frame_length, message_type, e
+                );
+                ok = false;
+            }
+        }
+        ok &= print_tlv(&frame[4..], offset + 4);
+
+        offset += frame_length;
+        index += 1;
+    }
+
+    ok
+}
+
+fn decode_session(frames: &[SessionFrame]) -> bool {
+    let mut ok = true;
+    for frame in frames {
+        println!("== {:?} at {}", frame.direction, frame.timestamp);
+        match frame.bytes() {
+            Ok(data) => ok &= decode_frames(&data),
+            Err(e) => {
+                println!("{}", e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn print_tlv(data: &[u8], base: usize) -> bool {
+    match TlvEncoder::decode_tree(data) {
+        Ok(nodes) => {
+            for node in &nodes {
+                print_node(node, base, 1);
+            }
+            true
+        }
+        Err(e) => {
+            println!("  TLV error (offsets relative to {}): {}", base, e);
+            if let Ok(items) = TlvEncoder::decode(&data[..longest_valid_prefix(data)]) {
+                for item in items {
+                    println!("  {:X} {}", item.tag, TlvEncoder::value_to_string(&item));
+                }
+            }
+            false
+        }
+    }
+}
+
+fn longest_valid_prefix(data: &[u8]) -> usize {
+    (0..=data.len())
+        .rev()
+        .find(|end| TlvEncoder::decode(&data[..*end]).is_ok())
+        .unwrap_or(0)
+}
+
+fn print_node(node: &TlvNode, base: usize, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let name = match node.item.definition {
+        Some(definition) => definition.name.clone(),
+        None if node.constructed => "(unknown, constructed)".to_string(),
+        None => "(unknown tag)".to_string(),
+    };
+
+    if node.constructed {
+        println!(
+            "{}[{:04}] {:X} {} len={}",
+            indent,
+            base + node.offset,
+            node.item.tag,
+            name,
+            node.item.length
+        );
+        for child in &node.children {
+            print_node(child, base, depth + 1);
+        }
+        return;
+    }
+
+    println!(
+        "{}[{:04}] {:X} {} len={} = {}",
+        indent,
+        base + node.offset,
+        node.item.tag,
+        name,
+        node.item.length,
+        TlvEncoder::value_to_string(&node.item)
+    );
+}
+
+fn decode_xml(xml: &str) -> bool {
+    match parse_inpas_response(xml) {
+        Ok(response) => {
+            match serde_json::to_string_pretty(&response) {
+                Ok(json) => println!("{}", json),
+                Err(_) => println!("{:#?}", response),
+            }
+            true
+        }
+        Err(e) => {
+            println!("DualConnector XML error: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_tags_are_decoded() {
+        // DF01 is not in the tag registry.
+        let data = parse_hex("000a 97f2 df01 01 2a 9b02 3030").unwrap();
+        assert!(decode_frames(&data));
+    }
+
+    #[test]
+    fn truncated_frames_are_reported() {
+        for hex in ["00", "0010", "001097", "001097f2", "001097f29b023030"] {
+            assert!(!decode_frames(&parse_hex(hex).unwrap()), "{}", hex);
+        }
+
+        let mut data = parse_hex("000a 97f2 df01 01 2a 9b02 3030").unwrap();
+        data.extend([0x00, 0x10, 0x97]);
+        assert!(!decode_frames(&data));
+    }
+
+    #[test]
+    fn bare_tlv_with_trailing_garbage_fails() {
+        assert!(print_tlv(&parse_hex("9b02 3030").unwrap(), 0));
+        assert!(!print_tlv(&parse_hex("9b02 3030 9f").unwrap(), 0));
+    }
+}
@@ -0,0 +1,110 @@
+use corex_payment::acquiring::simulator::{Scenario, TtkSimulator};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage:
+  ttk-simulator [--tcp <addr>] [--pty] [--scenario <spec>] [--op <ID>=<spec>]...
+
+  --tcp <addr>        listen on a TCP address (default 127.0.0.1:27015)
+  --pty               serve a pseudo-terminal instead and print its device path
+  --scenario <spec>   default scenario for every operation (default approve)
+  --op <ID>=<spec>    scenario for one message ID, e.g. --op REF=decline:51
+
+Scenarios: approve, decline[:CODE], timeout, split[:CHUNK[:DELAY_MS]],
+           prompts[:COUNT], garbage";
+
+struct Options {
+    tcp: String,
+    pty: bool,
+    scenario: Scenario,
+    operations: Vec<(String, Scenario)>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        tcp: "127.0.0.1:27015".to_string(),
+        pty: false,
+        scenario: Scenario::Approve,
+        operations: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tcp" => options.tcp = args.next().ok_or("--tcp requires an address")?.clone(),
+            "--pty" => options.pty = true,
+            "--scenario" => {
+                options.scenario = args.next().ok_or("--scenario requires a value")?.parse()?;
+            }
+            "--op" => {
+                let value = args.next().ok_or("--op requires ID=scenario")?;
+                let (id, spec) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid --op value: {}", value))?;
+                options.operations.push((id.to_string(), spec.parse()?));
+            }
+            "-h" | "--help" => return Err(String::new()),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) if e.is_empty() => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut simulator = TtkSimulator::new(options.scenario);
+    for (id, scenario) in options.operations {
+        simulator = simulator.with_scenario(&id, scenario);
+    }
+    let simulator = Arc::new(simulator);
+
+    let handle = if options.pty {
+        #[cfg(unix)]
+        match simulator.open_pty() {
+            Ok((path, handle)) => {
+                println!("Simulator listening on {}", path.display());
+                handle
+            }
+            Err(e) => {
+                eprintln!("Failed to open pseudo-terminal: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            eprintln!("Pseudo-terminals are only supported on unix");
+            return ExitCode::FAILURE;
+        }
+    } else {
+        match simulator.listen_tcp(&options.tcp).await {
+            Ok((addr, handle)) => {
+                println!("Simulator listening on {}", addr);
+                handle
+            }
+            Err(e) => {
+                eprintln!("Failed to listen on {}: {}", options.tcp, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = handle => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    ExitCode::SUCCESS
+}
@@ -1,7 +1,13 @@
 pub mod acquiring;
 pub mod kkt;
 
-pub use acquiring::{ConnectionConfig, ConnectionType, ProtocolType, Terminal, TerminalResponse};
+pub use acquiring::{
+    discover_terminals, list_serial_terminals, ConnectionConfig, ConnectionConfigBuilder, ConnectionPolicy,
+    ConnectionState, ConnectionType, DcClientConfig, DiscoveredTerminal, DiscoveryOptions, JsonLinesStore,
+    ManagedTerminalConfig, ProtocolType, ReconnectConfig, RoutedTerminal, SberConfig, SerialConfig,
+    SerialFlowControl, SerialParity, SerialPortDescription, Terminal, TerminalManager, TerminalManagerConfig,
+    TerminalResponse, TlsConfig, TransactionQuery, TransactionRecord, TransactionState, TransactionStore,
+};
 pub use kkt::{Kkt, KktConfig, ConnectionType as KktConnectionType};
 pub use kkt::types::{Operator, SellTask, Item, Payment, Tax, ClientInfo, TaxEntry};
 
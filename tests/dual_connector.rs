@@ -0,0 +1,173 @@
+use corex_payment::acquiring::protocol::inpas::{send_inpas_request, InpasField};
+use corex_payment::acquiring::simulator::{DcScenario, DualConnectorMock};
+use corex_payment::acquiring::types::{InpasFieldId, InpasOperation};
+use corex_payment::{ConnectionConfig, ConnectionConfigBuilder, DcClientConfig};
+
+async fn listen(mock: &DualConnectorMock) -> String {
+    let (addr, _) = mock.listen("127.0.0.1:0").await.unwrap();
+    addr.to_string()
+}
+
+fn config(dc_host: &str, timeout: u32) -> ConnectionConfig {
+    ConnectionConfigBuilder::inpas_dc_tcp(dc_host, "10.0.0.5", 27015)
+        .serial_number("00000001")
+        .timeout(timeout)
+        .build()
+        .unwrap()
+}
+
+fn payment_fields() -> Vec<InpasField> {
+    vec![
+        InpasField::new(InpasFieldId::Amount, "1000"),
+        InpasField::new(InpasFieldId::Currency, "643"),
+        InpasField::operation(InpasOperation::Sale),
+    ]
+}
+
+#[tokio::test]
+async fn success() {
+    let mock = DualConnectorMock::new(DcScenario::Success {
+        fields: vec![(InpasFieldId::Pan.code().to_string(), "400000******0002".to_string())],
+    });
+    let dc_host = listen(&mock).await;
+
+    let response = send_inpas_request(&config(&dc_host, 5000), &payment_fields())
+        .await
+        .unwrap();
+
+    assert!(response.success);
+    assert_eq!(response.code.as_deref(), Some("00"));
+    let data = response.data.unwrap();
+    assert_eq!(data.amount.as_deref(), Some("1000"));
+    assert_eq!(data.text_response.as_deref(), Some("ОДОБРЕНО"));
+    assert_eq!(data.pan_masked.as_deref(), Some("400000******0002"));
+
+    let received = mock.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].request.ipaddr.as_deref(), Some("10.0.0.5:27015"));
+    assert_eq!(received[0].request.timeout, Some(5000));
+    assert_eq!(
+        received[0].content_type.as_deref(),
+        Some("text/xml; charset=windows-1251")
+    );
+    assert_eq!(received[0].fields.get("00").map(String::as_str), Some("1000"));
+}
+
+#[tokio::test]
+async fn basic_auth_from_settings() {
+    let mock = DualConnectorMock::new(DcScenario::Success { fields: Vec::new() });
+    let dc_host = listen(&mock).await;
+    let mut config = config(&dc_host, 5000);
+    config.dc_client = Some(DcClientConfig {
+        username: Some("pos".to_string()),
+        password: Some("secret".to_string()),
+        ..Default::default()
+    });
+
+    send_inpas_request(&config, &payment_fields()).await.unwrap();
+
+    assert_eq!(
+        mock.received()[0].authorization.as_deref(),
+        Some("Basic cG9zOnNlY3JldA==")
+    );
+}
+
+#[tokio::test]
+async fn dual_connector_error() {
+    let mock = DualConnectorMock::new(DcScenario::Error {
+        code: "4".to_string(),
+        description: "Терминал не отвечает".to_string(),
+    });
+    let dc_host = listen(&mock).await;
+
+    let response = send_inpas_request(&config(&dc_host, 5000), &payment_fields())
+        .await
+        .unwrap();
+
+    assert!(!response.success);
+    assert_eq!(response.code.as_deref(), Some("4"));
+    assert_eq!(response.error.as_deref(), Some("Терминал не отвечает"));
+}
+
+#[tokio::test]
+async fn http_error() {
+    let mock = DualConnectorMock::new(DcScenario::HttpError {
+        status: 503,
+        body: "maintenance".to_string(),
+    });
+    let dc_host = listen(&mock).await;
+
+    let error = send_inpas_request(&config(&dc_host, 5000), &payment_fields())
+        .await
+        .unwrap_err()
+        .to_string();
+
+    assert_eq!(error, "DualConnector HTTP error 503: maintenance");
+}
+
+#[tokio::test]
+async fn wrong_charset_is_decoded_as_declared() {
+    let mock = DualConnectorMock::new(DcScenario::WrongCharset);
+    let dc_host = listen(&mock).await;
+
+    let response = send_inpas_request(&config(&dc_host, 5000), &payment_fields())
+        .await
+        .unwrap();
+
+    assert!(response.success);
+    let text = response.data.unwrap().text_response.unwrap();
+    assert!(!text.is_empty());
+    assert_ne!(text, "ОДОБРЕНО");
+}
+
+#[tokio::test]
+async fn slow_reply_within_timeout() {
+    let mock = DualConnectorMock::new(DcScenario::Slow {
+        delay_ms: 300,
+        then: Box::new(DcScenario::Success { fields: Vec::new() }),
+    });
+    let dc_host = listen(&mock).await;
+
+    let response = send_inpas_request(&config(&dc_host, 100), &payment_fields())
+        .await
+        .unwrap();
+
+    assert!(response.success);
+}
+
+#[tokio::test]
+async fn slow_reply_past_client_timeout() {
+    // The HTTP timeout is the operation timeout plus a 10 s grace period.
+    let mock = DualConnectorMock::new(DcScenario::Slow {
+        delay_ms: 15000,
+        then: Box::new(DcScenario::Success { fields: Vec::new() }),
+    });
+    let dc_host = listen(&mock).await;
+
+    let started = std::time::Instant::now();
+    let error = send_inpas_request(&config(&dc_host, 1), &payment_fields())
+        .await
+        .unwrap_err()
+        .to_string();
+
+    assert!(error.contains("did not respond within 10001 ms"), "{}", error);
+    assert!(started.elapsed() < std::time::Duration::from_secs(14));
+}
+
+#[tokio::test]
+async fn scripted_scenarios_run_in_order() {
+    let mock = DualConnectorMock::new(DcScenario::Success { fields: Vec::new() });
+    mock.script(DcScenario::Error {
+        code: "1".to_string(),
+        description: "Busy".to_string(),
+    });
+    let dc_host = listen(&mock).await;
+    let config = config(&dc_host, 5000);
+
+    let first = send_inpas_request(&config, &payment_fields()).await.unwrap();
+    let second = send_inpas_request(&config, &payment_fields()).await.unwrap();
+
+    assert!(!first.success);
+    assert!(second.success);
+    assert_eq!(mock.received().len(), 2);
+}
@@ -0,0 +1,34 @@
+#!/bin/sh
+# Regenerates the TLS test fixtures: a CA, a server certificate for
+# localhost/127.0.0.1 and a client certificate signed by it, and an unrelated
+# CA. Valid for 100 years so the tests don't expire.
+set -e
+cd "$(dirname "$0")"
+
+key() {
+    openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out "$1"
+}
+
+ca() {
+    key "$1.key"
+    openssl req -x509 -new -key "$1.key" -sha256 -days 36500 -subj "/CN=$2" \
+        -addext "basicConstraints=critical,CA:TRUE" \
+        -addext "keyUsage=critical,keyCertSign,cRLSign" \
+        -out "$1.pem"
+}
+
+leaf() {
+    key "$1.key"
+    openssl req -new -key "$1.key" -subj "/CN=$2" -out "$1.csr"
+    printf '%s\n' "basicConstraints=critical,CA:FALSE" "keyUsage=critical,digitalSignature" \
+        "extendedKeyUsage=$3" "subjectAltName=$4" > "$1.ext"
+    openssl x509 -req -in "$1.csr" -CA ca.pem -CAkey ca.key -CAcreateserial -sha256 \
+        -days 36500 -extfile "$1.ext" -out "$1.pem"
+    rm "$1.csr" "$1.ext"
+}
+
+ca ca "corex test CA"
+ca other-ca "corex other CA"
+leaf server localhost serverAuth "DNS:localhost,IP:127.0.0.1"
+leaf client pos-1 clientAuth "DNS:pos-1"
+rm -f ca.srl ca.key other-ca.key
@@ -0,0 +1,147 @@
+use corex_payment::acquiring::connection::tls::{cert_fingerprint, load_certs, server_config};
+use corex_payment::acquiring::simulator::{Scenario, TtkSimulator};
+use corex_payment::{ConnectionConfigBuilder, Terminal, TlsConfig};
+use std::sync::Arc;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn server_fingerprint() -> String {
+    cert_fingerprint(load_certs(&fixture("server.pem")).unwrap()[0].as_ref())
+}
+
+/// TLS simulator port; with `client_ca` it requires client certificates.
+async fn listen(client_ca: Option<&str>) -> u16 {
+    let config = server_config(
+        &fixture("server.pem"),
+        &fixture("server.key"),
+        client_ca.map(fixture).as_deref(),
+    )
+    .unwrap();
+    let simulator = Arc::new(TtkSimulator::new(Scenario::Approve));
+    let (addr, _) = simulator.listen_tls("127.0.0.1:0", config).await.unwrap();
+    addr.port()
+}
+
+fn tls_terminal(port: u16, tls: TlsConfig) -> Terminal {
+    let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", port)
+        .serial_number("1")
+        .timeout(2000)
+        .tls(tls)
+        .build()
+        .unwrap();
+    Terminal::new(config)
+}
+
+#[tokio::test]
+async fn pinned_certificate_is_accepted() {
+    let port = listen(None).await;
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            pinned_certs: vec![server_fingerprint()],
+            ..Default::default()
+        },
+    );
+
+    assert!(terminal.connect().await.unwrap());
+    assert!(terminal.payment(1000, None).await.unwrap().success);
+}
+
+#[tokio::test]
+async fn wrong_pin_is_rejected() {
+    let port = listen(None).await;
+    let wrong = cert_fingerprint(load_certs(&fixture("ca.pem")).unwrap()[0].as_ref());
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            pinned_certs: vec![wrong],
+            ..Default::default()
+        },
+    );
+
+    let error = terminal.connect().await.unwrap_err().to_string();
+    assert!(error.contains("is not pinned"), "{}", error);
+}
+
+#[tokio::test]
+async fn ca_validation() {
+    let port = listen(None).await;
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            ca_cert: Some(fixture("ca.pem")),
+            ..Default::default()
+        },
+    );
+    assert!(terminal.connect().await.unwrap());
+    assert!(terminal.payment(1000, None).await.unwrap().success);
+
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            ca_cert: Some(fixture("other-ca.pem")),
+            ..Default::default()
+        },
+    );
+    let error = terminal.connect().await.unwrap_err().to_string();
+    assert!(error.contains("TLS handshake"), "{}", error);
+
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            ca_cert: Some(fixture("ca.pem")),
+            server_name: Some("terminal.example".to_string()),
+            ..Default::default()
+        },
+    );
+    assert!(terminal.connect().await.is_err());
+}
+
+#[tokio::test]
+async fn ca_and_pin_together() {
+    let port = listen(None).await;
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            ca_cert: Some(fixture("ca.pem")),
+            pinned_certs: vec![server_fingerprint()],
+            ..Default::default()
+        },
+    );
+
+    assert!(terminal.connect().await.unwrap());
+    assert!(terminal.payment(1000, None).await.unwrap().success);
+}
+
+#[tokio::test]
+async fn client_certificate() {
+    let port = listen(Some("ca.pem")).await;
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            ca_cert: Some(fixture("ca.pem")),
+            client_cert: Some(fixture("client.pem")),
+            client_key: Some(fixture("client.key")),
+            ..Default::default()
+        },
+    );
+    assert!(terminal.connect().await.unwrap());
+    assert!(terminal.payment(1000, None).await.unwrap().success);
+
+    // With TLS 1.3 the server rejects a missing certificate after the client
+    // considers the handshake done, so the failure shows up on the exchange.
+    let mut terminal = tls_terminal(
+        port,
+        TlsConfig {
+            ca_cert: Some(fixture("ca.pem")),
+            ..Default::default()
+        },
+    );
+    let result = match terminal.connect().await {
+        Ok(_) => terminal.payment(1000, None).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    assert!(result.is_err());
+}
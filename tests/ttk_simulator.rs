@@ -0,0 +1,157 @@
+use corex_payment::acquiring::simulator::{Scenario, TtkSimulator};
+use corex_payment::{ConnectionConfigBuilder, ConnectionPolicy, ConnectionState, Terminal};
+use std::sync::Arc;
+
+async fn tcp_terminal(simulator: TtkSimulator, timeout: u32) -> Terminal {
+    let (addr, _) = Arc::new(simulator).listen_tcp("127.0.0.1:0").await.unwrap();
+    let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", addr.port())
+        .serial_number("1")
+        .timeout(timeout)
+        .build()
+        .unwrap();
+    let mut terminal = Terminal::new(config);
+    assert!(terminal.connect().await.unwrap());
+    terminal
+}
+
+#[tokio::test]
+async fn approve_over_tcp() {
+    let mut terminal = tcp_terminal(TtkSimulator::new(Scenario::Approve), 2000).await;
+
+    let response = terminal.payment(1000, None).await.unwrap();
+
+    assert!(response.success);
+    assert_eq!(response.code.as_deref(), Some("00"));
+    let data = response.data.unwrap();
+    assert_eq!(data.terminal_id.as_deref(), Some("00000001"));
+    assert!(data.rrn.is_some());
+}
+
+#[tokio::test]
+async fn decline_over_tcp() {
+    let simulator = TtkSimulator::new(Scenario::Approve).with_scenario(
+        "REF",
+        Scenario::Decline {
+            code: "51".to_string(),
+        },
+    );
+    let mut terminal = tcp_terminal(simulator, 2000).await;
+
+    let response = terminal.refund(1000, None).await.unwrap();
+    assert!(!response.success);
+    assert_eq!(response.code.as_deref(), Some("51"));
+
+    let response = terminal.payment(1000, None).await.unwrap();
+    assert!(response.success);
+}
+
+#[tokio::test]
+async fn timeout_over_tcp() {
+    let mut terminal = tcp_terminal(TtkSimulator::new(Scenario::Timeout), 300).await;
+
+    let started = std::time::Instant::now();
+    assert!(terminal.payment(1000, None).await.is_err());
+    assert!(started.elapsed() < std::time::Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn split_frames_over_tcp() {
+    let simulator = TtkSimulator::new(Scenario::SplitFrames {
+        chunk: 3,
+        delay_ms: 5,
+    });
+    let mut terminal = tcp_terminal(simulator, 2000).await;
+
+    let response = terminal.payment(1000, None).await.unwrap();
+    assert!(response.success);
+}
+
+#[tokio::test]
+async fn intermediate_prompts_are_skipped() {
+    let simulator = TtkSimulator::new(Scenario::IntermediatePrompts { count: 3 });
+    let mut terminal = tcp_terminal(simulator, 2000).await;
+
+    let response = terminal.payment(1000, None).await.unwrap();
+    assert!(response.success);
+    assert_eq!(response.message.as_deref(), Some("APPROVED"));
+}
+
+#[tokio::test]
+async fn garbage_is_an_error() {
+    let mut terminal = tcp_terminal(TtkSimulator::new(Scenario::Garbage), 1000).await;
+
+    assert!(terminal.payment(1000, None).await.is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn approve_over_pty() {
+    let simulator = TtkSimulator::new(Scenario::SplitFrames {
+        chunk: 7,
+        delay_ms: 5,
+    });
+    let (path, _) = Arc::new(simulator).open_pty().unwrap();
+    let config = ConnectionConfigBuilder::ttk_serial(path.to_str().unwrap(), 115200)
+        .serial_number("1")
+        .timeout(2000)
+        .build()
+        .unwrap();
+    let mut terminal = Terminal::new(config);
+    assert!(terminal.connect().await.unwrap());
+
+    let response = terminal.payment(1000, None).await.unwrap();
+    assert!(response.success);
+    assert_eq!(response.code.as_deref(), Some("00"));
+}
+
+#[tokio::test]
+async fn on_demand_connections_report_their_state() {
+    let (addr, _) = Arc::new(TtkSimulator::new(Scenario::Approve))
+        .listen_tcp("127.0.0.1:0")
+        .await
+        .unwrap();
+    for (policy, after_command) in [
+        (ConnectionPolicy::Lazy, ConnectionState::Connected),
+        (ConnectionPolicy::PerCommand, ConnectionState::OnDemand),
+    ] {
+        let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", addr.port())
+            .serial_number("1")
+            .timeout(2000)
+            .policy(policy)
+            .build()
+            .unwrap();
+        let mut terminal = Terminal::new(config);
+        terminal.connect().await.unwrap();
+        assert_eq!(terminal.state(), ConnectionState::OnDemand);
+        assert!(!terminal.connected());
+
+        assert!(terminal.payment(1000, None).await.unwrap().success);
+        assert_eq!(terminal.state(), after_command);
+    }
+}
+
+#[tokio::test]
+async fn journal_never_stores_the_full_pan() {
+    let path = std::env::temp_dir().join(format!("ttk-journal-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (addr, _) = Arc::new(TtkSimulator::new(Scenario::Approve))
+        .listen_tcp("127.0.0.1:0")
+        .await
+        .unwrap();
+    let config = ConnectionConfigBuilder::ttk_tcp("127.0.0.1", addr.port())
+        .serial_number("1")
+        .timeout(2000)
+        .journal(path.to_str().unwrap())
+        .build()
+        .unwrap();
+    let mut terminal = Terminal::new(config);
+    assert!(terminal.connect().await.unwrap());
+
+    let response = terminal.payment(1000, None).await.unwrap();
+    assert!(response.success);
+
+    let journal = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert!(!journal.contains("4000000000000002"), "{}", journal);
+    assert!(journal.contains("400000******0002"), "{}", journal);
+}